@@ -0,0 +1,22 @@
+use std::env;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("-h") | Some("--help") => {
+            println!("{{ values.description }}");
+            println!();
+            println!("Usage: {{ values.name }} [NAME]");
+            ExitCode::SUCCESS
+        }
+        Some(name) => {
+            println!("Hello, {name}!");
+            ExitCode::SUCCESS
+        }
+        None => {
+            println!("Hello, world!");
+            ExitCode::SUCCESS
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Name of the answers file in the destination
+pub const ANSWERS_FILE: &str = ".rte-answers.yaml";
+
+/// Source and parameters a destination was rendered with, recorded for `rte update`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Answers {
+    /// Source of the template as given on the command line
+    pub source: String,
+
+    /// Template path within the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_path: Option<String>,
+
+    /// Parameters given by the user (registry, parameter files and --set)
+    #[serde(default)]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Answers {
+    pub fn load(dest: &Path) -> Result<Self> {
+        let path = dest.join(ANSWERS_FILE);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read answers: {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse answers: {}", path.display()))
+    }
+
+    pub fn save(&self, dest: &Path) -> Result<()> {
+        let path = dest.join(ANSWERS_FILE);
+        let content = serde_yaml::to_string(self).context("Failed to serialize answers")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write answers: {}", path.display()))
+    }
+
+    /// Names of the parameters which differ between two sets of parameters (changed, added or
+    /// removed)
+    pub fn changed_parameters(
+        old: &serde_json::Map<String, serde_json::Value>,
+        new: &serde_json::Map<String, serde_json::Value>,
+    ) -> Vec<String> {
+        let mut changed: Vec<String> = new
+            .iter()
+            .filter(|(key, value)| old.get(*key) != Some(value))
+            .map(|(key, _)| key.clone())
+            .chain(old.keys().filter(|key| !new.contains_key(*key)).cloned())
+            .collect();
+        changed.sort();
+        changed
+    }
+}
@@ -0,0 +1,52 @@
+//! External assets declared in the manifest, e.g. a gradle wrapper jar, which are downloaded
+//! while rendering instead of being vendored in the template.
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::source::{self, SourceOptions};
+use crate::template::{FileKind, TemplateFile};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Asset {
+    /// HTTP(S) URL or any remote source URL (s3://, gs://)
+    pub url: String,
+    /// Expected SHA-256 checksum (hex) of the content
+    pub sha256: String,
+    /// Path of the asset in the output
+    pub path: PathBuf,
+}
+
+/// Download an asset (or take it from the cache) and verify its checksum. The content is not
+/// rendered.
+pub fn fetch(asset: &Asset, options: &SourceOptions) -> Result<TemplateFile> {
+    let url =
+        Url::parse(&asset.url).with_context(|| format!("Invalid asset URL '{}'", asset.url))?;
+    let (mut reader, _) = source::fetch(&asset.url, url.scheme(), options)?;
+    let mut content = Vec::new();
+    reader
+        .read_to_end(&mut content)
+        .with_context(|| format!("Failed to download asset {}", asset.url))?;
+
+    let checksum = hex::encode(Sha256::digest(&content));
+    if !checksum.eq_ignore_ascii_case(asset.sha256.trim()) {
+        anyhow::bail!(
+            "checksum mismatch for asset {}: expected {}, got {}",
+            asset.url,
+            asset.sha256,
+            checksum
+        );
+    }
+
+    Ok(TemplateFile {
+        path: asset.path.clone(),
+        content,
+        kind: FileKind::File,
+        mode: None,
+    })
+}
@@ -0,0 +1,425 @@
+//! Support for Backstage software templates
+//! (https://backstage.io/docs/features/software-templates/writing-templates).
+//!
+//! If the template root contains a `template.yaml` of kind Template, the skeleton of its
+//! `fetch:template` step is rendered with the values of that step, computed from the parameters
+//! the same way Backstage does.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use minijinja::value::ValueKind;
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use url::Url;
+
+use crate::complete;
+use crate::report::{self, SkipReason};
+use crate::template::{SyntaxMode, TemplateConfig, TemplateFile, environment};
+
+/// Name of the Backstage template definition in the template root
+pub const TEMPLATE_FILE: &str = "template.yaml";
+
+/// Action which copies and renders the skeleton
+const FETCH_TEMPLATE_ACTION: &str = "fetch:template";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    api_version: String,
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Definition {
+    spec: Spec,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Spec {
+    parameters: Parameters,
+    steps: Vec<Step>,
+}
+
+/// The parameters are either a single form or a list of form steps
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Parameters {
+    Single(ParameterSection),
+    List(Vec<ParameterSection>),
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters::List(Vec::new())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ParameterSection {
+    pub required: Vec<String>,
+    pub properties: BTreeMap<String, Property>,
+}
+
+/// JSON schema of a single parameter. Only the parts rte can check are parsed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Property {
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub default: Option<Value>,
+    #[serde(rename = "enum")]
+    pub allowed: Option<Vec<Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Step {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    input: StepInput,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct StepInput {
+    url: Option<String>,
+    target_path: Option<String>,
+    values: Map<String, Value>,
+}
+
+/// A parsed Backstage template definition
+#[derive(Debug)]
+pub struct BackstageTemplate {
+    pub parameters: Vec<ParameterSection>,
+    /// Directory of the skeleton relative to the template root
+    skeleton: PathBuf,
+    /// Directory in the destination the skeleton is rendered into
+    target_path: PathBuf,
+    values: Map<String, Value>,
+}
+
+impl BackstageTemplate {
+    /// Parse a template.yaml. Returns None if the file is not a Backstage template (e.g. an
+    /// ordinary file of the template which happens to have the same name).
+    pub fn parse(content: &[u8]) -> Result<Option<Self>> {
+        let Ok(header) = serde_yaml::from_slice::<Header>(content) else {
+            return Ok(None);
+        };
+        if !header.api_version.starts_with("scaffolder.backstage.io/") || header.kind != "Template"
+        {
+            return Ok(None);
+        }
+
+        let definition: Definition = serde_yaml::from_slice(content)
+            .with_context(|| format!("Failed to parse Backstage {}", TEMPLATE_FILE))?;
+
+        let mut fetch_steps = definition
+            .spec
+            .steps
+            .into_iter()
+            .filter(|s| s.action == FETCH_TEMPLATE_ACTION);
+        let step = fetch_steps.next().with_context(|| {
+            format!("Backstage template has no '{}' step", FETCH_TEMPLATE_ACTION)
+        })?;
+        if fetch_steps.next().is_some() {
+            anyhow::bail!(
+                "Backstage templates with multiple '{}' steps are not supported",
+                FETCH_TEMPLATE_ACTION
+            );
+        }
+
+        let url = step
+            .input
+            .url
+            .with_context(|| format!("'{}' step has no url", FETCH_TEMPLATE_ACTION))?;
+        if url.contains("://") {
+            anyhow::bail!("remote skeleton '{}' is not supported", url);
+        }
+
+        Ok(Some(Self {
+            parameters: match definition.spec.parameters {
+                Parameters::Single(section) => vec![section],
+                Parameters::List(sections) => sections,
+            },
+            skeleton: relative_path(&url)?,
+            target_path: relative_path(step.input.target_path.as_deref().unwrap_or("."))?,
+            values: step.input.values,
+        }))
+    }
+
+    /// Names of all declared parameters
+    pub fn declared_parameters(&self) -> impl Iterator<Item = &String> {
+        self.parameters.iter().flat_map(|s| s.properties.keys())
+    }
+
+    /// Declared parameters with the values they accept, empty if any value is accepted
+    pub fn parameter_choices(&self) -> Vec<(String, Vec<String>)> {
+        self.parameters
+            .iter()
+            .flat_map(|s| &s.properties)
+            .map(|(name, property)| {
+                let choices = match (&property.allowed, property.kind.as_deref()) {
+                    (Some(allowed), _) => allowed.iter().map(complete::value_text).collect(),
+                    (None, Some("boolean")) => complete::booleans(),
+                    (None, _) => Vec::new(),
+                };
+                (name.clone(), choices)
+            })
+            .collect()
+    }
+
+    /// Apply the defaults of the declared parameters and validate the parameters against their
+    /// declaration. Strings (e.g. from --set) are converted to the declared type.
+    pub fn apply_parameters(&self, params: &mut Map<String, Value>) -> Result<()> {
+        for section in &self.parameters {
+            for (name, property) in &section.properties {
+                if !params.contains_key(name)
+                    && let Some(default) = &property.default
+                {
+                    params.insert(name.clone(), default.clone());
+                }
+                let Some(value) = params.get_mut(name) else {
+                    continue;
+                };
+                if let Some(kind) = &property.kind {
+                    *value = convert(name, kind, value.take())?;
+                }
+                if let Some(allowed) = &property.allowed
+                    && !allowed.contains(value)
+                {
+                    anyhow::bail!(
+                        "parameter '{}' must be one of {}, got {}",
+                        name,
+                        Value::Array(allowed.clone()),
+                        value
+                    );
+                }
+            }
+
+            let missing: Vec<&str> = section
+                .required
+                .iter()
+                .filter(|name| !params.contains_key(*name))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                anyhow::bail!("missing required parameters: {}", missing.join(", "));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute the values of the `fetch:template` step from the parameters. A value which is a
+    /// single expression like `${{ parameters.port }}` keeps the type of its result. Like in
+    /// Backstage, values referencing parameters which are not set are left out.
+    pub fn values(&self, params: &Map<String, Value>) -> Result<Map<String, Value>> {
+        let mut env = environment(&TemplateConfig {
+            syntax: SyntaxMode::Backstage,
+            root_value: None,
+            ..Default::default()
+        });
+        env.set_undefined_behavior(UndefinedBehavior::Lenient);
+        let context = serde_json::json!({ "parameters": params });
+
+        let mut values = Map::new();
+        for (name, value) in &self.values {
+            let rendered = render_value(&env, &context, value)
+                .with_context(|| format!("Failed to compute value '{}'", name))?;
+            if rendered.is_null() && value.is_string() {
+                continue;
+            }
+            values.insert(name.clone(), rendered);
+        }
+        Ok(values)
+    }
+
+    /// Take the skeleton out of the template files and move it to the target path. Other files
+    /// (template.yaml, docs, ...) are not part of the output.
+    pub fn skeleton_files(&self, files: Vec<TemplateFile>) -> Vec<TemplateFile> {
+        files
+            .into_iter()
+            .filter_map(|mut file| {
+                if file.path == Path::new(TEMPLATE_FILE) {
+                    report::skip(file.path, SkipReason::Config);
+                    return None;
+                }
+                let Ok(path) = file.path.strip_prefix(&self.skeleton) else {
+                    report::skip(
+                        file.path,
+                        SkipReason::OutsideTemplatePath {
+                            template_path: self.skeleton.clone(),
+                        },
+                    );
+                    return None;
+                };
+                file.path = self.target_path.join(path);
+                Some(file)
+            })
+            .collect()
+    }
+}
+
+/// Take the Backstage template definition out of the template files, if there is one
+pub fn split_template(
+    files: Vec<TemplateFile>,
+) -> Result<(Option<BackstageTemplate>, Vec<TemplateFile>)> {
+    let template = match files.iter().find(|f| f.path == Path::new(TEMPLATE_FILE)) {
+        Some(file) => BackstageTemplate::parse(&file.content)?,
+        None => None,
+    };
+    match template {
+        Some(template) => {
+            let files = template.skeleton_files(files);
+            Ok((Some(template), files))
+        }
+        None => Ok((None, files)),
+    }
+}
+
+/// Normalize a relative path like `./skeleton`
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => normalized.push(part),
+            _ => anyhow::bail!("path '{}' must be relative to the template", path),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Convert a parameter to its declared JSON schema type
+fn convert(name: &str, kind: &str, value: Value) -> Result<Value> {
+    let converted = match (kind, value) {
+        ("string", v @ Value::String(_)) => v,
+        ("boolean", v @ Value::Bool(_)) => v,
+        ("boolean", Value::String(s)) if s == "true" || s == "false" => Value::Bool(s == "true"),
+        ("number", v @ Value::Number(_)) => v,
+        ("integer", Value::Number(n)) if n.is_i64() || n.is_u64() => Value::Number(n),
+        ("number" | "integer", Value::String(s)) => match serde_json::from_str(&s) {
+            Ok(Value::Number(n)) if kind == "number" || n.is_i64() || n.is_u64() => {
+                Value::Number(n)
+            }
+            _ => anyhow::bail!("parameter '{}' must be of type {}, got '{}'", name, kind, s),
+        },
+        ("array", v @ Value::Array(_)) => v,
+        ("object", v @ Value::Object(_)) => v,
+        // types we do not know are not checked
+        (kind, v)
+            if !matches!(
+                kind,
+                "string" | "boolean" | "number" | "integer" | "array" | "object"
+            ) =>
+        {
+            v
+        }
+        (kind, v) => anyhow::bail!("parameter '{}' must be of type {}, got {}", name, kind, v),
+    };
+    Ok(converted)
+}
+
+fn render_value(env: &minijinja::Environment, context: &Value, value: &Value) -> Result<Value> {
+    Ok(match value {
+        Value::String(s) => {
+            let trimmed = s.trim();
+            let expression = trimmed
+                .strip_prefix("${{")
+                .and_then(|e| e.strip_suffix("}}"))
+                .filter(|e| !e.contains("}}") && !e.contains("${{"));
+            match expression {
+                Some(expression) => {
+                    let result = env.compile_expression(expression)?.eval(context)?;
+                    if result.is_undefined() {
+                        Value::Null
+                    } else {
+                        serde_json::to_value(result)?
+                    }
+                }
+                None => Value::String(env.render_str(s, context)?),
+            }
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_value(env, context, item))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.clone(), render_value(env, context, v)?)))
+                .collect::<Result<_>>()?,
+        ),
+        v => v.clone(),
+    })
+}
+
+/// Add the filters of the Backstage scaffolder, so the skeletons of Backstage templates render
+/// without changes. See
+/// https://backstage.io/docs/features/software-templates/builtin-actions#using-the-built-in-filters
+pub fn add_filters(env: &mut Environment) {
+    env.add_filter("parseRepoUrl", |url: &str| {
+        parse_repo_url(url).map(minijinja::Value::from_serialize)
+    });
+    env.add_filter("projectSlug", |url: &str| {
+        let repo = parse_repo_url(url)?;
+        Ok::<_, minijinja::Error>(format!(
+            "{}/{}",
+            repo.get("owner").map(String::as_str).unwrap_or_default(),
+            repo["repo"]
+        ))
+    });
+    env.add_filter("pick", pick);
+    // alias of tojson (Nunjucks)
+    env.add_filter("dump", minijinja::filters::tojson);
+}
+
+/// Parts of a repository URL of the RepoUrlPicker, like `github.com?owner=dvob&repo=rte`: the
+/// `host` and the `owner`, `repo`, `organization`, `workspace` and `project` which are set
+fn parse_repo_url(url: &str) -> Result<BTreeMap<&'static str, String>, minijinja::Error> {
+    let invalid = |reason: &str| {
+        minijinja::Error::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid repo URL '{}': {}", url, reason),
+        )
+    };
+    let parsed = Url::parse(&format!("https://{}", url)).map_err(|e| invalid(&e.to_string()))?;
+    let mut parts = BTreeMap::new();
+    let host = parsed.host_str().ok_or_else(|| invalid("missing host"))?;
+    parts.insert("host", host.to_owned());
+    for (key, value) in parsed.query_pairs() {
+        let key = match key.as_ref() {
+            "owner" => "owner",
+            "repo" => "repo",
+            "organization" => "organization",
+            "workspace" => "workspace",
+            "project" => "project",
+            _ => continue,
+        };
+        parts.insert(key, value.into_owned());
+    }
+    if !parts.contains_key("repo") {
+        return Err(invalid("missing repo"));
+    }
+    Ok(parts)
+}
+
+/// Value at a dotted path of an object (lodash `get`), e.g.
+/// `parameters.component | pick('metadata.name')`
+fn pick(value: minijinja::Value, path: &str) -> Result<minijinja::Value, minijinja::Error> {
+    path.split('.').try_fold(value, |value, key| {
+        let key = match (value.kind(), key.parse::<usize>()) {
+            (ValueKind::Seq, Ok(index)) => minijinja::Value::from(index),
+            _ => minijinja::Value::from(key),
+        };
+        match value.is_undefined() || value.is_none() {
+            true => Ok(minijinja::Value::UNDEFINED),
+            false => value.get_item(&key),
+        }
+    })
+}
@@ -0,0 +1,90 @@
+//! Limits for the size of the rendered output, to catch templates which generate unexpectedly
+//! huge files (e.g. a runaway loop).
+
+use anyhow::Result;
+
+use crate::template::TemplateFile;
+use crate::warnings::{self, WarningKind};
+
+/// Size limits of the output, None means unlimited
+#[derive(Debug, Default)]
+pub struct SizeBudget {
+    max_file: Option<u64>,
+    max_total: Option<u64>,
+    total: u64,
+}
+
+impl SizeBudget {
+    pub fn new(max_file: Option<u64>, max_total: Option<u64>) -> Self {
+        Self {
+            max_file,
+            max_total,
+            total: 0,
+        }
+    }
+
+    /// Account for a rendered file and emit a size-budget warning if it exceeds a limit
+    pub fn check(&mut self, file: &TemplateFile) -> Result<()> {
+        let size = file.content.len() as u64;
+        if let Some(max) = self.max_file
+            && size > max
+        {
+            warnings::emit(
+                WarningKind::SizeBudget,
+                format!(
+                    "{} is {} bytes, more than the limit of {} bytes per file",
+                    file.path.display(),
+                    size,
+                    max
+                ),
+            )?;
+        }
+
+        let before = self.total;
+        self.total += size;
+        // only warn once, when the total crosses the limit
+        if let Some(max) = self.max_total
+            && before <= max
+            && self.total > max
+        {
+            warnings::emit(
+                WarningKind::SizeBudget,
+                format!(
+                    "output is larger than the limit of {} bytes with {}",
+                    max,
+                    file.path.display()
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse sizes like "512", "64k", "10M" or "1G" (binary units, an optional "iB" or "B" suffix is
+/// allowed: "10MiB")
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let trimmed = s.trim();
+    let trimmed = trimmed
+        .strip_suffix("iB")
+        .or_else(|| trimmed.strip_suffix('B'))
+        .unwrap_or(trimmed);
+    let (value, factor) = match trimmed.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => {
+            let factor: u64 = match unit.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                _ => return Err(format!("unknown size unit '{}', expected k, M or G", unit)),
+            };
+            (&trimmed[..i], factor)
+        }
+        _ => (trimmed, 1),
+    };
+    let value: u64 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("expected a size like 512k, 10M or 1G, got '{}'", s))?;
+    value
+        .checked_mul(factor)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
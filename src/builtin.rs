@@ -0,0 +1,53 @@
+//! Starter templates embedded in the binary (`builtin://NAME`), so rte can be tried without a
+//! template at hand. They live in `builtin/` of the repository. Their `Cargo.toml` is named
+//! `{{ "Cargo.toml" }}`, a directory with a `Cargo.toml` would be left out of the rte package.
+
+use anyhow::Result;
+use include_dir::{Dir, DirEntry, include_dir};
+
+use crate::source::FileIter;
+use crate::template::{FileKind, TemplateFile};
+
+static TEMPLATES: Dir = include_dir!("$CARGO_MANIFEST_DIR/builtin");
+
+/// Names of the built-in templates
+pub fn names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = TEMPLATES
+        .dirs()
+        .filter_map(|dir| dir.path().to_str())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+/// Open a built-in template by name
+pub fn open(name: &str) -> Result<FileIter> {
+    let Some(dir) = TEMPLATES.get_dir(name) else {
+        anyhow::bail!(
+            "unknown built-in template '{}', available: {}",
+            name,
+            names().join(", ")
+        );
+    };
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files);
+    Ok(Box::new(files.into_iter().map(Ok)))
+}
+
+fn collect_files(root: &Dir, dir: &Dir, files: &mut Vec<TemplateFile>) {
+    for entry in dir.entries() {
+        match entry {
+            DirEntry::Dir(dir) => collect_files(root, dir, files),
+            DirEntry::File(file) => files.push(TemplateFile {
+                path: file
+                    .path()
+                    .strip_prefix(root.path())
+                    .expect("embedded files are below their template")
+                    .to_path_buf(),
+                content: file.contents().to_vec(),
+                kind: FileKind::File,
+                mode: None,
+            }),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Template bundles (.rtepkg): a gzip compressed tar archive with the template files under
+//! `template/`, a metadata file which pins where the template came from and a digest of its
+//! files, and optionally a signature of the digest (HMAC-SHA256 with a shared key).
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::source::FileIter;
+use crate::tar::write_to_tar_gz;
+use crate::template::{FileKind, TemplateFile};
+
+/// Extension of bundle files
+pub const EXTENSION: &str = "rtepkg";
+
+/// Version of the bundle format
+const FORMAT: u32 = 1;
+
+const METADATA_FILE: &str = "rte-bundle.yaml";
+const SIGNATURE_FILE: &str = "rte-bundle.sig";
+const TEMPLATE_DIR: &str = "template";
+
+/// Metadata of a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleMetadata {
+    pub format: u32,
+    /// Source the template was bundled from
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_path: Option<String>,
+    /// Creation time (RFC 3339)
+    pub created: String,
+    /// Version of rte which created the bundle
+    pub rte_version: String,
+    /// SHA-256 digest over the paths, kinds, modes and contents of the template files
+    pub digest: String,
+}
+
+/// Whether a source (path or URL) is a bundle
+pub fn is_bundle(source: &str) -> bool {
+    Path::new(source)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION))
+}
+
+/// Write a bundle of the template files. With a key the digest is signed.
+pub fn write_bundle(
+    dest: &Path,
+    source: &str,
+    template_path: Option<&str>,
+    mut files: Vec<TemplateFile>,
+    key: Option<&str>,
+) -> Result<BundleMetadata> {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let metadata = BundleMetadata {
+        format: FORMAT,
+        source: source.to_owned(),
+        template_path: template_path.map(str::to_owned),
+        created: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        rte_version: env!("CARGO_PKG_VERSION").to_owned(),
+        digest: digest(&files),
+    };
+
+    let mut entries = vec![TemplateFile {
+        path: PathBuf::from(METADATA_FILE),
+        content: serde_yaml::to_string(&metadata)
+            .context("Failed to serialize bundle metadata")?
+            .into_bytes(),
+        kind: FileKind::File,
+        mode: None,
+    }];
+    if let Some(key) = key {
+        entries.push(TemplateFile {
+            path: PathBuf::from(SIGNATURE_FILE),
+            content: sign(key, &metadata.digest).into_bytes(),
+            kind: FileKind::File,
+            mode: None,
+        });
+    }
+    entries.extend(files.into_iter().map(|mut file| {
+        file.path = Path::new(TEMPLATE_DIR).join(&file.path);
+        file
+    }));
+
+    write_to_tar_gz(dest, entries.into_iter().map(Ok), false)?;
+    Ok(metadata)
+}
+
+/// Take the template files out of the files of a bundle and verify them against the digest. With
+/// a key the bundle must be signed with it.
+pub fn unpack(files: FileIter, key: Option<&str>) -> Result<FileIter> {
+    let mut metadata = None;
+    let mut signature = None;
+    let mut template = Vec::new();
+    for file in files {
+        let mut file = file?;
+        if file.path == Path::new(METADATA_FILE) {
+            metadata = Some(
+                serde_yaml::from_slice::<BundleMetadata>(&file.content)
+                    .context("Failed to parse bundle metadata")?,
+            );
+        } else if file.path == Path::new(SIGNATURE_FILE) {
+            signature = Some(String::from_utf8_lossy(&file.content).trim().to_owned());
+        } else if let Ok(path) = file.path.strip_prefix(TEMPLATE_DIR) {
+            file.path = path.to_path_buf();
+            template.push(file);
+        }
+    }
+
+    let metadata = metadata.with_context(|| format!("bundle has no {}", METADATA_FILE))?;
+    if metadata.format != FORMAT {
+        anyhow::bail!("unsupported bundle format {}", metadata.format);
+    }
+
+    if digest(&template) != metadata.digest {
+        anyhow::bail!("bundle is corrupted: the digest of its files does not match");
+    }
+
+    if let Some(key) = key {
+        let signature = signature.context("bundle is not signed")?;
+        if !verify(key, &metadata.digest, &signature) {
+            anyhow::bail!("bundle signature is invalid");
+        }
+    }
+
+    Ok(Box::new(template.into_iter().map(Ok)))
+}
+
+/// Digest over the files sorted by path: path, kind, mode, length and content of each file. Files
+/// without a mode count as `0644`, the mode archives give them.
+pub fn digest(files: &[TemplateFile]) -> String {
+    let mut sorted: Vec<&TemplateFile> = files.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut hasher = Sha256::new();
+    for file in sorted {
+        let (kind, mode) = match file.kind {
+            FileKind::File => (0u8, file.mode.unwrap_or(0o644)),
+            FileKind::Dir => (1, 0),
+            FileKind::Symlink => (2, 0),
+        };
+        hasher.update(file.path.to_string_lossy().as_bytes());
+        hasher.update([0, kind]);
+        hasher.update(mode.to_le_bytes());
+        hasher.update((file.content.len() as u64).to_le_bytes());
+        hasher.update(&file.content);
+    }
+    format!("sha256:{}", hex::encode(hasher.finalize()))
+}
+
+fn mac(key: &str, digest: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(digest.as_bytes());
+    mac
+}
+
+fn sign(key: &str, digest: &str) -> String {
+    hex::encode(mac(key, digest).finalize().into_bytes())
+}
+
+fn verify(key: &str, digest: &str, signature: &str) -> bool {
+    match hex::decode(signature) {
+        // constant time comparison
+        Ok(signature) => mac(key, digest).verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
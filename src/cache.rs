@@ -0,0 +1,177 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::filelock::FileLock;
+
+/// Lock file in the cache directory. Entries are replaced and evicted holding the lock
+/// exclusively and opened holding it shared.
+const LOCK_FILE: &str = "cache.lock";
+
+/// Local cache for downloaded template archives (~/.cache/rte by default)
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    /// Entries older than this are downloaded again
+    max_age: Duration,
+    /// Oldest entries are evicted once the cache grows beyond this size (in bytes)
+    max_size: u64,
+}
+
+/// Default cache directory: $XDG_CACHE_HOME/rte or ~/.cache/rte
+pub fn default_dir() -> Option<PathBuf> {
+    let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_dir.join("rte"))
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, max_age: Duration, max_size: u64) -> Self {
+        Self {
+            dir,
+            max_age,
+            max_size,
+        }
+    }
+
+    /// Path of the entry for a source URL. The URL includes the ref, so every ref has its own
+    /// entry.
+    fn entry_path(&self, source: &str) -> PathBuf {
+        self.dir
+            .join(hex::encode(Sha256::digest(source.as_bytes())))
+    }
+
+    /// Look up the cached archive of a source. Expired entries are only returned with
+    /// `allow_expired` (e.g. in offline mode where there is no way to refresh them).
+    pub fn get(&self, source: &str, allow_expired: bool) -> Option<PathBuf> {
+        let path = self.entry_path(source);
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        (allow_expired || age <= self.max_age).then_some(path)
+    }
+
+    /// Open the cached archive of a source, see [Cache::get]. Returns the archive and its
+    /// Content-Type.
+    pub fn open(
+        &self,
+        source: &str,
+        allow_expired: bool,
+    ) -> Result<Option<(File, Option<String>)>> {
+        if !self.dir.exists() {
+            return Ok(None);
+        }
+        let _lock = FileLock::shared(&self.dir.join(LOCK_FILE))?;
+        let Some(path) = self.get(source, allow_expired) else {
+            return Ok(None);
+        };
+        let file = File::open(&path)
+            .with_context(|| format!("Failed to open cache entry: {}", path.display()))?;
+        Ok(Some((file, self.content_type(source))))
+    }
+
+    /// Content-Type the cached archive of a source was served with, if any
+    pub fn content_type(&self, source: &str) -> Option<String> {
+        fs::read_to_string(content_type_path(&self.entry_path(source))).ok()
+    }
+
+    /// Store the archive of a source and evict old entries. Returns the new entry, opened before
+    /// other processes can evict it.
+    pub fn store(
+        &self,
+        source: &str,
+        mut archive: impl Read,
+        content_type: Option<&str>,
+    ) -> Result<File> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create cache directory: {}", self.dir.display()))?;
+
+        // write to a temporary file first so an interrupted download never ends up in the cache,
+        // the name is unique per process so concurrent downloads of the same source do not mix
+        let path = self.entry_path(source);
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        {
+            let mut file = File::create(&tmp)
+                .with_context(|| format!("Failed to create cache entry: {}", tmp.display()))?;
+            io::copy(&mut archive, &mut file)
+                .with_context(|| format!("Failed to download {}", source))?;
+        }
+
+        let _lock = FileLock::exclusive(&self.dir.join(LOCK_FILE))?;
+        fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to create cache entry: {}", path.display()))?;
+
+        let type_path = content_type_path(&path);
+        match content_type {
+            Some(content_type) => fs::write(&type_path, content_type).with_context(|| {
+                format!("Failed to create cache entry: {}", type_path.display())
+            })?,
+            None => {
+                let _ = fs::remove_file(&type_path);
+            }
+        }
+
+        self.evict(&path)?;
+        File::open(&path).with_context(|| format!("Failed to open cache entry: {}", path.display()))
+    }
+
+    /// Remove expired entries and then the oldest entries until the cache fits into max_size.
+    /// The entry `keep` is never removed.
+    fn evict(&self, keep: &Path) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read cache directory: {}", self.dir.display()))?
+        {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            // sidecar files (content type) and temporary files are handled with their entry
+            if !metadata.is_file() || entry.path() == keep || entry.path().extension().is_some() {
+                continue;
+            }
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+
+        let now = SystemTime::now();
+        let mut size: u64 = fs::metadata(keep).map(|m| m.len()).unwrap_or_default();
+        entries.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified)); // newest first
+        for (modified, len, path) in entries {
+            let expired = now.duration_since(modified).unwrap_or_default() > self.max_age;
+            if expired || size + len > self.max_size {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove cache entry: {}", path.display()))?;
+                let _ = fs::remove_file(content_type_path(&path));
+            } else {
+                size += len;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path of the file holding the Content-Type of a cache entry
+fn content_type_path(entry: &Path) -> PathBuf {
+    entry.with_extension("type")
+}
+
+/// Parse durations like "90s", "30m", "12h" or "7d"
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (value, unit) = s.split_at(s.len().saturating_sub(1));
+    let value: u64 = value
+        .parse()
+        .map_err(|_| "expected a number followed by s, m, h or d (e.g. 12h)".to_owned())?;
+    let seconds = match unit {
+        "s" => value,
+        "m" => value * 60,
+        "h" => value * 60 * 60,
+        "d" => value * 60 * 60 * 24,
+        _ => return Err("expected a unit of s, m, h or d (e.g. 12h)".to_owned()),
+    };
+    Ok(Duration::from_secs(seconds))
+}
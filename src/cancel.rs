@@ -0,0 +1,62 @@
+//! Graceful cancellation with Ctrl-C (SIGINT) or SIGTERM. The signal only marks the run as
+//! cancelled, the render stops at the next file or step and fails like on any other error: the
+//! destination is rolled back, temporary directories are removed and locks are released. A
+//! second signal exits right away.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of a cancelled run, like a shell reports a process killed by SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Error of a cancelled run
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Handle SIGINT and SIGTERM from now on. Until then a signal ends the process right away, which
+/// is what should happen while rte waits for input at a prompt.
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { libc::_exit(EXIT_CODE) };
+        }
+    }
+
+    // SAFETY: the handler only touches an atomic and calls _exit
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut());
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+/// Fail if the run was cancelled
+pub fn check() -> anyhow::Result<()> {
+    if CANCELLED.load(Ordering::SeqCst) {
+        return Err(Cancelled.into());
+    }
+    Ok(())
+}
+
+/// Whether an error is caused by cancellation
+pub fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<Cancelled>())
+}
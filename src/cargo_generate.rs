@@ -0,0 +1,352 @@
+//! Compatibility with templates written for cargo-generate
+//! (https://cargo-generate.github.io/cargo-generate).
+//!
+//! If the template root contains a `cargo-generate.toml`, its placeholders provide the defaults
+//! and types of the parameters, the `ignore`, `include` and `exclude` lists and conditional
+//! ignores are honored and the builtin placeholders (`project-name`, `crate_name`, ...) are set.
+//! Liquid placeholders with a dash like `{{project-name}}` are not valid Jinja, they are rewritten
+//! to `{{project_name}}` before rendering.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::complete;
+use crate::glob;
+use crate::report::{self, SkipReason};
+use crate::template::{SyntaxMode, TemplateConfig, TemplateFile, environment};
+
+/// Name of the cargo-generate configuration in the template root
+pub const CONFIG_FILE: &str = "cargo-generate.toml";
+
+/// Suffix of files which is removed from their name
+const LIQUID_SUFFIX: &str = ".liquid";
+
+/// Builtin placeholders with a dash
+const BUILTIN_DASHED: [&str; 2] = ["project-name", "os-arch"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Config {
+    template: TemplateSection,
+    placeholders: BTreeMap<String, Placeholder>,
+    conditional: BTreeMap<String, Conditional>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TemplateSection {
+    /// Files which are not part of the output
+    ignore: Vec<String>,
+    /// Only these files are rendered, the others are copied
+    include: Option<Vec<String>>,
+    /// Files which are copied without rendering
+    exclude: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Placeholder {
+    #[serde(rename = "type")]
+    kind: String,
+    default: Option<toml::Value>,
+    choices: Option<Vec<String>>,
+    regex: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Conditional {
+    ignore: Vec<String>,
+}
+
+/// A parsed cargo-generate.toml
+#[derive(Debug)]
+pub struct CargoGenerateTemplate {
+    placeholders: BTreeMap<String, Placeholder>,
+    ignore: GlobSet,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    /// Conditions (Jinja expressions) with the files ignored if they are true
+    conditionals: Vec<(String, Vec<String>)>,
+    /// Placeholder names with a dash, rewritten in the templates
+    dashed: Vec<String>,
+}
+
+impl CargoGenerateTemplate {
+    pub fn parse(content: &[u8]) -> Result<Self> {
+        let content = std::str::from_utf8(content)
+            .with_context(|| format!("{} is not valid UTF8", CONFIG_FILE))?;
+        let config: Config =
+            toml::from_str(content).with_context(|| format!("Failed to parse {}", CONFIG_FILE))?;
+
+        let mut ignore = config.template.ignore;
+        ignore.extend([CONFIG_FILE.to_owned(), "/.git".to_owned()]);
+
+        let mut dashed: Vec<String> = BUILTIN_DASHED.iter().map(|s| s.to_string()).collect();
+        dashed.extend(
+            config
+                .placeholders
+                .keys()
+                .filter(|k| k.contains('-'))
+                .cloned(),
+        );
+        // longer names first, so that "project-name" is not rewritten as part of "my-project-name"
+        dashed.sort_by_key(|name| std::cmp::Reverse(name.len()));
+
+        Ok(Self {
+            placeholders: config.placeholders,
+            ignore: glob::gitignore_set(&ignore)?,
+            include: config
+                .template
+                .include
+                .map(|include| glob::gitignore_set(&include))
+                .transpose()?,
+            exclude: glob::gitignore_set(&config.template.exclude)?,
+            conditionals: config
+                .conditional
+                .into_iter()
+                .map(|(condition, c)| (rewrite_dashed(&condition, &dashed, false), c.ignore))
+                .collect(),
+            dashed,
+        })
+    }
+
+    /// Declared placeholders with the values they accept, empty if any value is accepted
+    pub fn parameter_choices(&self) -> Vec<(String, Vec<String>)> {
+        self.placeholders
+            .iter()
+            .map(|(name, placeholder)| {
+                let choices = match (&placeholder.choices, placeholder.kind.as_str()) {
+                    (Some(choices), _) => choices.clone(),
+                    (None, "bool") => complete::booleans(),
+                    (None, _) => Vec::new(),
+                };
+                (name.clone(), choices)
+            })
+            .collect()
+    }
+
+    /// Set the builtin placeholders and answer the placeholders of the template from the
+    /// parameters or their default. Parameter names with a dash are stored with an underscore.
+    /// The project name defaults to the name of the destination.
+    pub fn answer(&self, params: &mut Map<String, Value>, destination: &Path) -> Result<()> {
+        let dashed: Vec<String> = params.keys().filter(|k| k.contains('-')).cloned().collect();
+        for key in dashed {
+            if let Some(value) = params.remove(&key) {
+                params.insert(key.replace('-', "_"), value);
+            }
+        }
+
+        let project_name = match params.get("project_name").or_else(|| params.get("name")) {
+            Some(Value::String(name)) => name.clone(),
+            Some(value) => anyhow::bail!("project name must be a string, got {}", value),
+            None => destination
+                .file_name()
+                .and_then(|n| n.to_str())
+                .context(
+                    "Failed to derive the project name from the destination, set project-name",
+                )?
+                .to_owned(),
+        };
+        let project_name = project_name.replace(['_', ' '], "-").to_lowercase();
+        params.insert(
+            "crate_name".to_owned(),
+            project_name.replace('-', "_").into(),
+        );
+        params.insert("project_name".to_owned(), project_name.into());
+        params.entry("crate_type").or_insert_with(|| "bin".into());
+        params.entry("os_arch").or_insert_with(|| {
+            format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH).into()
+        });
+        params.entry("authors").or_insert_with(|| authors().into());
+
+        for (name, placeholder) in &self.placeholders {
+            let key = name.replace('-', "_");
+            let value = match params.remove(&key) {
+                Some(value) => value,
+                None => match &placeholder.default {
+                    Some(default) => serde_json::to_value(default)?,
+                    None => anyhow::bail!("missing value for placeholder '{}'", name),
+                },
+            };
+            let value = match (placeholder.kind.as_str(), value) {
+                ("bool", Value::String(s)) => match s.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => anyhow::bail!("placeholder '{}' must be true or false, got '{}'", name, s),
+                },
+                ("bool", value @ Value::Bool(_)) | ("string", value @ Value::String(_)) => value,
+                (kind, value) => {
+                    anyhow::bail!("placeholder '{}' must be a {}, got {}", name, kind, value)
+                }
+            };
+
+            if let Value::String(s) = &value {
+                if let Some(choices) = &placeholder.choices
+                    && !choices.contains(s)
+                {
+                    anyhow::bail!(
+                        "placeholder '{}' must be one of {}, got '{}'",
+                        name,
+                        choices.join(", "),
+                        s
+                    );
+                }
+                if let Some(regex) = &placeholder.regex {
+                    let regex = Regex::new(regex)
+                        .with_context(|| format!("invalid regex of placeholder '{}'", name))?;
+                    if !regex.is_match(s) {
+                        anyhow::bail!("placeholder '{}' must match {}, got '{}'", name, regex, s);
+                    }
+                }
+            }
+            params.insert(key, value);
+        }
+        Ok(())
+    }
+
+    /// Apply the ignore, include and exclude lists and the liquid suffix to the template files
+    /// and rewrite dashed placeholders. Returns the files to write and the paths of those which
+    /// must be copied without rendering.
+    pub fn prepare_files(&self, files: Vec<TemplateFile>) -> (Vec<TemplateFile>, HashSet<PathBuf>) {
+        let mut prepared = Vec::with_capacity(files.len());
+        let mut verbatim = HashSet::new();
+        for mut file in files {
+            if file.path == Path::new(CONFIG_FILE) {
+                report::skip(file.path, SkipReason::Config);
+                continue;
+            }
+            if self.ignore.is_match(&file.path) {
+                report::skip(
+                    file.path,
+                    SkipReason::Ignored {
+                        by: format!("{} ignore", CONFIG_FILE),
+                    },
+                );
+                continue;
+            }
+            let copied = self.exclude.is_match(&file.path)
+                || self
+                    .include
+                    .as_ref()
+                    .is_some_and(|include| !include.is_match(&file.path));
+
+            if let Some(path) = file.path.to_str() {
+                let path = path.strip_suffix(LIQUID_SUFFIX).unwrap_or(path);
+                file.path = PathBuf::from(rewrite_dashed(path, &self.dashed, true));
+            }
+            if copied {
+                verbatim.insert(file.path.clone());
+            } else if let Ok(content) = std::str::from_utf8(&file.content) {
+                file.content = rewrite_dashed(content, &self.dashed, true).into_bytes();
+            }
+            prepared.push(file);
+        }
+        (prepared, verbatim)
+    }
+
+    /// Remove the files of conditional ignores whose condition is true for the parameters
+    pub fn apply_conditionals(&self, files: &mut Vec<TemplateFile>, params: &Value) -> Result<()> {
+        let env = environment(&TemplateConfig {
+            syntax: SyntaxMode::Jinja,
+            root_value: None,
+            ..Default::default()
+        });
+        let mut ignore = Vec::new();
+        for (condition, patterns) in &self.conditionals {
+            let result = env
+                .compile_expression(condition)
+                .and_then(|e| e.eval(params))
+                .with_context(|| format!("Failed to evaluate condition '{}'", condition))?;
+            if result.is_true() {
+                ignore.push((condition, glob::gitignore_set(patterns)?));
+            }
+        }
+        files.retain(
+            |file| match ignore.iter().find(|(_, set)| set.is_match(&file.path)) {
+                Some((condition, _)) => {
+                    report::skip(
+                        &file.path,
+                        SkipReason::Conditional {
+                            condition: condition.to_string(),
+                        },
+                    );
+                    false
+                }
+                None => true,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Find and parse the cargo-generate configuration of the template files, if there is one. It is
+/// removed from the output by the ignore list.
+pub fn split_template(files: &[TemplateFile]) -> Result<Option<CargoGenerateTemplate>> {
+    match files.iter().find(|f| f.path == Path::new(CONFIG_FILE)) {
+        Some(file) => Ok(Some(CargoGenerateTemplate::parse(&file.content)?)),
+        None => Ok(None),
+    }
+}
+
+/// Replace the dashed names by their underscore variant. With `in_tags` only within `{{ }}` and
+/// `{% %}`, otherwise in the whole text (for conditions).
+fn rewrite_dashed(text: &str, names: &[String], in_tags: bool) -> String {
+    let replace = |mut part: String| {
+        for name in names {
+            part = part.replace(name.as_str(), &name.replace('-', "_"));
+        }
+        part
+    };
+    if !in_tags {
+        return replace(text.to_owned());
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let (before, tag) = rest.split_at(start);
+        result.push_str(before);
+        let end = match tag.get(..2) {
+            Some("{{") => tag.find("}}"),
+            Some("{%") => tag.find("%}"),
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                result.push_str(&replace(tag[..end + 2].to_owned()));
+                rest = &tag[end + 2..];
+            }
+            None => {
+                result.push('{');
+                rest = &tag[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Authors from the git configuration like cargo-generate does, e.g. `Jane <jane@example.com>`
+fn authors() -> String {
+    let git_config = |key: &str| {
+        std::process::Command::new("git")
+            .args(["config", "--get", key])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| String::from_utf8(o.stdout).ok())
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+    };
+    match (git_config("user.name"), git_config("user.email")) {
+        (Some(name), Some(email)) => format!("{} <{}>", name, email),
+        (Some(name), None) => name,
+        _ => std::env::var("USER").unwrap_or_default(),
+    }
+}
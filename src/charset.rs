@@ -0,0 +1,123 @@
+//! Text encodings other than UTF-8 for legacy templates. Files declared under `encodings` in the
+//! manifest and UTF-16 files with a byte order mark are decoded, rendered as UTF-8 and encoded
+//! again, other files which are not UTF-8 are copied without rendering.
+//!
+//! ```yaml
+//! encodings:
+//!   - path: legacy/*.properties
+//!     encoding: latin1
+//! ```
+
+use anyhow::Result;
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+
+use crate::glob;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    /// ISO-8859-1
+    #[serde(rename = "latin1", alias = "iso-8859-1")]
+    Latin1,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "latin1",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        }
+    }
+
+    /// Decode the content, a byte order mark is kept as U+FEFF so it is encoded again
+    pub fn decode(self, content: &[u8]) -> Result<String> {
+        let invalid = || anyhow::anyhow!("the content is not valid {}", self.name());
+        let from_bytes = match self {
+            Encoding::Utf8 => {
+                return String::from_utf8(content.to_vec()).map_err(|_| invalid());
+            }
+            Encoding::Latin1 => return Ok(content.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Utf16Le => u16::from_le_bytes,
+            Encoding::Utf16Be => u16::from_be_bytes,
+        };
+        if !content.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let units = content.chunks(2).map(|pair| from_bytes([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|_| invalid())
+    }
+
+    pub fn encode(self, text: &str) -> Result<Vec<u8>> {
+        let to_bytes = match self {
+            Encoding::Utf8 => return Ok(text.as_bytes().to_vec()),
+            Encoding::Latin1 => {
+                return text
+                    .chars()
+                    .map(|c| {
+                        u8::try_from(c).map_err(|_| {
+                            anyhow::anyhow!("'{}' can not be encoded as {}", c, self.name())
+                        })
+                    })
+                    .collect();
+            }
+            Encoding::Utf16Le => u16::to_le_bytes,
+            Encoding::Utf16Be => u16::to_be_bytes,
+        };
+        Ok(text.encode_utf16().flat_map(to_bytes).collect())
+    }
+
+    /// Detect UTF-16 by its byte order mark
+    fn detect(content: &[u8]) -> Option<Self> {
+        match content {
+            [0xff, 0xfe, ..] => Some(Encoding::Utf16Le),
+            [0xfe, 0xff, ..] => Some(Encoding::Utf16Be),
+            _ => None,
+        }
+    }
+}
+
+/// Encoding of the files matching a gitignore-like pattern
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncodingRule {
+    pub path: String,
+    pub encoding: Encoding,
+}
+
+#[derive(Default)]
+pub struct Encodings {
+    rules: Vec<(Encoding, GlobSet)>,
+}
+
+impl Encodings {
+    pub fn new(rules: &[EncodingRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let set = glob::gitignore_set(std::slice::from_ref(&rule.path))?;
+                Ok((rule.encoding, set))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Encoding of a file other than UTF-8: declared by the first rule matching it, or detected
+    pub fn of(&self, path: &std::path::Path, content: &[u8]) -> Option<Encoding> {
+        self.rules
+            .iter()
+            .find(|(_, set)| set.is_match(path))
+            .map(|(encoding, _)| *encoding)
+            .or_else(|| Encoding::detect(content))
+            .filter(|encoding| *encoding != Encoding::Utf8)
+    }
+}
@@ -0,0 +1,29 @@
+//! Times of templates. The time templates get from `now()` is taken once per render (see
+//! `TemplateConfig::now`), so all files of a render get the same time, and can be frozen
+//! (--frozen-time, SOURCE_DATE_EPOCH) for golden tests and reproducible builds.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// Parse seconds since the Unix epoch (like SOURCE_DATE_EPOCH), an RFC 3339 time, `YYYY-MM-DD
+/// HH:MM:SS` or a date. Times without an offset are UTC.
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0)
+            .ok_or_else(|| format!("'{}' seconds are out of range", s));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        return Ok(time.and_utc());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_time(Default::default()).and_utc());
+    }
+    Err(format!(
+        "'{}' is not a time, expected seconds since the epoch, RFC 3339 \
+         (2024-05-01T12:00:00Z) or a date (2024-05-01)",
+        s
+    ))
+}
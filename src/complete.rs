@@ -0,0 +1,103 @@
+//! Shell completions (`rte completions <SHELL>`). The registration script calls rte back to
+//! complete a command line, so besides the options the keys and values of --set are completed
+//! from the metadata of the source given earlier on the command line.
+
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use serde_json::Value;
+
+/// Environment variable the registration script sets to the shell when it calls rte
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// A parameter which can be set with --set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    /// Values the parameter accepts, empty if any value is accepted
+    pub choices: Vec<String>,
+    pub help: Option<String>,
+}
+
+/// Names of the supported shells
+pub fn shells() -> Vec<&'static str> {
+    Shells::builtins().names().collect()
+}
+
+/// Write the script which registers the completions of rte in the shell
+pub fn write_registration(shell: &str, out: &mut dyn Write) -> Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .with_context(|| format!("unsupported shell '{}'", shell))?;
+    let exe = std::env::current_exe().context("Failed to determine the path of rte")?;
+    completer
+        .write_registration(COMPLETE_VAR, "rte", "rte", &exe.to_string_lossy(), out)
+        .context("Failed to write completion script")
+}
+
+/// Words of the command line which is completed, without the word at the cursor (`current`),
+/// which is usually incomplete. The registration script passes them after `--`.
+pub fn command_line(current: &OsStr) -> Vec<OsString> {
+    let mut words: Vec<OsString> = std::env::args_os()
+        .skip_while(|arg| arg != "--")
+        .skip(1)
+        .collect();
+    if let Some(index) = words.iter().rposition(|word| word == current) {
+        words.remove(index);
+    }
+    words
+}
+
+/// Add a parameter. A parameter known already only gets the choices and help it lacks.
+pub fn add(parameters: &mut Vec<Parameter>, parameter: Parameter) {
+    match parameters.iter_mut().find(|p| p.name == parameter.name) {
+        Some(known) => {
+            if known.choices.is_empty() {
+                known.choices = parameter.choices;
+            }
+            if known.help.is_none() {
+                known.help = parameter.help;
+            }
+        }
+        None => parameters.push(parameter),
+    }
+}
+
+/// Candidates for the value of --set: `KEY=` for the parameters starting with `current` and,
+/// once the key is complete, `KEY=VALUE` for its choices
+pub fn candidates(parameters: &[Parameter], current: &str) -> Vec<CompletionCandidate> {
+    match current.split_once('=') {
+        Some((key, prefix)) => parameters
+            .iter()
+            .filter(|p| p.name == key)
+            .flat_map(|p| &p.choices)
+            .filter(|choice| choice.starts_with(prefix))
+            .map(|choice| CompletionCandidate::new(format!("{}={}", key, choice)))
+            .collect(),
+        None => parameters
+            .iter()
+            .filter(|p| p.name.starts_with(current))
+            .map(|p| {
+                CompletionCandidate::new(format!("{}=", p.name))
+                    .help(p.help.as_ref().map(|help| help.into()))
+            })
+            .collect(),
+    }
+}
+
+/// A value as given with --set, strings without quotes
+pub fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Choices of a boolean parameter
+pub fn booleans() -> Vec<String> {
+    vec!["true".to_owned(), "false".to_owned()]
+}
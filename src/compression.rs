@@ -0,0 +1,77 @@
+use std::io::Read;
+
+use anyhow::{Context, Result};
+
+/// Compression of a template archive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+    /// Plain (uncompressed) tar
+    None,
+}
+
+impl Compression {
+    /// Detect the compression by the magic bytes at the start of the data
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Detect the compression by the file extension of a path or URL
+    pub fn from_extension(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Compression::Gzip)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(Compression::Xz)
+        } else if name.ends_with(".tar.bz2") || name.ends_with(".tbz2") {
+            Some(Compression::Bzip2)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Compression::Zstd)
+        } else if name.ends_with(".tar") {
+            Some(Compression::None)
+        } else {
+            None
+        }
+    }
+
+    /// Detect the compression by the HTTP Content-Type of a download
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "application/gzip" | "application/x-gzip" | "application/x-tar+gzip" => {
+                Some(Compression::Gzip)
+            }
+            "application/x-xz" => Some(Compression::Xz),
+            "application/x-bzip2" => Some(Compression::Bzip2),
+            "application/zstd" => Some(Compression::Zstd),
+            "application/x-tar" => Some(Compression::None),
+            _ => None,
+        }
+    }
+
+    /// Wrap a reader with the matching decoder
+    pub fn decoder(self, reader: impl Read + 'static) -> Result<Box<dyn Read>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new(reader)),
+            Compression::Bzip2 => Box::new(bzip2::read::BzDecoder::new(reader)),
+            Compression::Zstd => Box::new(
+                zstd::stream::read::Decoder::new(reader)
+                    .context("Failed to initialize zstd decoder")?,
+            ),
+            Compression::None => Box::new(reader),
+        })
+    }
+}
@@ -0,0 +1,90 @@
+//! User configuration with defaults for every run, read from `$XDG_CONFIG_HOME/rte/config.yaml`
+//! (or `~/.config/rte/config.yaml`, `--config` and `RTE_CONFIG` name another file). Options and
+//! their environment variables override it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::template::SyntaxMode;
+use crate::warnings::{WarningLevel, parse_warning_level};
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Syntax of templates which do not set one (--syntax)
+    pub syntax: Option<String>,
+    /// Don't wrap parameters under the root key (--parameters-on-root)
+    pub parameters_on_root: bool,
+    /// Cache directory for remote sources (--cache-dir)
+    pub cache_dir: Option<PathBuf>,
+    /// Tokens for gitlab:// and github:// sources by host, used without --gitlab-token or
+    /// --github-token
+    pub tokens: BTreeMap<String, String>,
+    /// Short names for sources or registered templates
+    pub aliases: BTreeMap<String, String>,
+    /// Severities of warning classes (-W), e.g. `unused-parameter: deny`
+    pub warnings: BTreeMap<String, String>,
+}
+
+/// Default location of the configuration: $XDG_CONFIG_HOME/rte/config.yaml or
+/// ~/.config/rte/config.yaml
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("rte").join("config.yaml"))
+}
+
+impl UserConfig {
+    /// Load the configuration from a file or the default location. A missing file is an empty
+    /// configuration, unless the file was given explicitly.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) if !path.exists() => {
+                anyhow::bail!("configuration {} does not exist", path.display())
+            }
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read configuration: {}", path.display()))?;
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let config: Self = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse configuration: {}", path.display()))?;
+        if let Some(syntax) = &config.syntax
+            && !SyntaxMode::NAMES.contains(&syntax.as_str())
+        {
+            anyhow::bail!(
+                "unknown syntax '{}' in {}, expected one of {}",
+                syntax,
+                path.display(),
+                SyntaxMode::NAMES.join(", ")
+            );
+        }
+        config
+            .warning_levels()
+            .map_err(|e| anyhow::anyhow!("invalid warnings in {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// Severities of the warning classes, `all` first so that the classes refine it
+    pub fn warning_levels(&self) -> Result<Vec<WarningLevel>, String> {
+        let mut levels = self
+            .warnings
+            .iter()
+            .map(|(kind, severity)| parse_warning_level(&format!("{}={}", kind, severity)))
+            .collect::<Result<Vec<_>, _>>()?;
+        levels.sort_by_key(|level| level.kind.is_some());
+        Ok(levels)
+    }
+}
@@ -0,0 +1,313 @@
+//! Compatibility with templates written for copier (https://copier.readthedocs.io).
+//!
+//! If the template root contains a `copier.yml`, its questions provide the defaults and types of
+//! the parameters, and the `_subdirectory`, `_exclude` and `_templates_suffix` settings are
+//! honored. Like in copier, only files with the templates suffix (`.jinja`) are rendered, the
+//! parameters are passed on the root level.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use minijinja::Environment;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::complete;
+use crate::glob;
+use crate::report::{self, SkipReason};
+use crate::template::{SyntaxMode, TemplateConfig, TemplateFile, environment};
+
+/// Names of the copier configuration in the template root
+pub const CONFIG_FILES: [&str; 2] = ["copier.yml", "copier.yaml"];
+
+/// Patterns copier excludes unless `_exclude` is set
+const DEFAULT_EXCLUDE: [&str; 8] = [
+    "copier.yaml",
+    "copier.yml",
+    "~*",
+    "*.py[co]",
+    "__pycache__",
+    ".git",
+    ".DS_Store",
+    ".svn",
+];
+
+const DEFAULT_TEMPLATES_SUFFIX: &str = ".jinja";
+
+/// A question of copier.yml, either in its long form or just the default value
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Question {
+    Full(QuestionSpec),
+    Default(Value),
+}
+
+/// The parts of a question rte uses, the rest (help, validator, ...) is ignored
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct QuestionSpec {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    default: Option<Value>,
+    choices: Option<Choices>,
+    when: Option<Value>,
+}
+
+/// Choices are a list of values or a mapping of labels to values
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Choices {
+    List(Vec<Value>),
+    Labeled(serde_yaml::Mapping),
+}
+
+impl Choices {
+    fn values(&self) -> Vec<Value> {
+        match self {
+            Choices::List(values) => values.clone(),
+            Choices::Labeled(mapping) => mapping
+                .values()
+                .filter_map(|v| serde_json::to_value(v).ok())
+                .collect(),
+        }
+    }
+}
+
+/// A parsed copier.yml
+#[derive(Debug)]
+pub struct CopierTemplate {
+    questions: Vec<(String, Question)>,
+    subdirectory: PathBuf,
+    exclude: GlobSet,
+    templates_suffix: String,
+}
+
+impl CopierTemplate {
+    pub fn parse(content: &[u8]) -> Result<Self> {
+        let config: serde_yaml::Mapping =
+            serde_yaml::from_slice(content).context("Failed to parse copier.yml")?;
+
+        let mut questions = Vec::new();
+        let mut subdirectory = PathBuf::new();
+        let mut exclude: Vec<String> = DEFAULT_EXCLUDE.iter().map(|p| p.to_string()).collect();
+        let mut templates_suffix = DEFAULT_TEMPLATES_SUFFIX.to_owned();
+        for (key, value) in config {
+            let key = key.as_str().context("copier.yml keys must be strings")?;
+            match key {
+                "_subdirectory" => {
+                    subdirectory =
+                        serde_yaml::from_value(value).context("_subdirectory must be a string")?;
+                }
+                "_exclude" => {
+                    exclude = serde_yaml::from_value(value)
+                        .context("_exclude must be a list of patterns")?;
+                }
+                "_templates_suffix" => {
+                    templates_suffix = serde_yaml::from_value(value)
+                        .context("_templates_suffix must be a string")?;
+                }
+                // other settings like _tasks or _migrations are not supported
+                key if key.starts_with('_') => {}
+                key => {
+                    let question = serde_yaml::from_value(value)
+                        .with_context(|| format!("invalid question '{}'", key))?;
+                    questions.push((key.to_owned(), question));
+                }
+            }
+        }
+
+        Ok(Self {
+            questions,
+            subdirectory,
+            exclude: glob::gitignore_set(&exclude)?,
+            templates_suffix,
+        })
+    }
+
+    /// Declared questions with the answers they accept, empty if any answer is accepted
+    pub fn parameter_choices(&self) -> Vec<(String, Vec<String>)> {
+        self.questions
+            .iter()
+            .map(|(name, question)| {
+                let choices = match question {
+                    Question::Full(QuestionSpec {
+                        choices: Some(choices),
+                        ..
+                    }) => choices.values().iter().map(complete::value_text).collect(),
+                    Question::Full(QuestionSpec {
+                        kind: Some(kind), ..
+                    }) if kind == "bool" => complete::booleans(),
+                    Question::Default(Value::Bool(_)) => complete::booleans(),
+                    _ => Vec::new(),
+                };
+                (name.clone(), choices)
+            })
+            .collect()
+    }
+
+    /// Answer the questions from the parameters or, if not given, with the default. Defaults can
+    /// be templates referencing earlier answers. Strings (e.g. from --set) are converted to the
+    /// type of the question.
+    pub fn answer(&self, params: &mut Map<String, Value>) -> Result<()> {
+        let env = environment(&TemplateConfig {
+            syntax: SyntaxMode::Jinja,
+            root_value: None,
+            ..Default::default()
+        });
+
+        for (name, question) in &self.questions {
+            let spec = match question {
+                Question::Full(spec) => spec,
+                Question::Default(default) => {
+                    if !params.contains_key(name) {
+                        let default = render_default(&env, params, default)?;
+                        params.insert(name.clone(), default);
+                    }
+                    continue;
+                }
+            };
+
+            let asked = match &spec.when {
+                None => true,
+                Some(when) => is_true(&render_default(&env, params, when)?),
+            };
+
+            let value = match params.remove(name) {
+                Some(value) => value,
+                None => match &spec.default {
+                    Some(default) => render_default(&env, params, default)?,
+                    None if asked => anyhow::bail!("missing answer for question '{}'", name),
+                    None => continue,
+                },
+            };
+            let value = match &spec.kind {
+                Some(kind) => convert(name, kind, value)?,
+                None => value,
+            };
+            if asked
+                && let Some(choices) = &spec.choices
+                && !choices.values().contains(&value)
+            {
+                anyhow::bail!(
+                    "answer {} for question '{}' must be one of {}",
+                    value,
+                    name,
+                    Value::Array(choices.values())
+                );
+            }
+            params.insert(name.clone(), value);
+        }
+        Ok(())
+    }
+
+    /// Apply subdirectory, excludes and templates suffix to the template files. Returns the
+    /// files to write and the paths of those which must be copied without rendering.
+    pub fn prepare_files(&self, files: Vec<TemplateFile>) -> (Vec<TemplateFile>, HashSet<PathBuf>) {
+        let mut prepared = Vec::with_capacity(files.len());
+        let mut verbatim = HashSet::new();
+        for mut file in files {
+            let Ok(path) = file.path.strip_prefix(&self.subdirectory) else {
+                report::skip(
+                    file.path,
+                    SkipReason::OutsideTemplatePath {
+                        template_path: self.subdirectory.clone(),
+                    },
+                );
+                continue;
+            };
+            if self.exclude.is_match(path) {
+                let reason = if CONFIG_FILES.iter().any(|c| path == Path::new(c)) {
+                    SkipReason::Config
+                } else {
+                    SkipReason::Ignored {
+                        by: "copier _exclude".to_owned(),
+                    }
+                };
+                report::skip(path, reason);
+                continue;
+            }
+            let mut path = path.to_path_buf();
+
+            if !self.templates_suffix.is_empty() {
+                match path
+                    .to_str()
+                    .and_then(|p| p.strip_suffix(self.templates_suffix.as_str()))
+                {
+                    Some(stripped) => path = PathBuf::from(stripped),
+                    None => {
+                        verbatim.insert(path.clone());
+                    }
+                }
+            }
+            file.path = path;
+            prepared.push(file);
+        }
+        (prepared, verbatim)
+    }
+}
+
+/// Find and parse the copier configuration of the template files, if there is one. It is
+/// removed from the output by the default excludes.
+pub fn split_template(files: &[TemplateFile]) -> Result<Option<CopierTemplate>> {
+    match files
+        .iter()
+        .find(|f| CONFIG_FILES.iter().any(|c| f.path == Path::new(c)))
+    {
+        Some(file) => Ok(Some(CopierTemplate::parse(&file.content)?)),
+        None => Ok(None),
+    }
+}
+
+/// Render string defaults with the answers given so far
+fn render_default(
+    env: &Environment,
+    answers: &Map<String, Value>,
+    default: &Value,
+) -> Result<Value> {
+    match default {
+        Value::String(template) => Ok(Value::String(
+            env.render_str(template, answers)
+                .with_context(|| format!("Failed to render default '{}'", template))?,
+        )),
+        value => Ok(value.clone()),
+    }
+}
+
+/// Evaluate a rendered `when` condition
+fn is_true(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::String(s) => !matches!(
+            s.trim().to_lowercase().as_str(),
+            "" | "false" | "no" | "n" | "off" | "0"
+        ),
+        Value::Null => false,
+        _ => true,
+    }
+}
+
+/// Convert an answer to the type of its question
+fn convert(name: &str, kind: &str, value: Value) -> Result<Value> {
+    let Value::String(s) = value else {
+        return Ok(value);
+    };
+    let converted =
+        match kind {
+            "str" => Value::String(s),
+            "bool" => Value::Bool(is_true(&Value::String(s))),
+            "int" => Value::from(s.trim().parse::<i64>().with_context(|| {
+                format!("answer '{}' for question '{}' is not an int", s, name)
+            })?),
+            "float" => Value::from(s.trim().parse::<f64>().with_context(|| {
+                format!("answer '{}' for question '{}' is not a float", s, name)
+            })?),
+            "json" => serde_json::from_str(&s)
+                .with_context(|| format!("answer for question '{}' is not valid JSON", name))?,
+            "yaml" => serde_yaml::from_str(&s)
+                .with_context(|| format!("answer for question '{}' is not valid YAML", name))?,
+            _ => Value::String(s),
+        };
+    Ok(converted)
+}
@@ -0,0 +1,87 @@
+//! Deduplication of identical rendered files. Files with the same content as a file written
+//! before are linked to it instead of written again, which saves a lot of disk space when many
+//! similar projects are generated.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+/// How duplicates are linked to the first file with the same content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LinkMode {
+    /// Hardlink duplicates. Changing one of the files changes all of them.
+    Hardlink,
+    /// Copy-on-write clone (reflink) of the first file, falls back to a regular copy on
+    /// filesystems without reflink support
+    Reflink,
+}
+
+/// Remembers the written files by content hash. A single instance can be shared by several
+/// destinations (which must be on the same filesystem to be linked).
+#[derive(Debug)]
+pub struct Dedup {
+    mode: LinkMode,
+    written: Mutex<HashMap<[u8; 32], PathBuf>>,
+}
+
+impl Dedup {
+    pub fn new(mode: LinkMode) -> Self {
+        Self {
+            mode,
+            written: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Link `path` to an earlier file with the same content and mode (hardlinks share the mode).
+    /// Returns false if there is none, in which case `path` is remembered and must be written by
+    /// the caller.
+    ///
+    /// An existing file at `path` is removed in any case, as it might be linked to other files
+    /// which must not change when `path` is written.
+    pub fn link_duplicate(&self, path: &Path, content: &[u8], mode: Option<u32>) -> Result<bool> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(e)
+                    .with_context(|| format!("Failed to remove file: {}", path.display()));
+            }
+            _ => {}
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(content);
+        if let Some(mode) = mode {
+            hasher.update(mode.to_le_bytes());
+        }
+        let hash: [u8; 32] = hasher.finalize().into();
+        let mut written = self.written.lock().expect("dedup index is not poisoned");
+        let Some(original) = written.get(&hash) else {
+            written.insert(hash, path.to_path_buf());
+            return Ok(false);
+        };
+
+        let result = match self.mode {
+            LinkMode::Hardlink => fs::hard_link(original, path),
+            LinkMode::Reflink => reflink_copy::reflink_or_copy(original, path).map(|_| ()),
+        };
+        // destinations on different filesystems can not share files
+        if let Err(e) = &result
+            && e.kind() == ErrorKind::CrossesDevices
+        {
+            return Ok(false);
+        }
+        result.with_context(|| {
+            format!(
+                "Failed to link {} to {}",
+                path.display(),
+                original.display()
+            )
+        })?;
+        Ok(true)
+    }
+}
@@ -1,17 +1,148 @@
+use std::collections::HashSet;
 use std::fs;
-use std::path::{Component, Path};
+use std::io::IsTerminal;
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
 use walkdir::WalkDir;
 
-use crate::template::TemplateFile;
+use crate::dedup::Dedup;
+use crate::fsync;
+use crate::git;
+use crate::prompt;
+use crate::report::{self, SkipReason};
+use crate::rollback::Rollback;
+use crate::template::{FileKind, Symlinks, TemplateFile};
+use crate::warnings::{self, WarningKind};
+
+/// Options for writing rendered files to a directory
+#[derive(Debug, Default)]
+pub struct WriteOptions {
+    /// Write into an already existing destination
+    pub force: bool,
+    /// Sync files and directories to disk and replace files via rename (for network filesystems)
+    pub sync: bool,
+    /// Link files with the same content as an already written file instead of writing them
+    pub dedup: Option<Dedup>,
+    /// What to do with files which exist with other content, without a strategy they are
+    /// overwritten with a warning
+    pub on_conflict: Option<ConflictStrategy>,
+    /// Keep the files written so far if rendering fails instead of rolling the destination back
+    pub keep_partial: bool,
+    /// Overwrite files with uncommitted changes if the destination is in a git worktree
+    pub allow_dirty: bool,
+}
+
+/// What to do with a rendered file which exists with other content in the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Keep the existing file
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and write the rendered one next to it as FILE.new
+    Backup,
+    /// Ask for every file
+    Prompt,
+}
+
+/// Suffix of rendered files written next to an existing file (--on-conflict backup)
+pub const BACKUP_SUFFIX: &str = ".new";
+
+/// Resolves conflicts with existing files and remembers what was done for the summary
+struct Conflicts {
+    strategy: ConflictStrategy,
+    overwritten: Vec<PathBuf>,
+    skipped: Vec<PathBuf>,
+    backups: Vec<PathBuf>,
+}
+
+impl Conflicts {
+    fn new(strategy: ConflictStrategy) -> Self {
+        Self {
+            strategy,
+            overwritten: Vec::new(),
+            skipped: Vec::new(),
+            backups: Vec::new(),
+        }
+    }
+
+    /// The file to write instead of the rendered one, None if nothing is written
+    fn resolve(&mut self, dest: &Path, mut file: TemplateFile) -> Result<Option<TemplateFile>> {
+        let existing = destination_path(dest, &file.path)?;
+        if !existing.is_file() || fs::read(&existing).is_ok_and(|c| c == file.content) {
+            return Ok(Some(file));
+        }
+        let mut strategy = self.strategy;
+        if strategy == ConflictStrategy::Prompt {
+            let (answer, all) = prompt::ask_conflict(
+                &file.path,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stderr(),
+            )?;
+            if all {
+                self.strategy = answer;
+            }
+            strategy = answer;
+        }
+        match strategy {
+            ConflictStrategy::Skip | ConflictStrategy::Prompt => {
+                report::skip(&file.path, SkipReason::Existing);
+                self.skipped.push(file.path);
+                return Ok(None);
+            }
+            ConflictStrategy::Overwrite => self.overwritten.push(file.path.clone()),
+            ConflictStrategy::Backup => {
+                let mut name = file.path.clone().into_os_string();
+                name.push(BACKUP_SUFFIX);
+                self.backups.push(file.path);
+                file.path = name.into();
+            }
+        }
+        Ok(Some(file))
+    }
+
+    fn print_summary(&self) {
+        let lines = [
+            ("existing files overwritten", &self.overwritten),
+            ("existing files kept", &self.skipped),
+            ("existing files kept, rendered as .new", &self.backups),
+        ];
+        for (what, paths) in lines {
+            if !paths.is_empty() {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                tracing::info!("{} {}: {}", paths.len(), what, paths.join(", "));
+            }
+        }
+    }
+}
 
 pub fn read_dir_iter(dir: &Path) -> impl Iterator<Item = Result<TemplateFile>> + use<> {
     let base = dir.to_path_buf();
+    let root = base.clone();
     WalkDir::new(dir)
         .into_iter()
-        .filter_entry(|e| e.file_name() != ".git")
-        .filter(|entry| entry.as_ref().map_or(true, |e| !e.file_type().is_dir()))
+        .filter_entry(move |e| {
+            let git = e.file_name() == ".git";
+            if git && let Ok(path) = e.path().strip_prefix(&root) {
+                report::skip(
+                    path,
+                    SkipReason::Ignored {
+                        by: ".git".to_owned(),
+                    },
+                );
+            }
+            !git
+        })
+        // directories are created for their files, only empty ones are entries of their own
+        .filter(|entry| {
+            entry.as_ref().map_or(true, |e| {
+                !e.file_type().is_dir() || (e.depth() > 0 && is_empty_dir(e.path()))
+            })
+        })
         .map(move |entry| {
             let entry = entry?;
             let path = entry.path();
@@ -21,54 +152,218 @@ pub fn read_dir_iter(dir: &Path) -> impl Iterator<Item = Result<TemplateFile>> +
                     format!("path {} not under base {}", path.display(), base.display())
                 })?
                 .to_path_buf();
+            if entry.file_type().is_dir() {
+                return Ok(TemplateFile {
+                    path: relative_path,
+                    content: Vec::new(),
+                    kind: FileKind::Dir,
+                    mode: None,
+                });
+            }
+            if entry.path_is_symlink() {
+                let target = fs::read_link(path)
+                    .with_context(|| format!("Failed to read link {}", path.display()))?;
+                let target = target.into_os_string().into_string().map_err(|target| {
+                    anyhow::anyhow!(
+                        "the target '{}' of the symlink '{}' is not UTF8",
+                        target.display(),
+                        path.display()
+                    )
+                })?;
+                return Ok(TemplateFile {
+                    path: relative_path,
+                    content: target.into_bytes(),
+                    kind: FileKind::Symlink,
+                    mode: None,
+                });
+            }
             let content =
                 fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let metadata = entry
+                .metadata()
+                .with_context(|| format!("Failed to read {}", path.display()))?;
             Ok(TemplateFile {
                 path: relative_path,
                 content,
+                kind: FileKind::File,
+                mode: file_mode(&metadata),
             })
         })
 }
 
+/// Permissions of a file, there are none to preserve on other platforms than unix
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Set the permissions of a written file, files without a mode keep the default (umask)
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let Some(mode) = mode else {
+        return Ok(());
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set the mode of {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> Result<()> {
+    Ok(())
+}
+
+/// Create a symlink, replacing an existing file. The target is checked before it is written.
+fn write_symlink(path: &Path, target: &[u8]) -> Result<()> {
+    let target = Path::new(std::str::from_utf8(target).context("symlink target is not UTF8")?);
+    if path.symlink_metadata().is_ok_and(|m| !m.is_dir()) {
+        fs::remove_file(path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    }
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, path)
+        .with_context(|| format!("Failed to create symlink {}", path.display()));
+    #[cfg(not(unix))]
+    anyhow::bail!(
+        "Failed to create symlink {} to {}: symlinks are only supported on unix",
+        path.display(),
+        target.display()
+    )
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
 pub fn write_to_directory(
     dest: &Path,
     files: impl Iterator<Item = Result<TemplateFile>>,
-    force: bool,
+    options: &WriteOptions,
 ) -> Result<()> {
-    if dest.exists() && !force {
+    if dest.exists() && !options.force && options.on_conflict.is_none() {
         anyhow::bail!(
             "Destination '{}' already exists. Use --force to overwrite.",
             dest.display()
         );
     }
 
-    fs::create_dir_all(dest)
-        .with_context(|| format!("Failed to create destination directory: {}", dest.display()))?;
+    if options.on_conflict == Some(ConflictStrategy::Prompt) && !std::io::stdin().is_terminal() {
+        anyhow::bail!("--on-conflict prompt requires a terminal");
+    }
+
+    // local work which is not committed must not be lost silently
+    let dirty = match options.allow_dirty {
+        true => None,
+        false => git::dirty_paths(dest)?,
+    };
+
+    let mut rollback = (!options.keep_partial).then(|| Rollback::new(dest));
+    let result = write_files(dest, files, options, dirty.as_ref(), rollback.as_mut());
+    if let (Err(_), Some(rollback)) = (&result, rollback) {
+        match rollback.restore() {
+            Ok(false) => {}
+            Ok(true) => tracing::warn!(
+                "rendering failed, rolled back {} (--keep-partial keeps the files written so far)",
+                dest.display()
+            ),
+            Err(e) => tracing::error!("Failed to roll back {}: {:#}", dest.display(), e),
+        }
+    }
+    result
+}
+
+fn write_files(
+    dest: &Path,
+    files: impl Iterator<Item = Result<TemplateFile>>,
+    options: &WriteOptions,
+    dirty: Option<&HashSet<PathBuf>>,
+    mut rollback: Option<&mut Rollback>,
+) -> Result<()> {
+    if options.sync {
+        fsync::create_dir_all_synced(dest)?;
+    } else {
+        fs::create_dir_all(dest).with_context(|| {
+            format!("Failed to create destination directory: {}", dest.display())
+        })?;
+    }
 
+    let mut conflicts = options.on_conflict.map(Conflicts::new);
+    let mut symlinks = Symlinks::default();
     for file in files {
         let file = file?;
-        write_file(dest, &file)?;
+        symlinks.check(&file)?;
+        if file.kind != FileKind::File {
+            let path = destination_path(dest, &file.path)?;
+            if let Some(rollback) = rollback.as_deref_mut() {
+                match file.kind {
+                    FileKind::Dir => rollback.record_dir(&path),
+                    _ => rollback.record(&path)?,
+                }
+            }
+            write_file(dest, &file, options)?;
+            continue;
+        }
+        let file = match &mut conflicts {
+            Some(conflicts) => conflicts.resolve(dest, file)?,
+            None => Some(file),
+        };
+        if let Some(file) = file {
+            if let Some(dirty) = dirty {
+                check_clean(dest, &file, dirty)?;
+            }
+            if let Some(rollback) = rollback.as_deref_mut() {
+                rollback.record(&destination_path(dest, &file.path)?)?;
+            }
+            write_file(dest, &file, options)?;
+        }
+    }
+    if let Some(conflicts) = &conflicts {
+        conflicts.print_summary();
+    }
+    Ok(())
+}
+
+/// Fail if the file would overwrite uncommitted changes with other content
+fn check_clean(dest: &Path, file: &TemplateFile, dirty: &HashSet<PathBuf>) -> Result<()> {
+    let path = destination_path(dest, &file.path)?;
+    let Ok(relative) = path.strip_prefix(dest) else {
+        return Ok(());
+    };
+    if dirty.contains(relative) && fs::read(&path).is_ok_and(|content| content != file.content) {
+        anyhow::bail!(
+            "'{}' has uncommitted changes, commit or stash them first or use --allow-dirty",
+            path.display()
+        );
     }
     Ok(())
 }
 
-pub fn write_file(dest: &Path, file: &TemplateFile) -> Result<()> {
+/// Path of a file in the destination. Paths leaving the destination are rejected.
+fn destination_path(dest: &Path, path: &Path) -> Result<PathBuf> {
     let mut file_dst = dest.to_path_buf();
-    {
-        for part in file.path.components() {
-            // Code adapted from https://github.com/alexcrichton/tar-rs/blob/d0261f1f6cc959ba0758e7236b3fd81e90dd1dc6/src/entry.rs#L382
-            match part {
-                Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
-                Component::ParentDir => {
-                    return Err(anyhow::anyhow!(
-                        "invalid path '{}' containing ..",
-                        file.path.display()
-                    ));
-                }
-                Component::Normal(part) => file_dst.push(part),
+    for part in path.components() {
+        // Code adapted from https://github.com/alexcrichton/tar-rs/blob/d0261f1/src/entry.rs#L382
+        match part {
+            Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => {
+                return Err(anyhow::anyhow!(
+                    "invalid path '{}' containing ..",
+                    path.display()
+                ));
             }
+            Component::Normal(part) => file_dst.push(part),
         }
     }
+    Ok(file_dst)
+}
+
+pub fn write_file(dest: &Path, file: &TemplateFile, options: &WriteOptions) -> Result<()> {
+    let file_dst = destination_path(dest, &file.path)?;
 
     // Skip cases where only slashes or '.' parts were seen, because
     // this is effectively an empty filename.
@@ -76,16 +371,53 @@ pub fn write_file(dest: &Path, file: &TemplateFile) -> Result<()> {
         return Ok(());
     }
 
+    if file.kind == FileKind::Dir {
+        return match options.sync {
+            true => fsync::create_dir_all_synced(&file_dst),
+            false => fs::create_dir_all(&file_dst)
+                .with_context(|| format!("Failed to create directory: {}", file_dst.display())),
+        };
+    }
+
     // Skip entries without a parent (i.e. outside of FS root)
     let parent = match file_dst.parent() {
         Some(p) => p,
         None => return Err(anyhow::anyhow!("invalid path '{}'", file.path.display())),
     };
 
-    fs::create_dir_all(parent)
-        .with_context(|| format!("Failed to create parent directory: {}", parent.display()))?;
-    fs::write(&file_dst, &file.content)
-        .with_context(|| format!("failed to write file: {}", file_dst.display()))?;
+    if file_dst.exists() {
+        warnings::emit(
+            WarningKind::OverwrittenFile,
+            format!("overwriting existing file '{}'", file_dst.display()),
+        )?;
+    }
 
-    Ok(())
+    if options.sync {
+        fsync::create_dir_all_synced(parent)?;
+    } else {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create parent directory: {}", parent.display()))?;
+    }
+
+    if file.kind == FileKind::Symlink {
+        return write_symlink(&file_dst, &file.content);
+    }
+
+    if let Some(dedup) = &options.dedup
+        && dedup.link_duplicate(&file_dst, &file.content, file.mode)?
+    {
+        return if options.sync {
+            fsync::sync_dir(parent)
+        } else {
+            Ok(())
+        };
+    }
+
+    if options.sync {
+        fsync::write_synced(&file_dst, &file.content)?;
+    } else {
+        fs::write(&file_dst, &file.content)
+            .with_context(|| format!("failed to write file: {}", file_dst.display()))?;
+    }
+    set_mode(&file_dst, file.mode)
 }
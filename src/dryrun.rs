@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use similar::{ChangeTag, TextDiff};
+
+use crate::format::OutputFormat;
+use crate::secrets;
+use crate::source::FileIter;
+use crate::tar::TarFileIter;
+use crate::template::TemplateFile;
+use crate::zip::ZipFileIter;
+
+/// What a render without writing prints
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preview {
+    /// Size and changed lines per file (--dry-run)
+    Stats,
+    /// Unified diff against the destination (`rte diff`)
+    Diff,
+    /// Paths of the rendered files (`rte ls`)
+    List,
+    /// Only the errors (`rte validate`)
+    Validate,
+}
+
+/// What rendering a file would do to the destination
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// File does not exist in the destination yet
+    New,
+    /// File exists with identical content
+    Unchanged,
+    /// File exists with different content
+    Modified { added: usize, removed: usize },
+    /// File exists with different content which is not valid UTF8
+    ModifiedBinary,
+}
+
+/// Rendered size and change summary of a single file
+#[derive(Debug)]
+pub struct FileStat {
+    pub path: PathBuf,
+    pub size: usize,
+    pub change: Change,
+    /// Unified diff against the destination, if asked for and the file changes
+    pub diff: Option<String>,
+}
+
+/// What a dry run found: the files which would be written and the errors of the files which
+/// failed to render
+#[derive(Debug, Default)]
+pub struct DryRun {
+    pub files: Vec<FileStat>,
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Existing destination content to compare the rendered files against
+enum Existing {
+    None,
+    Directory(PathBuf),
+    Archive(HashMap<PathBuf, Vec<u8>>),
+}
+
+impl Existing {
+    fn open(dest: &Path, format: OutputFormat) -> Result<Self> {
+        if !dest.exists() {
+            return Ok(Existing::None);
+        }
+        let open = || {
+            File::open(dest).with_context(|| format!("Failed to open archive: {}", dest.display()))
+        };
+        let files: FileIter = match format {
+            OutputFormat::Dir => return Ok(Existing::Directory(dest.to_path_buf())),
+            OutputFormat::TarGz => Box::new(TarFileIter::new(GzDecoder::new(open()?))?),
+            OutputFormat::Zip => Box::new(ZipFileIter::new(open()?)?),
+        };
+        let mut archive = HashMap::new();
+        for file in files {
+            let file = file?;
+            archive.insert(file.path, file.content);
+        }
+        Ok(Existing::Archive(archive))
+    }
+
+    fn get(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        match self {
+            Existing::None => Ok(None),
+            Existing::Archive(files) => Ok(files.get(path).cloned()),
+            Existing::Directory(dir) => {
+                let file_path = dir.join(path);
+                if !file_path.is_file() {
+                    return Ok(None);
+                }
+                let content = fs::read(&file_path)
+                    .with_context(|| format!("Failed to read {}", file_path.display()))?;
+                Ok(Some(content))
+            }
+        }
+    }
+}
+
+/// Compare the rendered files with the destination. Rendering continues after a file failed, so
+/// that all errors are found at once. With `with_diff` the changes are kept as unified diffs.
+pub fn collect_stats(
+    dest: &Path,
+    format: OutputFormat,
+    files: impl Iterator<Item = Result<TemplateFile>>,
+    with_diff: bool,
+) -> Result<DryRun> {
+    let existing = Existing::open(dest, format)?;
+
+    let mut dry_run = DryRun::default();
+    for file in files {
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                dry_run.errors.push(e);
+                continue;
+            }
+        };
+        let old = existing.get(&file.path)?;
+        let change = match &old {
+            None => Change::New,
+            Some(old) => diff(old, &file.content),
+        };
+        let diff = match with_diff && change != Change::Unchanged {
+            true => Some(unified_diff(&file.path, old.as_deref(), &file.content)),
+            false => None,
+        };
+        dry_run.files.push(FileStat {
+            path: file.path,
+            size: file.content.len(),
+            change,
+            diff,
+        });
+    }
+    Ok(dry_run)
+}
+
+fn diff(old: &[u8], new: &[u8]) -> Change {
+    if old == new {
+        return Change::Unchanged;
+    }
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return Change::ModifiedBinary;
+    };
+
+    let (mut added, mut removed) = (0, 0);
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    Change::Modified { added, removed }
+}
+
+/// Diff of a file in the destination (none for a new file) and its rendered content, like `git
+/// diff` prints it
+fn unified_diff(path: &Path, old: Option<&[u8]>, new: &[u8]) -> String {
+    let path = path.display();
+    let old_name = match old {
+        Some(_) => format!("a/{}", path),
+        None => "/dev/null".to_owned(),
+    };
+    let new_name = format!("b/{}", path);
+    let (Ok(old), Ok(new)) = (
+        std::str::from_utf8(old.unwrap_or_default()),
+        std::str::from_utf8(new),
+    ) else {
+        return format!("Binary files {} and {} differ\n", old_name, new_name);
+    };
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&old_name, &new_name)
+        .to_string()
+}
+
+/// The diffs of the changed files
+pub fn print_diff(stats: &[FileStat]) {
+    for diff in stats.iter().filter_map(|stat| stat.diff.as_ref()) {
+        print!("{}", secrets::mask(diff));
+    }
+}
+
+/// The sorted paths of the rendered files, one per line
+pub fn print_list(stats: &[FileStat]) {
+    let mut paths: Vec<&Path> = stats.iter().map(|stat| stat.path.as_path()).collect();
+    paths.sort();
+    for path in paths {
+        println!("{}", secrets::mask(&path.display().to_string()));
+    }
+}
+
+pub fn print_stats(stats: &[FileStat]) {
+    let (mut new, mut modified, mut unchanged) = (0, 0, 0);
+    let (mut total_added, mut total_removed, mut total_size) = (0, 0, 0);
+
+    for stat in stats {
+        total_size += stat.size;
+        let (status, lines) = match &stat.change {
+            Change::New => {
+                new += 1;
+                ("new", String::new())
+            }
+            Change::Unchanged => {
+                unchanged += 1;
+                ("unchanged", String::new())
+            }
+            Change::Modified { added, removed } => {
+                modified += 1;
+                total_added += added;
+                total_removed += removed;
+                ("modified", format!(" (+{} -{})", added, removed))
+            }
+            Change::ModifiedBinary => {
+                modified += 1;
+                ("modified", " (binary)".to_owned())
+            }
+        };
+        println!(
+            "{:<9} {:>10} B  {}{}",
+            status,
+            stat.size,
+            secrets::mask(&stat.path.display().to_string()),
+            lines
+        );
+    }
+
+    println!(
+        "{} files, {} B total: {} new, {} modified, {} unchanged (+{} -{} lines)",
+        stats.len(),
+        total_size,
+        new,
+        modified,
+        unchanged,
+        total_added,
+        total_removed
+    );
+}
@@ -0,0 +1,225 @@
+//! Format-aware checks of rendered config files, declared under `formats` in the manifest. A
+//! template producing invalid JSON, YAML or TOML fails with the line of the rendered file, and
+//! JSON and YAML files can be pretty-printed.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use serde::Deserialize;
+
+use crate::glob;
+use crate::template::TemplateFile;
+
+/// Config format of the files matching a gitignore-like pattern
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormatRule {
+    pub path: String,
+    /// Format of the files, by default detected by their extension
+    pub format: Option<ConfigFormat>,
+    /// Replace the files with their pretty-printed content (comments in YAML are lost)
+    #[serde(default)]
+    pub pretty: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Detect the format by the extension of the file
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(ConfigFormat::Json),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn encoder(self) -> &'static dyn Encoder {
+        match self {
+            ConfigFormat::Json => &JsonEncoder,
+            ConfigFormat::Yaml => &YamlEncoder,
+            ConfigFormat::Toml => &TomlEncoder,
+        }
+    }
+}
+
+/// Line (starting at 1) and message of a syntax error
+#[derive(Debug, PartialEq)]
+pub struct SyntaxError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Checks and pretty-prints one config format
+pub trait Encoder: Sync {
+    fn name(&self) -> &'static str;
+
+    fn check(&self, text: &str) -> Result<(), SyntaxError>;
+
+    /// Pretty-print valid content, None if the format can not be pretty-printed
+    fn pretty(&self, text: &str) -> Option<Result<String>>;
+}
+
+struct JsonEncoder;
+
+impl Encoder for JsonEncoder {
+    fn name(&self) -> &'static str {
+        "JSON"
+    }
+
+    fn check(&self, text: &str) -> Result<(), SyntaxError> {
+        serde_json::from_str::<serde::de::IgnoredAny>(text)
+            .map(|_| ())
+            .map_err(|e| SyntaxError {
+                line: Some(e.line()),
+                message: e.to_string(),
+            })
+    }
+
+    fn pretty(&self, text: &str) -> Option<Result<String>> {
+        // a YAML value keeps the order of the keys
+        let pretty = serde_json::from_str::<serde_yaml::Value>(text)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .map(|mut pretty| {
+                pretty.push('\n');
+                pretty
+            })
+            .context("Failed to pretty-print JSON");
+        Some(pretty)
+    }
+}
+
+struct YamlEncoder;
+
+impl Encoder for YamlEncoder {
+    fn name(&self) -> &'static str {
+        "YAML"
+    }
+
+    fn check(&self, text: &str) -> Result<(), SyntaxError> {
+        for document in serde_yaml::Deserializer::from_str(text) {
+            serde_yaml::Value::deserialize(document).map_err(|e| SyntaxError {
+                line: e.location().map(|location| location.line()),
+                message: e.to_string(),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn pretty(&self, text: &str) -> Option<Result<String>> {
+        let documents = serde_yaml::Deserializer::from_str(text)
+            .map(|document| {
+                serde_yaml::Value::deserialize(document)
+                    .and_then(|value| serde_yaml::to_string(&value))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|documents| documents.join("---\n"))
+            .context("Failed to pretty-print YAML");
+        Some(documents)
+    }
+}
+
+struct TomlEncoder;
+
+impl Encoder for TomlEncoder {
+    fn name(&self) -> &'static str {
+        "TOML"
+    }
+
+    fn check(&self, text: &str) -> Result<(), SyntaxError> {
+        toml::from_str::<toml::Table>(text)
+            .map(|_| ())
+            .map_err(|e| SyntaxError {
+                line: e
+                    .span()
+                    .map(|span| text[..span.start].matches('\n').count() + 1),
+                message: e.message().to_owned(),
+            })
+    }
+
+    fn pretty(&self, _text: &str) -> Option<Result<String>> {
+        None
+    }
+}
+
+/// Compiled format rules of a template
+#[derive(Debug)]
+pub struct Formats {
+    rules: Vec<(FormatRule, GlobSet)>,
+}
+
+impl Formats {
+    pub fn new(rules: &[FormatRule]) -> Result<Self> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for rule in rules {
+            if rule.pretty && rule.format == Some(ConfigFormat::Toml) {
+                anyhow::bail!(
+                    "formats of '{}': TOML can not be pretty-printed, only checked",
+                    rule.path
+                );
+            }
+            let set = glob::gitignore_set(std::slice::from_ref(&rule.path))?;
+            compiled.push((rule.clone(), set));
+        }
+        Ok(Self { rules: compiled })
+    }
+
+    /// Check the file with the first rule matching it and pretty-print it if requested
+    pub fn apply(&self, mut file: TemplateFile) -> Result<TemplateFile> {
+        let Some((rule, _)) = self.rules.iter().find(|(_, set)| set.is_match(&file.path)) else {
+            return Ok(file);
+        };
+        let Some(format) = rule.format.or_else(|| ConfigFormat::detect(&file.path)) else {
+            anyhow::bail!(
+                "the format of '{}' is unknown, set format in formats of '{}'",
+                file.path.display(),
+                rule.path
+            );
+        };
+        let encoder = format.encoder();
+        let text = std::str::from_utf8(&file.content)
+            .with_context(|| format!("'{}' is not valid UTF8", file.path.display()))?;
+        if let Err(error) = encoder.check(text) {
+            match error.line {
+                Some(line) => anyhow::bail!(
+                    "'{}' is not valid {}, line {}: {}\n  {} | {}",
+                    file.path.display(),
+                    encoder.name(),
+                    line,
+                    error.message,
+                    line,
+                    text.lines().nth(line.saturating_sub(1)).unwrap_or_default()
+                ),
+                None => anyhow::bail!(
+                    "'{}' is not valid {}: {}",
+                    file.path.display(),
+                    encoder.name(),
+                    error.message
+                ),
+            }
+        }
+        if rule.pretty {
+            match encoder.pretty(text) {
+                Some(pretty) => {
+                    file.content = pretty
+                        .with_context(|| format!("Failed to format '{}'", file.path.display()))?
+                        .into_bytes();
+                }
+                None => anyhow::bail!(
+                    "'{}': {} can not be pretty-printed, only checked",
+                    file.path.display(),
+                    encoder.name()
+                ),
+            }
+        }
+        Ok(file)
+    }
+}
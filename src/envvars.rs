@@ -0,0 +1,29 @@
+//! Environment variables templates read with `env()`, e.g. `{{ env('CI_PROJECT_URL') }}`. Only
+//! variables declared under `env` in the manifest or allowed with --allow-env can be read, so a
+//! template can not copy arbitrary secrets of the environment into the rendered files.
+
+/// Whether a variable matches the names and `PREFIX*` patterns templates can read
+fn is_allowed(allowed: &[String], name: &str) -> bool {
+    allowed
+        .iter()
+        .any(|allowed| match allowed.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => allowed == name,
+        })
+}
+
+/// Value of an allowed variable, the default if it is not set
+pub fn get(allowed: &[String], name: &str, default: Option<&str>) -> Result<String, String> {
+    if !is_allowed(allowed, name) {
+        return Err(format!(
+            "the template is not allowed to read the environment variable '{}', declare it under \
+             env in rte.yaml or use --allow-env {}",
+            name, name
+        ));
+    }
+    match (std::env::var(name), default) {
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(default)) => Ok(default.to_owned()),
+        (Err(_), None) => Err(format!("environment variable '{}' is not set", name)),
+    }
+}
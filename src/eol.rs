@@ -0,0 +1,91 @@
+//! Line endings of the rendered files (--line-endings), so templates authored on Windows do not
+//! produce shell scripts with CRLF and vice versa. The manifest can override them per file:
+//!
+//! ```yaml
+//! line_endings:
+//!   - path: "*.bat"
+//!     endings: crlf
+//! ```
+
+use anyhow::Result;
+use clap::ValueEnum;
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+
+use crate::glob;
+use crate::template::{TemplateFile, is_binary};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+    /// CRLF on Windows, LF elsewhere
+    Native,
+    /// As the template has them
+    #[default]
+    Keep,
+}
+
+/// Line endings of the files matching a gitignore-like pattern
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineEndingRule {
+    pub path: String,
+    pub endings: LineEnding,
+}
+
+pub struct LineEndings {
+    default: LineEnding,
+    rules: Vec<(LineEnding, GlobSet)>,
+}
+
+impl LineEndings {
+    pub fn new(default: LineEnding, rules: &[LineEndingRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let set = glob::gitignore_set(std::slice::from_ref(&rule.path))?;
+                Ok((rule.endings, set))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { default, rules })
+    }
+
+    /// Convert the line endings of a text file with the first rule matching it, or the default
+    pub fn apply(&self, mut file: TemplateFile) -> TemplateFile {
+        let endings = self
+            .rules
+            .iter()
+            .find(|(_, set)| set.is_match(&file.path))
+            .map_or(self.default, |(endings, _)| *endings);
+        let crlf = match endings {
+            LineEnding::Keep => return file,
+            LineEnding::Native => cfg!(windows),
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+        };
+        if is_binary(&file.content) {
+            return file;
+        }
+        file.content = convert(&file.content, crlf);
+        file
+    }
+}
+
+fn convert(content: &[u8], crlf: bool) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        if byte == b'\n' && crlf {
+            converted.push(b'\r');
+        }
+        converted.push(byte);
+    }
+    converted
+}
@@ -0,0 +1,82 @@
+//! Filters backed by external commands, for what the built-in filters can not do, e.g. piping a
+//! value through `jq` or asking an internal naming service. The manifest declares them as command
+//! and arguments (no shell), the value is written to stdin and the filter returns stdout without
+//! the trailing newline:
+//!
+//! ```yaml
+//! exec:
+//!   jq_compact: [jq, -c, .]
+//!   service_name: [naming-service, --kind, service]
+//! ```
+//!
+//! Running commands of a template has to be allowed with --allow-exec, without it the filters
+//! fail when they are used.
+
+use minijinja::{Environment, ErrorKind};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Name of a filter and its command with arguments
+pub type Filter = (String, Vec<String>);
+
+/// Add the filters of the manifest to the environment, they only run if `allowed`
+pub fn add_filters(env: &mut Environment, filters: &[Filter], allowed: bool) {
+    for (name, command) in filters.iter().cloned() {
+        let filter_name = name.clone();
+        env.add_filter(name, move |input: &str| {
+            if !allowed {
+                return Err(minijinja::Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!(
+                        "the filter '{}' runs '{}', allow running commands of the \
+                         template with --allow-exec",
+                        filter_name,
+                        command.join(" ")
+                    ),
+                ));
+            }
+            run(&command, input).map_err(|message| {
+                minijinja::Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("filter '{}': {}", filter_name, message),
+                )
+            })
+        });
+    }
+}
+
+/// Run the command with the input on stdin and return its stdout
+fn run(command: &[String], input: &str) -> Result<String, String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| "the command is empty".to_owned())?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run '{}': {}", program, e))?;
+
+    // write stdin while stdout is read, a large output would block the command otherwise
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_owned();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run '{}': {}", program, e))?;
+    // a command which does not read its input closes the pipe, that is not an error
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(format!(
+            "'{}' failed with {}: {}",
+            command.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("the output of '{}' is not valid UTF8", command.join(" ")))?;
+    Ok(stdout.strip_suffix('\n').unwrap_or(&stdout).to_owned())
+}
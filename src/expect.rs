@@ -0,0 +1,115 @@
+//! Assertions on the content of rendered files, declared under `expect` in the manifest and
+//! checked on every render. They catch regressions of parameter combinations the tests of a
+//! template do not cover, e.g. a missing `edition` in Cargo.toml.
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::glob;
+use crate::message::Messages;
+use crate::template::TemplateFile;
+
+/// What the files matching a gitignore-like pattern have to contain. The texts are templates
+/// rendered with the parameters, like --git-commit-message.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    pub path: String,
+    /// Text the content contains
+    pub contains: Option<String>,
+    /// Text the content must not contain
+    pub not_contains: Option<String>,
+    /// Regular expression matching the content, `^` and `$` match at line boundaries
+    pub matches: Option<String>,
+}
+
+#[derive(Debug)]
+enum Check {
+    Contains(String),
+    NotContains(String),
+    Matches(Regex),
+}
+
+/// Compiled expectations of a template
+#[derive(Debug)]
+pub struct Expectations {
+    rules: Vec<(String, GlobSet, Vec<Check>)>,
+}
+
+impl Expectations {
+    pub fn new(expectations: &[Expectation], messages: &Messages) -> Result<Self> {
+        let mut rules = Vec::with_capacity(expectations.len());
+        for expectation in expectations {
+            let what = format!("expect of '{}'", expectation.path);
+            let mut checks = Vec::new();
+            if let Some(text) = &expectation.contains {
+                checks.push(Check::Contains(messages.render(&what, text)?));
+            }
+            if let Some(text) = &expectation.not_contains {
+                checks.push(Check::NotContains(messages.render(&what, text)?));
+            }
+            if let Some(pattern) = &expectation.matches {
+                let pattern = messages.render(&what, pattern)?;
+                let regex = Regex::new(&format!("(?m){}", pattern))
+                    .with_context(|| format!("Failed to parse {}", what))?;
+                checks.push(Check::Matches(regex));
+            }
+            if checks.is_empty() {
+                anyhow::bail!(
+                    "{} has nothing to check, set contains, not_contains or matches",
+                    what
+                );
+            }
+            let set = glob::gitignore_set(std::slice::from_ref(&expectation.path))?;
+            rules.push((expectation.path.clone(), set, checks));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Fail if the rendered file does not meet the expectations matching it
+    pub fn check(&self, file: &TemplateFile) -> Result<()> {
+        let mut rules = self
+            .rules
+            .iter()
+            .filter(|(_, set, _)| set.is_match(&file.path))
+            .peekable();
+        let Some((pattern, _, _)) = rules.peek() else {
+            return Ok(());
+        };
+        let content = std::str::from_utf8(&file.content).with_context(|| {
+            format!(
+                "'{}' is expected to be text (expect of '{}')",
+                file.path.display(),
+                pattern
+            )
+        })?;
+        for (pattern, _, checks) in rules {
+            for check in checks {
+                let failure = match check {
+                    Check::Contains(text) if !content.contains(text.as_str()) => {
+                        format!("does not contain '{}'", text)
+                    }
+                    Check::NotContains(text) if content.contains(text.as_str()) => {
+                        format!("contains '{}'", text)
+                    }
+                    Check::Matches(regex) if !regex.is_match(content) => {
+                        format!(
+                            "does not match '{}'",
+                            regex.as_str().trim_start_matches("(?m)")
+                        )
+                    }
+                    _ => continue,
+                };
+                anyhow::bail!(
+                    "'{}' {} (expect of '{}')",
+                    file.path.display(),
+                    failure,
+                    pattern
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+/// Name of the feature which lists the features enabled by default
+pub const DEFAULT_FEATURE: &str = "default";
+
+/// Resolve the enabled features like Cargo does: start with the requested features (plus the
+/// default features if enabled) and transitively enable everything they list.
+///
+/// Returns a map of every declared feature to whether it is enabled, so templates can test
+/// disabled features without tripping over undefined values.
+pub fn resolve(
+    declared: &BTreeMap<String, Vec<String>>,
+    requested: &[String],
+    default_features: bool,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut pending: Vec<&str> = requested.iter().map(String::as_str).collect();
+    if default_features && declared.contains_key(DEFAULT_FEATURE) {
+        pending.push(DEFAULT_FEATURE);
+    }
+
+    let mut enabled = std::collections::HashSet::new();
+    while let Some(feature) = pending.pop() {
+        let Some(implied) = declared.get(feature) else {
+            anyhow::bail!("template does not declare feature '{}'", feature);
+        };
+        if enabled.insert(feature) {
+            pending.extend(implied.iter().map(String::as_str));
+        }
+    }
+
+    Ok(declared
+        .keys()
+        .filter(|name| *name != DEFAULT_FEATURE)
+        .map(|name| {
+            (
+                name.clone(),
+                serde_json::Value::Bool(enabled.contains(name.as_str())),
+            )
+        })
+        .collect())
+}
@@ -0,0 +1,93 @@
+//! Advisory file locks, so concurrent rte processes (e.g. CI matrix jobs) sharing a cache or a
+//! destination do not corrupt each other. A lock is released when it is dropped.
+
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+/// A held lock on a lock file
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Lock exclusively, waiting for other processes holding the lock
+    pub fn exclusive(path: &Path) -> Result<Self> {
+        acquire(path, false)
+    }
+
+    /// Lock shared with other readers, waiting for a process holding the lock exclusively
+    pub fn shared(path: &Path) -> Result<Self> {
+        acquire(path, true)
+    }
+}
+
+fn acquire(path: &Path, shared: bool) -> Result<FileLock> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file: {}", path.display()))?;
+
+    let locked = if shared {
+        file.try_lock_shared()
+    } else {
+        file.try_lock()
+    };
+    match locked {
+        Ok(()) => {}
+        Err(std::fs::TryLockError::WouldBlock) => {
+            tracing::info!(
+                "waiting for another rte process to release {}",
+                path.display()
+            );
+            if shared {
+                file.lock_shared()
+            } else {
+                file.lock()
+            }
+            .with_context(|| format!("Failed to lock {}", path.display()))?;
+        }
+        Err(std::fs::TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+        }
+    }
+    Ok(FileLock { _file: file })
+}
+
+/// Lock file of a destination in `lock_dir` (e.g. the cache directory), named after a hash of the
+/// canonical destination path, so the destination itself is not touched. It is kept, removing it
+/// would allow two processes to hold a lock on different files with the same path.
+pub fn destination_lock_path(lock_dir: &Path, destination: &Path) -> PathBuf {
+    let canonical = canonical_path(destination);
+    let hash = Sha256::digest(canonical.as_os_str().as_encoded_bytes());
+    lock_dir
+        .join("locks")
+        .join(format!("{}.lock", hex::encode(hash)))
+}
+
+/// Canonical path of a destination which might not exist yet
+fn canonical_path(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name())
+        && let Ok(parent) = fs::canonicalize(if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        })
+    {
+        return parent.join(name);
+    }
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::eol::LineEnding;
+use crate::template::{Delimiters, Undefined, Whitespace};
+
+/// Name of the fingerprint file in the destination
+pub const FINGERPRINT_FILE: &str = ".rte-fingerprint";
+
+/// Everything the output of a render depends on (--fingerprint). Renders with the same
+/// fingerprint write the same files.
+#[derive(Debug, Serialize)]
+pub struct Inputs<'a> {
+    /// Digest of the files of the source
+    pub source_digest: &'a str,
+    /// Digest of the template files after overlays and the manifest are applied
+    pub files_digest: String,
+    /// Parameters as the templates get them
+    pub parameters: &'a serde_json::Value,
+    pub root_value: Option<&'a str>,
+    pub backstage: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiters: Option<&'a Delimiters>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub handlebars: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undefined: Option<Undefined>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whitespace: Option<Whitespace>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sprig: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_endings: Option<LineEnding>,
+    pub rte_version: &'a str,
+}
+
+impl Inputs<'_> {
+    pub fn fingerprint(&self) -> Result<String> {
+        // the keys of JSON objects are sorted, the serialization is stable
+        let json = serde_json::to_vec(self).context("Failed to serialize fingerprint inputs")?;
+        Ok(format!("sha256:{}", hex::encode(Sha256::digest(json))))
+    }
+}
+
+/// Load the fingerprint of a destination, None if there is none
+pub fn load(dest: &Path) -> Result<Option<String>> {
+    let path = dest.join(FINGERPRINT_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read fingerprint: {}", path.display()))?;
+    Ok(Some(content.trim().to_owned()))
+}
+
+pub fn save(dest: &Path, fingerprint: &str) -> Result<()> {
+    let path = dest.join(FINGERPRINT_FILE);
+    fs::write(&path, format!("{}\n", fingerprint))
+        .with_context(|| format!("Failed to write fingerprint: {}", path.display()))
+}
@@ -0,0 +1,126 @@
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::compression::Compression;
+use crate::source::FileIter;
+use crate::tar::{TarFileIter, is_tar_gz};
+use crate::template::{FileKind, TemplateFile};
+use crate::zip::{ZipFileIter, is_zip};
+
+/// Format of the destination
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Directory
+    Dir,
+    /// Gzip compressed tar archive
+    TarGz,
+    /// Zip archive
+    Zip,
+}
+
+impl OutputFormat {
+    /// Detect the format by the extension of the destination, everything else is a directory
+    pub fn detect(dest: &Path) -> Self {
+        if is_tar_gz(dest) {
+            OutputFormat::TarGz
+        } else if is_zip(dest) {
+            OutputFormat::Zip
+        } else {
+            OutputFormat::Dir
+        }
+    }
+}
+
+/// Number of bytes needed to detect the format: the tar magic "ustar" ends at offset 262
+const HEADER_LEN: usize = 262;
+
+/// Format of a non-directory source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Tar archive, possibly compressed
+    Tar(Compression),
+    Zip,
+    /// A single file which is the whole template
+    File,
+}
+
+impl Format {
+    /// Detect the format by magic bytes, then by HTTP Content-Type (if any) and last by the
+    /// extension of `name`. Everything else is a plain file.
+    pub fn detect(header: &[u8], content_type: Option<&str>, name: &str) -> Self {
+        if let Some(compression) = Compression::from_magic(header) {
+            return Format::Tar(compression);
+        }
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            return Format::Zip;
+        }
+        if header.get(257..262) == Some(b"ustar") {
+            return Format::Tar(Compression::None);
+        }
+
+        // ignore parameters like "; charset=binary"
+        let content_type = content_type.map(|c| c.split(';').next().unwrap_or_default().trim());
+        if let Some(content_type) = content_type {
+            if matches!(
+                content_type,
+                "application/zip" | "application/x-zip-compressed"
+            ) {
+                return Format::Zip;
+            }
+            if let Some(compression) = Compression::from_content_type(content_type) {
+                return Format::Tar(compression);
+            }
+        }
+
+        if name.to_ascii_lowercase().ends_with(".zip") {
+            return Format::Zip;
+        }
+        match Compression::from_extension(name) {
+            Some(compression) => Format::Tar(compression),
+            None => Format::File,
+        }
+    }
+}
+
+/// Open a non-directory source (local file or download) with the iterator matching its format.
+/// `name` is the path or URL path of the source, `content_type` the HTTP Content-Type if known.
+pub fn open_archive(
+    mut reader: impl Read + 'static,
+    name: &str,
+    content_type: Option<&str>,
+) -> Result<FileIter> {
+    // read the header up front instead of peeking, as a single read from the network may return
+    // fewer bytes than we need
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    (&mut reader)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)
+        .with_context(|| format!("Failed to read {}", name))?;
+
+    let format = Format::detect(&header, content_type, name);
+    let reader = Cursor::new(header).chain(reader);
+
+    Ok(match format {
+        Format::Tar(compression) => Box::new(TarFileIter::new(compression.decoder(reader)?)?),
+        Format::Zip => Box::new(ZipFileIter::new(reader)?),
+        Format::File => {
+            let path = Path::new(name)
+                .file_name()
+                .map(PathBuf::from)
+                .with_context(|| format!("'{}' has no file name", name))?;
+            let mut content = Vec::new();
+            { reader }
+                .read_to_end(&mut content)
+                .with_context(|| format!("Failed to read {}", name))?;
+            Box::new(std::iter::once(Ok(TemplateFile {
+                path,
+                content,
+                kind: FileKind::File,
+                mode: None,
+            })))
+        }
+    })
+}
@@ -0,0 +1,124 @@
+//! Durable file writes for destinations on network filesystems (NFS/SMB), where data written with
+//! a plain write can still be in flight when rte exits.
+
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Temporary sibling path used while a file is written
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".rte-tmp");
+    path.with_file_name(name)
+}
+
+/// Write content to a temporary sibling file, fsync it and move it into place.
+pub fn write_synced(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = tmp_path(path);
+    {
+        let mut file = File::create(&tmp)
+            .with_context(|| format!("Failed to create file: {}", tmp.display()))?;
+        file.write_all(content)
+            .with_context(|| format!("Failed to write file: {}", tmp.display()))?;
+        file.sync_all()
+            .with_context(|| format!("Failed to sync file: {}", tmp.display()))?;
+    }
+    persist(&tmp, path)
+}
+
+/// Create the parent directory of an archive destination and return the path to write the
+/// archive to: with `sync` a temporary sibling which is moved into place with [`persist`] once it
+/// is synced.
+pub fn archive_target(dest: &Path, sync: bool) -> Result<PathBuf> {
+    if let Some(parent) = dest.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        if sync {
+            create_dir_all_synced(parent)?;
+        } else {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create parent directory: {}", parent.display())
+            })?;
+        }
+    }
+    Ok(if sync {
+        tmp_path(dest)
+    } else {
+        dest.to_path_buf()
+    })
+}
+
+/// Move a fully written and synced file to its final path and sync the parent directory. If the
+/// filesystem refuses the rename (e.g. across devices), the file is copied instead.
+pub fn persist(tmp: &Path, path: &Path) -> Result<()> {
+    match fs::rename(tmp, path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::CrossesDevices || e.kind() == ErrorKind::Unsupported => {
+            copy_synced(tmp, path)?;
+            fs::remove_file(tmp)
+                .with_context(|| format!("Failed to remove file: {}", tmp.display()))?;
+        }
+        Err(e) => {
+            let _ = fs::remove_file(tmp);
+            return Err(e).with_context(|| {
+                format!("Failed to move {} to {}", tmp.display(), path.display())
+            });
+        }
+    }
+    if let Some(parent) = path.parent() {
+        sync_dir(parent)?;
+    }
+    Ok(())
+}
+
+fn copy_synced(from: &Path, to: &Path) -> Result<()> {
+    fs::copy(from, to)
+        .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+    File::open(to)
+        .and_then(|f| f.sync_all())
+        .with_context(|| format!("Failed to sync file: {}", to.display()))
+}
+
+/// Create a directory and all missing parents and sync every directory an entry was added to.
+pub fn create_dir_all_synced(path: &Path) -> Result<()> {
+    if path.as_os_str().is_empty() || path.is_dir() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        create_dir_all_synced(parent)?;
+    }
+    match fs::create_dir(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists && path.is_dir() => return Ok(()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to create directory: {}", path.display()));
+        }
+    }
+    match path.parent() {
+        Some(parent) => sync_dir(parent),
+        None => Ok(()),
+    }
+}
+
+/// Sync a directory so that new entries in it are durable. Directories can only be synced on
+/// unix, elsewhere this is a no-op.
+pub fn sync_dir(dir: &Path) -> Result<()> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    #[cfg(unix)]
+    {
+        File::open(dir)
+            .and_then(|d| d.sync_all())
+            .with_context(|| format!("Failed to sync directory: {}", dir.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, Response};
+use url::Url;
+
+/// Parsed Google Cloud Storage URL from gs:// scheme
+/// Format: gs://bucket/object
+#[derive(Debug)]
+pub struct GcsSource {
+    pub bucket: String,
+    pub object: String,
+}
+
+impl GcsSource {
+    /// Parse a gs:// URL
+    /// Examples:
+    ///   gs://my-bucket/templates/rust-service.tar.gz
+    pub fn parse(source: &str) -> Result<Self> {
+        let url = Url::parse(source).context("Invalid URL format")?;
+
+        let bucket = url
+            .host_str()
+            .context("URL must contain a bucket")?
+            .to_string();
+
+        let object = urlencoding::decode(url.path().trim_start_matches('/'))
+            .context("Object name must be valid UTF8")?;
+        if object.is_empty() {
+            anyhow::bail!("Object name cannot be empty");
+        }
+
+        Ok(Self {
+            bucket,
+            object: object.into_owned(),
+        })
+    }
+
+    /// Build the JSON API media download URL. `endpoint` overrides the default
+    /// https://storage.googleapis.com (e.g. for a storage emulator).
+    pub fn object_url(&self, endpoint: Option<&str>) -> String {
+        let endpoint = endpoint.unwrap_or("https://storage.googleapis.com");
+        // The object name must be encoded as a single path segment (e.g. "a/b" -> "a%2Fb")
+        format!(
+            "{}/storage/v1/b/{}/o/{}?alt=media",
+            endpoint.trim_end_matches('/'),
+            urlencoding::encode(&self.bucket),
+            urlencoding::encode(&self.object)
+        )
+    }
+}
+
+/// URL the object of a gs:// source is downloaded from. STORAGE_EMULATOR_HOST overrides the API
+/// endpoint.
+pub fn download_url(source: &str) -> Result<String> {
+    let endpoint = std::env::var("STORAGE_EMULATOR_HOST").ok();
+    Ok(GcsSource::parse(source)?.object_url(endpoint.as_deref()))
+}
+
+/// Download a (compressed) tar archive object from Google Cloud Storage.
+/// STORAGE_EMULATOR_HOST overrides the API endpoint.
+pub fn download(client: &Client, source: &str, token: Option<&str>) -> Result<Response> {
+    let object_url = download_url(source)?;
+
+    let mut request = client.get(&object_url);
+
+    if let Some(t) = token {
+        request = request.header("Authorization", format!("Bearer {}", t));
+    }
+
+    let response = request
+        .send()
+        .with_context(|| format!("Failed to fetch object from {}", object_url))?;
+
+    if !response.status().is_success() {
+        anyhow::bail!(
+            "GCS '{}' returned error {}: {}",
+            object_url,
+            response.status(),
+            response.text().unwrap_or_default()
+        );
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,128 @@
+//! Running git: sparse fetches of GitHub sources, the initial commit of a destination
+//! (--git-init) and the uncommitted changes of a destination.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+/// Default message of the initial commit
+pub const DEFAULT_COMMIT_MESSAGE: &str = "Initial commit";
+
+/// Run a git command. A token is masked in the error output.
+pub fn run(command: Command, token: Option<&str>) -> Result<()> {
+    output(command, token).map(|_| ())
+}
+
+/// Run a git command and return its output. A token is masked in the error output.
+fn output(mut command: Command, token: Option<&str>) -> Result<String> {
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        let mut stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        if let Some(token) = token {
+            stderr = stderr.replace(token, "***");
+        }
+        anyhow::bail!("git failed ({}): {}", output.status, stderr);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+fn git(dir: &Path, args: &[&str], token: Option<&str>) -> Result<String> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(dir).args(args);
+    output(command, token)
+}
+
+/// Initialize a git repository in the directory, optionally with the name of the initial branch,
+/// and commit all files in it
+pub fn init_commit(dir: &Path, message: &str, branch: Option<&str>) -> Result<()> {
+    match branch {
+        Some(branch) => git(dir, &["init", "--quiet", "--initial-branch", branch], None)?,
+        None => git(dir, &["init", "--quiet"], None)?,
+    };
+    commit_all(dir, message)?;
+    Ok(())
+}
+
+/// Commit all changes in the working tree of the repository. Returns false if nothing changed.
+pub fn commit_all(dir: &Path, message: &str) -> Result<bool> {
+    git(dir, &["add", "--all"], None)?;
+    if git(dir, &["status", "--porcelain"], None)?.is_empty() {
+        return Ok(false);
+    }
+    git(dir, &["commit", "--quiet", "--message", message], None)
+        .context("Failed to commit, is user.name and user.email configured?")?;
+    Ok(true)
+}
+
+/// Tracked files with uncommitted changes (modified or staged) below the directory, as paths in
+/// the directory. Untracked files are not included. None if the directory is not in a git
+/// worktree (or git is not installed).
+pub fn dirty_paths(dir: &Path) -> Result<Option<HashSet<PathBuf>>> {
+    let Ok(toplevel) = git(dir, &["rev-parse", "--show-toplevel"], None) else {
+        return Ok(None);
+    };
+    let toplevel = PathBuf::from(toplevel);
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+
+    // not `output`, its trimming would cut the status of the first entry
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(&dir)
+        .args([
+            "status",
+            "--porcelain",
+            "-z",
+            "--untracked-files=no",
+            "--",
+            ".",
+        ])
+        .output()
+        .context("Failed to run git")?;
+    if !status.status.success() {
+        anyhow::bail!(
+            "git status failed ({}): {}",
+            status.status,
+            String::from_utf8_lossy(&status.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8_lossy(&status.stdout);
+    let mut entries = stdout.split('\0').filter(|entry| !entry.is_empty());
+    let mut paths = HashSet::new();
+    while let Some(entry) = entries.next() {
+        let Some((state, path)) = entry.split_at_checked(3) else {
+            continue;
+        };
+        // renames and copies are followed by the path they come from
+        if state.starts_with(['R', 'C']) {
+            entries.next();
+        }
+        if let Ok(path) = toplevel.join(path).strip_prefix(&dir) {
+            paths.insert(path.to_path_buf());
+        }
+    }
+    Ok(Some(paths))
+}
+
+/// Clone the latest commit of the default branch of a repository into the directory and check out
+/// a new branch. Returns the name of the default branch.
+pub fn clone_branch(url: &str, dir: &Path, branch: &str, token: Option<&str>) -> Result<String> {
+    let mut clone = Command::new("git");
+    clone.args(["clone", "--quiet", "--depth=1", url]).arg(dir);
+    run(clone, token)?;
+    let base = git(dir, &["symbolic-ref", "--short", "HEAD"], None)?;
+    git(dir, &["checkout", "--quiet", "-b", branch], None)?;
+    Ok(base)
+}
+
+/// Push the current branch of the repository to a URL
+pub fn push(dir: &Path, url: &str, token: Option<&str>) -> Result<()> {
+    git(dir, &["push", "--quiet", url, "HEAD"], token)?;
+    Ok(())
+}
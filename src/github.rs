@@ -1,20 +1,29 @@
-use std::io::Cursor;
+use std::path::Path;
+use std::process::Command;
 
 use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::Deserialize;
 use url::Url;
 
-use crate::tar::{StripComponents, TarFileIter};
+use crate::dir::read_dir_iter;
+use crate::git;
+use crate::publish::Visibility;
+use crate::refs::{GitRef, PAGE_SIZE, RefKind};
 use crate::template::TemplateFile;
 
 /// Parsed GitHub URL from github:// scheme
-/// Format: github://host/owner/repo[@ref]
+/// Format: github://host/owner/repo[@ref][!asset]
 #[derive(Debug)]
 pub struct GitHubSource {
     pub host: String,
+    /// Base URL of the API of the host
+    pub api_url: String,
     pub owner: String,
     pub repo: String,
     pub git_ref: Option<String>,
+    /// Name of a release asset to use instead of the repository archive
+    pub asset: Option<String>,
 }
 
 impl GitHubSource {
@@ -24,6 +33,8 @@ impl GitHubSource {
     ///   github://github.com/owner/repo@main
     ///   github://github.com/owner/repo@v1.0.0
     ///   github://github.example.com/owner/repo@develop
+    ///   github://github.com/owner/repo@v1.2.0!template.tar.gz (asset of release v1.2.0)
+    ///   github://github.com/owner/repo!template.tar.gz (asset of the latest release)
     pub fn parse(source: &str) -> Result<Self> {
         // Replace github:// with https:// for parsing
         let https_url = source
@@ -43,6 +54,12 @@ impl GitHubSource {
             anyhow::bail!("Project path cannot be empty");
         }
 
+        // Split off !asset from the end if present
+        let (path, asset) = match path.rfind('!') {
+            Some(pos) => (&path[..pos], Some(path[pos + 1..].to_string())),
+            None => (path, None),
+        };
+
         // Split off @ref from the end if present
         let (path, git_ref) = match path.rfind('@') {
             Some(pos) => (path[..pos].to_string(), Some(path[pos + 1..].to_string())),
@@ -56,19 +73,39 @@ impl GitHubSource {
         }
 
         Ok(Self {
+            api_url: format!("https://api.{}", host),
             host,
             owner: parts[0].to_string(),
             repo: parts[1].to_string(),
             git_ref,
+            asset,
         })
     }
 
+    /// Build the API URL of the release which contains the asset
+    pub fn release_url(&self) -> String {
+        let base = format!(
+            "{}/repos/{}/{}/releases",
+            self.api_url, self.owner, self.repo
+        );
+        match &self.git_ref {
+            Some(tag) => format!("{}/tags/{}", base, urlencoding::encode(tag)),
+            None => format!("{}/latest", base),
+        }
+    }
+
+    /// Number of leading path components to strip: repository archives have a root folder like
+    /// "owner-repo-sha/", release assets are used as they are.
+    pub fn strip_components(&self) -> usize {
+        if self.asset.is_some() { 0 } else { 1 }
+    }
+
     /// Build the archive API URL
     pub fn archive_url(&self) -> String {
         let git_ref = &self.git_ref;
         let mut url = format!(
-            "https://api.{}/repos/{}/{}/tarball",
-            self.host, self.owner, self.repo
+            "{}/repos/{}/{}/tarball",
+            self.api_url, self.owner, self.repo
         );
 
         if let Some(git_ref) = git_ref {
@@ -76,47 +113,312 @@ impl GitHubSource {
         }
         url
     }
+
+    /// URL to clone the repository with git
+    pub fn clone_url(&self, token: Option<&str>) -> String {
+        match token {
+            Some(t) => format!(
+                "https://x-access-token:{}@{}/{}/{}.git",
+                t, self.host, self.owner, self.repo
+            ),
+            None => format!("https://{}/{}/{}.git", self.host, self.owner, self.repo),
+        }
+    }
+
+    /// Build the API URL of the authenticated user
+    pub fn user_url(&self) -> String {
+        format!("{}/user", self.api_url)
+    }
+
+    /// Build the API URL to create the repository, for the authenticated user or an organization
+    pub fn create_url(&self, user: bool) -> String {
+        if user {
+            format!("{}/user/repos", self.api_url)
+        } else {
+            format!("{}/orgs/{}/repos", self.api_url, self.owner)
+        }
+    }
+
+    /// Build the API URL of the commit a ref (HEAD for the default branch) points to
+    pub fn commit_url(&self, git_ref: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/commits/{}",
+            self.api_url,
+            self.owner,
+            self.repo,
+            urlencoding::encode(git_ref)
+        )
+    }
+
+    /// Build the API URL of a page (starting at 1) of the branches or tags
+    pub fn refs_url(&self, kind: RefKind, page: usize) -> String {
+        let kind = match kind {
+            RefKind::Branch => "branches",
+            RefKind::Tag => "tags",
+        };
+        format!(
+            "{}/repos/{}/{}/{}?per_page={}&page={}",
+            self.api_url, self.owner, self.repo, kind, PAGE_SIZE, page
+        )
+    }
 }
 
-/// Fetch a GitHub repository archive and return an iterator over its files
-pub fn fetch_archive(
-    source: &str,
-    token: Option<&str>,
-) -> Result<impl Iterator<Item = Result<TemplateFile>> + use<>> {
-    let source = GitHubSource::parse(source)?;
-    let archive_url = source.archive_url();
+#[derive(Deserialize)]
+struct Release {
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    /// API URL of the asset, which unlike browser_download_url also works for private repositories
+    url: String,
+}
 
-    let client = reqwest::blocking::Client::builder()
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .build()?;
+#[derive(Deserialize)]
+struct Ref {
+    name: String,
+    commit: RefCommit,
+}
+
+#[derive(Deserialize)]
+struct RefCommit {
+    sha: String,
+}
 
-    let mut request = client.get(&archive_url);
+#[derive(Deserialize)]
+struct Commit {
+    sha: String,
+}
 
+/// Resolve the ref of the source (the default branch if there is none) to a commit SHA
+pub fn resolve_commit(
+    client: &Client,
+    source: &GitHubSource,
+    token: Option<&str>,
+) -> Result<String> {
+    let url = source.commit_url(source.git_ref.as_deref().unwrap_or("HEAD"));
+    let mut request = client.get(&url).header("User-Agent", "rte");
     if let Some(t) = token {
         request = request.header("Authorization", format!("Bearer {}", t));
     }
+    let commit: Commit = serde_json::from_reader(send(request, &url)?)
+        .with_context(|| format!("Failed to parse commit from {}", url))?;
+    Ok(commit.sha)
+}
+
+#[derive(Deserialize)]
+struct User {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct CreatedRepository {
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    html_url: String,
+}
+
+/// Open a pull request of a branch into the base branch. Returns the URL of the pull request.
+pub fn create_pull_request(
+    client: &Client,
+    repository: &GitHubSource,
+    token: &str,
+    branch: &str,
+    base: &str,
+    title: &str,
+    body: &str,
+) -> Result<String> {
+    let url = format!(
+        "{}/repos/{}/{}/pulls",
+        repository.api_url, repository.owner, repository.repo
+    );
+    let body = serde_json::json!({
+        "head": branch,
+        "base": base,
+        "title": title,
+        "body": body,
+    });
+    let request = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", token))
+        .header("User-Agent", "rte")
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    let pull_request: PullRequest = serde_json::from_reader(send(request, &url)?)
+        .with_context(|| format!("Failed to parse pull request from {}", url))?;
+    Ok(pull_request.html_url)
+}
+
+/// Create the repository of the source for the authenticated user or, if the owner is another
+/// account, in the organization. Returns the URL of the repository.
+pub fn create_repository(
+    client: &Client,
+    repository: &GitHubSource,
+    token: &str,
+    visibility: Visibility,
+) -> Result<String> {
+    let request = |request: RequestBuilder| {
+        request
+            .header("Authorization", format!("Bearer {}", token))
+            .header("User-Agent", "rte")
+    };
+
+    let user_url = repository.user_url();
+    let user: User = serde_json::from_reader(send(request(client.get(&user_url)), &user_url)?)
+        .with_context(|| format!("Failed to parse user from {}", user_url))?;
+    let for_user = user.login.eq_ignore_ascii_case(&repository.owner);
+
+    let mut body = serde_json::json!({
+        "name": repository.repo,
+        "private": visibility != Visibility::Public,
+    });
+    // internal repositories only exist in organizations of GitHub Enterprise
+    if !for_user {
+        body["visibility"] = visibility.to_string().into();
+    }
+    let url = repository.create_url(for_user);
+    let create = request(client.post(&url))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    let created: CreatedRepository = serde_json::from_reader(send(create, &url)?)
+        .with_context(|| format!("Failed to parse repository from {}", url))?;
+    Ok(created.html_url)
+}
 
-    // GitHub requires User-Agent header
-    request = request.header("User-Agent", "rte");
+/// List the branches and tags of the repository
+pub fn list_refs(
+    client: &Client,
+    source: &GitHubSource,
+    token: Option<&str>,
+) -> Result<Vec<GitRef>> {
+    let mut refs = Vec::new();
+    for kind in [RefKind::Branch, RefKind::Tag] {
+        for page in 1.. {
+            let url = source.refs_url(kind, page);
+            let mut request = client.get(&url).header("User-Agent", "rte");
+            if let Some(t) = token {
+                request = request.header("Authorization", format!("Bearer {}", t));
+            }
+            let entries: Vec<Ref> = serde_json::from_reader(send(request, &url)?)
+                .with_context(|| format!("Failed to parse refs from {}", url))?;
+            let last_page = entries.len() < PAGE_SIZE;
+            refs.extend(entries.into_iter().map(|r| GitRef {
+                kind,
+                name: r.name,
+                commit: r.commit.sha,
+            }));
+            if last_page {
+                break;
+            }
+        }
+    }
+    Ok(refs)
+}
 
+/// Clone only the given directory of the repository with a shallow, sparse and blobless git fetch
+/// instead of downloading the archive of the whole repository
+pub fn sparse_checkout(
+    source: &GitHubSource,
+    path: &str,
+    token: Option<&str>,
+) -> Result<Vec<TemplateFile>> {
+    sparse_fetch(
+        &source.clone_url(token),
+        source.git_ref.as_deref(),
+        path,
+        token,
+    )
+}
+
+/// Fetch a directory of the commit of a ref (branch, tag or commit SHA, HEAD if None) of a git
+/// repository. The files keep their path within the repository.
+pub fn sparse_fetch(
+    url: &str,
+    git_ref: Option<&str>,
+    path: &str,
+    token: Option<&str>,
+) -> Result<Vec<TemplateFile>> {
+    let dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+    let git = |args: &[&str]| {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(dir.path()).args(args);
+        git::run(command, token)
+    };
+
+    git(&["init", "--quiet"])?;
+    git(&["remote", "add", "origin", url])?;
+    git(&["sparse-checkout", "set", path])?;
+    git(&[
+        "fetch",
+        "--quiet",
+        "--depth=1",
+        "--filter=blob:none",
+        "origin",
+        git_ref.unwrap_or("HEAD"),
+    ])?;
+    git(&["checkout", "--quiet", "FETCH_HEAD"])?;
+
+    // the cone also contains the files of the root, only keep the requested directory
+    read_dir_iter(dir.path())
+        .filter(|file| {
+            file.as_ref()
+                .map_or(true, |file| file.path.starts_with(Path::new(path)))
+        })
+        .collect()
+}
+
+/// Download a GitHub repository archive or release asset (tar.gz)
+pub fn download(client: &Client, source: &GitHubSource, token: Option<&str>) -> Result<Response> {
+    let request = |url: &str| {
+        let mut request = client.get(url);
+        if let Some(t) = token {
+            request = request.header("Authorization", format!("Bearer {}", t));
+        }
+        // GitHub requires User-Agent header
+        request.header("User-Agent", "rte")
+    };
+
+    match &source.asset {
+        Some(asset) => {
+            let release_url = source.release_url();
+            let release: Release =
+                serde_json::from_reader(send(request(&release_url), &release_url)?)
+                    .with_context(|| format!("Failed to parse release from {}", release_url))?;
+            let asset_url = release
+                .assets
+                .into_iter()
+                .find(|a| &a.name == asset)
+                .with_context(|| format!("release {} has no asset '{}'", release_url, asset))?
+                .url;
+            send(
+                request(&asset_url).header("Accept", "application/octet-stream"),
+                &asset_url,
+            )
+        }
+        None => {
+            let archive_url = source.archive_url();
+            send(request(&archive_url), &archive_url)
+        }
+    }
+}
+
+fn send(request: RequestBuilder, url: &str) -> Result<Response> {
     let response = request
         .send()
-        .with_context(|| format!("Failed to fetch archive from {}", archive_url))?;
+        .with_context(|| format!("Failed to fetch {}", url))?;
 
     if !response.status().is_success() {
         anyhow::bail!(
             "GitHub API {} returned error {}: {}",
-            archive_url,
+            url,
             response.status(),
             response.text().unwrap_or_default()
         );
     }
 
-    let bytes = response.bytes().context("Failed to read response body")?;
-
-    let decoder = GzDecoder::new(Cursor::new(bytes));
-    let tar_iter = TarFileIter::new(decoder)?;
-
-    // GitHub archives have a root folder like "owner-repo-sha/"
-    Ok(StripComponents::new(tar_iter, 1))
+    Ok(response)
 }
@@ -1,19 +1,24 @@
-use std::io::Cursor;
-
 use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::Deserialize;
 use url::Url;
 
-use crate::tar::{StripComponents, TarFileIter};
-use crate::template::TemplateFile;
+use crate::publish::Visibility;
+use crate::refs::{GitRef, PAGE_SIZE, RefKind};
 
 /// Parsed GitLab URL from gitlab:// scheme
-/// Format: gitlab://host/group/project[@ref]
+/// Format: gitlab://host/group/project[@ref][!asset][?path=subdir]
 #[derive(Debug)]
 pub struct GitlabSource {
     pub host: String,
+    /// Base URL of the API of the host
+    pub api_url: String,
     pub project_path: String,
     pub git_ref: Option<String>,
+    /// Name of a release asset (link) to use instead of the repository archive
+    pub asset: Option<String>,
+    /// Only download this subdirectory of the repository
+    pub path: Option<String>,
 }
 
 impl GitlabSource {
@@ -22,6 +27,9 @@ impl GitlabSource {
     ///   gitlab://gitlab.com/group/project
     ///   gitlab://gitlab.com/group/subgroup/project@main
     ///   gitlab://gitlab.example.com/group/project@v1.0.0
+    ///   gitlab://gitlab.com/group/project@v1.2.0!template.tar.gz (asset of release v1.2.0)
+    ///   gitlab://gitlab.com/group/project!template.tar.gz (asset of the latest release)
+    ///   gitlab://gitlab.com/group/project@main?path=templates/service (only templates/service)
     pub fn parse(source: &str) -> Result<Self> {
         let url = Url::parse(source).context("Invalid URL format")?;
 
@@ -35,68 +43,351 @@ impl GitlabSource {
             anyhow::bail!("Project path cannot be empty");
         }
 
+        // Split off !asset from the end if present
+        let (path, asset) = match path.rfind('!') {
+            Some(pos) => (&path[..pos], Some(path[pos + 1..].to_string())),
+            None => (path, None),
+        };
+
         // Split off @ref from the end if present
         let (project_path, git_ref) = match path.rfind('@') {
             Some(pos) => (path[..pos].to_string(), Some(path[pos + 1..].to_string())),
             None => (path.to_string(), None),
         };
 
+        let path = url
+            .query_pairs()
+            .find(|(key, _)| key == "path")
+            .map(|(_, value)| value.into_owned());
+        if path.is_some() && asset.is_some() {
+            anyhow::bail!("path can not be used with release assets");
+        }
+
         Ok(Self {
+            api_url: format!("https://{}/api/v4", host),
             host,
             project_path,
             git_ref,
+            asset,
+            path,
         })
     }
 
+    /// Build the API URL of the release which contains the asset
+    pub fn release_url(&self) -> String {
+        let base = format!(
+            "{}/projects/{}/releases",
+            self.api_url,
+            urlencoding::encode(&self.project_path)
+        );
+        match &self.git_ref {
+            Some(tag) => format!("{}/{}", base, urlencoding::encode(tag)),
+            None => format!("{}/permalink/latest", base),
+        }
+    }
+
+    /// Number of leading path components to strip: repository archives have a root folder like
+    /// "project-branch-sha/", release assets are used as they are.
+    pub fn strip_components(&self) -> usize {
+        if self.asset.is_some() { 0 } else { 1 }
+    }
+
     /// Build the archive API URL
     pub fn archive_url(&self) -> String {
         // URL-encode the project path (e.g., "group/project" -> "group%2Fproject")
         let encoded_path = urlencoding::encode(&self.project_path);
         let base = format!(
-            "https://{}/api/v4/projects/{}/repository/archive.tar.gz",
-            self.host, encoded_path
+            "{}/projects/{}/repository/archive.tar.gz",
+            self.api_url, encoded_path
         );
-        match &self.git_ref {
-            Some(r) => format!("{}?sha={}", base, urlencoding::encode(r)),
-            None => base,
+        let mut query = Vec::new();
+        if let Some(r) = &self.git_ref {
+            query.push(format!("sha={}", urlencoding::encode(r)));
         }
+        if let Some(path) = &self.path {
+            query.push(format!("path={}", urlencoding::encode(path)));
+        }
+        if query.is_empty() {
+            base
+        } else {
+            format!("{}?{}", base, query.join("&"))
+        }
+    }
+
+    /// Build the API URL of the project
+    pub fn project_url(&self) -> String {
+        format!(
+            "{}/projects/{}",
+            self.api_url,
+            urlencoding::encode(&self.project_path)
+        )
+    }
+
+    /// Build the API URL of the commit a ref points to
+    pub fn commit_url(&self, git_ref: &str) -> String {
+        format!(
+            "{}/repository/commits/{}",
+            self.project_url(),
+            urlencoding::encode(git_ref)
+        )
+    }
+
+    /// Split the project path into the namespace (group) and the name of the project
+    pub fn namespace(&self) -> Result<(&str, &str)> {
+        self.project_path
+            .rsplit_once('/')
+            .with_context(|| format!("project '{}' has no group", self.project_path))
+    }
+
+    /// Build the API URL of the namespace of the project
+    pub fn namespace_url(&self) -> Result<String> {
+        let (namespace, _) = self.namespace()?;
+        Ok(format!(
+            "{}/namespaces/{}",
+            self.api_url,
+            urlencoding::encode(namespace)
+        ))
+    }
+
+    /// Build the API URL to create projects
+    pub fn projects_url(&self) -> String {
+        format!("{}/projects", self.api_url)
+    }
+
+    /// Build the URL to push to the repository of the project with a token
+    pub fn push_url(&self, token: &str) -> String {
+        format!(
+            "https://oauth2:{}@{}/{}.git",
+            urlencoding::encode(token),
+            self.host,
+            self.project_path
+        )
+    }
+
+    /// Build the API URL of a page (starting at 1) of the branches or tags
+    pub fn refs_url(&self, kind: RefKind, page: usize) -> String {
+        let kind = match kind {
+            RefKind::Branch => "branches",
+            RefKind::Tag => "tags",
+        };
+        format!(
+            "{}/projects/{}/repository/{}?per_page={}&page={}",
+            self.api_url,
+            urlencoding::encode(&self.project_path),
+            kind,
+            PAGE_SIZE,
+            page
+        )
     }
 }
 
-/// Fetch a GitLab repository archive and return an iterator over its files
-pub fn fetch_archive(
-    source: &str,
-    token: Option<&str>,
-) -> Result<impl Iterator<Item = Result<TemplateFile>> + use<>> {
-    let source = GitlabSource::parse(source)?;
+#[derive(Deserialize)]
+struct Release {
+    assets: ReleaseAssets,
+}
 
-    let archive_url = source.archive_url();
+#[derive(Deserialize)]
+struct ReleaseAssets {
+    links: Vec<ReleaseLink>,
+}
 
-    let client = reqwest::blocking::Client::new();
-    let mut request = client.get(&archive_url);
+#[derive(Deserialize)]
+struct ReleaseLink {
+    name: String,
+    url: String,
+    direct_asset_url: Option<String>,
+}
 
-    if let Some(t) = token {
-        request = request.header("PRIVATE-TOKEN", t);
+#[derive(Deserialize)]
+struct Ref {
+    name: String,
+    commit: RefCommit,
+}
+
+#[derive(Deserialize)]
+struct RefCommit {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct Namespace {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct CreatedProject {
+    web_url: String,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    web_url: String,
+}
+
+/// Open a merge request of a branch into the target branch. Returns the URL of the merge request.
+pub fn create_merge_request(
+    client: &Client,
+    project: &GitlabSource,
+    token: &str,
+    branch: &str,
+    target: &str,
+    title: &str,
+    description: &str,
+) -> Result<String> {
+    let url = format!("{}/merge_requests", project.project_url());
+    let request = client.post(&url).header("PRIVATE-TOKEN", token).form(&[
+        ("source_branch", branch),
+        ("target_branch", target),
+        ("title", title),
+        ("description", description),
+    ]);
+    let merge_request: MergeRequest = serde_json::from_reader(send(request, &url)?)
+        .with_context(|| format!("Failed to parse merge request from {}", url))?;
+    Ok(merge_request.web_url)
+}
+
+/// Create the project of the source in its namespace (group), which must exist. Returns the URL
+/// of the project.
+pub fn create_project(
+    client: &Client,
+    project: &GitlabSource,
+    token: &str,
+    visibility: Visibility,
+) -> Result<String> {
+    let namespace_url = project.namespace_url()?;
+    let namespace: Namespace = serde_json::from_reader(send(
+        client.get(&namespace_url).header("PRIVATE-TOKEN", token),
+        &namespace_url,
+    )?)
+    .with_context(|| format!("Failed to parse namespace from {}", namespace_url))?;
+
+    let (_, name) = project.namespace()?;
+    let url = project.projects_url();
+    let namespace_id = namespace.id.to_string();
+    let visibility = visibility.to_string();
+    let request = client.post(&url).header("PRIVATE-TOKEN", token).form(&[
+        ("name", name),
+        ("path", name),
+        ("namespace_id", &namespace_id),
+        ("visibility", &visibility),
+    ]);
+    let created: CreatedProject = serde_json::from_reader(send(request, &url)?)
+        .with_context(|| format!("Failed to parse project from {}", url))?;
+    Ok(created.web_url)
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    id: String,
+}
+
+/// Resolve the ref of the source (the default branch if there is none) to a commit SHA
+pub fn resolve_commit(
+    client: &Client,
+    source: &GitlabSource,
+    token: Option<&str>,
+) -> Result<String> {
+    let request = |url: &str| {
+        let request = client.get(url);
+        match token {
+            Some(t) => request.header("PRIVATE-TOKEN", t),
+            None => request,
+        }
+    };
+
+    let git_ref = match &source.git_ref {
+        Some(git_ref) => git_ref.clone(),
+        None => {
+            let url = source.project_url();
+            let project: Project = serde_json::from_reader(send(request(&url), &url)?)
+                .with_context(|| format!("Failed to parse project from {}", url))?;
+            project.default_branch
+        }
+    };
+    let url = source.commit_url(&git_ref);
+    let commit: Commit = serde_json::from_reader(send(request(&url), &url)?)
+        .with_context(|| format!("Failed to parse commit from {}", url))?;
+    Ok(commit.id)
+}
+
+/// List the branches and tags of the project
+pub fn list_refs(
+    client: &Client,
+    source: &GitlabSource,
+    token: Option<&str>,
+) -> Result<Vec<GitRef>> {
+    let mut refs = Vec::new();
+    for kind in [RefKind::Branch, RefKind::Tag] {
+        for page in 1.. {
+            let url = source.refs_url(kind, page);
+            let mut request = client.get(&url);
+            if let Some(t) = token {
+                request = request.header("PRIVATE-TOKEN", t);
+            }
+            let entries: Vec<Ref> = serde_json::from_reader(send(request, &url)?)
+                .with_context(|| format!("Failed to parse refs from {}", url))?;
+            let last_page = entries.len() < PAGE_SIZE;
+            refs.extend(entries.into_iter().map(|r| GitRef {
+                kind,
+                name: r.name,
+                commit: r.commit.id,
+            }));
+            if last_page {
+                break;
+            }
+        }
     }
+    Ok(refs)
+}
 
+/// Download a GitLab repository archive or release asset (tar.gz)
+pub fn download(client: &Client, source: &GitlabSource, token: Option<&str>) -> Result<Response> {
+    let request = |url: &str| {
+        let request = client.get(url);
+        match token {
+            Some(t) => request.header("PRIVATE-TOKEN", t),
+            None => request,
+        }
+    };
+
+    let archive_url = match &source.asset {
+        Some(asset) => {
+            let release_url = source.release_url();
+            let release: Release =
+                serde_json::from_reader(send(request(&release_url), &release_url)?)
+                    .with_context(|| format!("Failed to parse release from {}", release_url))?;
+            let link = release
+                .assets
+                .links
+                .into_iter()
+                .find(|l| &l.name == asset)
+                .with_context(|| format!("release {} has no asset '{}'", release_url, asset))?;
+            link.direct_asset_url.unwrap_or(link.url)
+        }
+        None => source.archive_url(),
+    };
+
+    send(request(&archive_url), &archive_url)
+}
+
+fn send(request: RequestBuilder, url: &str) -> Result<Response> {
     let response = request
         .send()
-        .with_context(|| format!("Failed to fetch archive from {}", archive_url))?;
+        .with_context(|| format!("Failed to fetch {}", url))?;
 
     if !response.status().is_success() {
         anyhow::bail!(
             "GitLab API '{}' returned error {}: {}",
-            archive_url,
+            url,
             response.status(),
             response.text().unwrap_or_default()
         );
     }
 
-    let bytes = response.bytes().context("Failed to read response body")?;
-
-    let decoder = GzDecoder::new(Cursor::new(bytes));
-    let tar_iter = TarFileIter::new(decoder)?;
-
-    // GitLab archives have a root folder like "project-branch-sha/"
-    Ok(StripComponents::new(tar_iter, 1))
+    Ok(response)
 }
@@ -0,0 +1,25 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Build a set of gitignore-like patterns: patterns without a slash match at any level, a leading
+/// slash anchors the pattern at the root and matching a directory matches everything below it.
+pub fn gitignore_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        let pattern = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_owned(),
+            None if !pattern.contains('/') => format!("**/{}", pattern),
+            None => pattern.to_owned(),
+        };
+        for glob in [pattern.clone(), format!("{}/**", pattern)] {
+            builder.add(
+                GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("invalid pattern '{}'", pattern))?,
+            );
+        }
+    }
+    builder.build().context("invalid patterns")
+}
@@ -0,0 +1,74 @@
+//! Handlebars/Mustache syntax (--syntax handlebars) for templates written for Yeoman- or
+//! hygen-style tooling. Partials (`{{> _partials/header}}`) are resolved with the loader of the
+//! template files, like the includes of Jinja templates.
+
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use handlebars::{Handlebars, RenderErrorReason, handlebars_helper};
+
+use crate::template::{self, Engine, Loader, Undefined};
+
+/// Partials loaded at most for one file, in case they include each other endlessly
+const MAX_PARTIALS: usize = 100;
+
+handlebars_helper!(snake_case: |s: str| template::snake_case(s));
+handlebars_helper!(kebab_case: |s: str| template::kebab_case(s));
+handlebars_helper!(camel_case: |s: str| template::camel_case(s));
+handlebars_helper!(pascal_case: |s: str| template::pascal_case(s));
+handlebars_helper!(screaming_snake_case: |s: str| template::screaming_snake_case(s));
+
+pub struct HandlebarsEngine {
+    /// Partials are registered when a file first uses them
+    registry: Mutex<Handlebars<'static>>,
+    loader: Option<Loader>,
+}
+
+impl HandlebarsEngine {
+    pub fn new(undefined: Undefined) -> Self {
+        let mut registry = Handlebars::new();
+        // Handlebars is strict or renders undefined values as empty
+        registry.set_strict_mode(undefined == Undefined::Strict);
+        // the output is source code and config, not HTML
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_helper("snake_case", Box::new(snake_case));
+        registry.register_helper("kebab_case", Box::new(kebab_case));
+        registry.register_helper("camel_case", Box::new(camel_case));
+        registry.register_helper("pascal_case", Box::new(pascal_case));
+        registry.register_helper("screaming_snake_case", Box::new(screaming_snake_case));
+        Self {
+            registry: Mutex::new(registry),
+            loader: None,
+        }
+    }
+}
+
+impl Engine for HandlebarsEngine {
+    // errors of handlebars name the line and column themselves
+    fn render(&self, _name: &str, source: &str, params: &serde_json::Value) -> Result<String> {
+        let mut registry = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+        for _ in 0..MAX_PARTIALS {
+            let error = match registry.render_template(source, params) {
+                Ok(rendered) => return Ok(rendered),
+                Err(e) => e,
+            };
+            let (RenderErrorReason::PartialNotFound(name), Some(loader)) =
+                (error.reason(), &self.loader)
+            else {
+                return Err(error.into());
+            };
+            let name = name.clone();
+            let Some(partial) = loader(&name).map_err(|e| anyhow::anyhow!("{:#}", e))? else {
+                return Err(error.into());
+            };
+            registry
+                .register_partial(&name, partial)
+                .with_context(|| format!("Failed to parse the partial '{}'", name))?;
+        }
+        anyhow::bail!("more than {} partials", MAX_PARTIALS)
+    }
+
+    fn set_loader(&mut self, loader: Loader) {
+        self.loader = Some(loader);
+    }
+}
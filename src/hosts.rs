@@ -0,0 +1,124 @@
+//! Policy restricting the hosts remote templates may be fetched from, so e.g. only templates of
+//! the company's GitLab can be rendered. It is read from `$XDG_CONFIG_HOME/rte/hosts.yaml` (or
+//! `~/.config/rte/hosts.yaml`) and extended by the comma separated patterns in
+//! `RTE_ALLOWED_HOSTS` and `RTE_DENIED_HOSTS`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{gcs, s3};
+
+/// Hosts remote sources may be fetched from. A host must not match any of the denied patterns
+/// and, if there are allowed patterns, match one of them. `*.example.com` matches the subdomains
+/// of example.com, other patterns match a host exactly.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostPolicy {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// Default location of the policy file: $XDG_CONFIG_HOME/rte/hosts.yaml or
+/// ~/.config/rte/hosts.yaml
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("rte").join("hosts.yaml"))
+}
+
+impl HostPolicy {
+    /// Load the policy from the default file and the environment. Without both every host is
+    /// allowed.
+    pub fn load() -> Result<Self> {
+        let mut policy = match default_path() {
+            Some(path) => Self::load_file(&path)?,
+            None => Self::default(),
+        };
+        policy.extend(
+            std::env::var("RTE_ALLOWED_HOSTS").ok().as_deref(),
+            std::env::var("RTE_DENIED_HOSTS").ok().as_deref(),
+        );
+        Ok(policy)
+    }
+
+    /// Load a policy file. A missing file allows every host.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read host policy: {}", path.display()))?;
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse host policy: {}", path.display()))
+    }
+
+    /// Add comma separated allowed and denied patterns
+    pub fn extend(&mut self, allow: Option<&str>, deny: Option<&str>) {
+        let patterns = |list: Option<&str>| {
+            list.unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        self.allow.extend(patterns(allow));
+        self.deny.extend(patterns(deny));
+    }
+
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        let matches = |pattern: &String| {
+            let pattern = pattern.to_lowercase();
+            match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => pattern == "*" || pattern == host,
+            }
+        };
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+
+    /// Fail if the host is not allowed
+    pub fn check_host(&self, host: &str) -> Result<()> {
+        if !self.is_allowed(host) {
+            anyhow::bail!(
+                "host '{}' is not allowed by the host policy (hosts.yaml, RTE_ALLOWED_HOSTS, \
+                 RTE_DENIED_HOSTS)",
+                host
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail if a gitlab://, github:// or http(s):// source is on a host which is not allowed. For
+    /// s3:// and gs:// sources the host the object is downloaded from is checked, e.g.
+    /// `bucket.s3.eu-west-1.amazonaws.com` or `storage.googleapis.com`. Local sources are not
+    /// restricted.
+    pub fn check_source(&self, source: &str) -> Result<()> {
+        let Ok(url) = Url::parse(source) else {
+            return Ok(());
+        };
+        let url = match url.scheme() {
+            "gitlab" | "github" | "http" | "https" => url,
+            "s3" => Url::parse(&s3::download_url(source)?).context("Invalid object URL")?,
+            "gs" => Url::parse(&gcs::download_url(source)?).context("Invalid object URL")?,
+            _ => return Ok(()),
+        };
+        let host = url
+            .host_str()
+            .with_context(|| format!("source '{}' has no host", source))?;
+        self.check_host(host)
+            .with_context(|| format!("Failed to open source '{}'", source))
+    }
+}
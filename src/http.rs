@@ -0,0 +1,67 @@
+//! HTTP client shared by all remote sources, with a configurable redirect policy.
+
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use reqwest::redirect::Policy;
+
+use crate::hosts::HostPolicy;
+
+/// Redirects followed unless --max-redirects is set
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Which redirects the client follows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RedirectPolicy {
+    /// Maximum number of redirects per request
+    pub max_redirects: usize,
+    /// Fail on redirects to another host. Credentials like GitLab's PRIVATE-TOKEN header are
+    /// sent along with redirected requests.
+    pub same_host: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            same_host: false,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    fn policy(self, hosts: HostPolicy) -> Policy {
+        Policy::custom(move |attempt| {
+            if attempt.previous().len() > self.max_redirects {
+                let max_redirects = self.max_redirects;
+                return attempt.error(format!("more than {} redirects", max_redirects));
+            }
+            if self.same_host {
+                let from = attempt.previous().first().and_then(|url| url.host_str());
+                let to = attempt.url().host_str();
+                if from != to {
+                    let message = format!(
+                        "redirect from {} to another host {} is forbidden",
+                        from.unwrap_or_default(),
+                        to.unwrap_or_default()
+                    );
+                    return attempt.error(message);
+                }
+            }
+            if let Some(host) = attempt.url().host_str()
+                && let Err(e) = hosts.check_host(host)
+            {
+                let message = format!("redirect to {}: {}", attempt.url(), e);
+                return attempt.error(message);
+            }
+            attempt.follow()
+        })
+    }
+}
+
+/// Build a client which follows redirects according to the policy, only to allowed hosts
+pub fn client(redirects: RedirectPolicy, hosts: &HostPolicy) -> Result<Client> {
+    Client::builder()
+        .redirect(redirects.policy(hosts.clone()))
+        .build()
+        .context("Failed to create HTTP client")
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use minijinja::{Error, ErrorKind};
+use url::Url;
+
+use crate::source::{SourceOptions, open_source};
+use crate::template::TemplateFile;
+
+/// Text files of the template source by their path from the template root, so files can
+/// include and import each other (`{% include "_partials/header.txt" %}`)
+#[derive(Debug, Clone, Default)]
+pub struct SourceFiles {
+    files: HashMap<String, String>,
+}
+
+impl SourceFiles {
+    pub fn new(files: &[TemplateFile]) -> Self {
+        let files = files
+            .iter()
+            .filter_map(|file| {
+                let path = sanitize_path(file.path.to_str()?).ok()?;
+                let name = path.to_str()?.replace('\\', "/");
+                let content = String::from_utf8(file.content.clone()).ok()?;
+                Some((name, content))
+            })
+            .collect();
+        Self { files }
+    }
+
+    pub fn load(&self, name: &str) -> Option<String> {
+        let path = sanitize_path(name).ok()?;
+        self.files.get(&path.to_str()?.replace('\\', "/")).cloned()
+    }
+}
+
+/// Loader for the template environment: files of the source first, then remote includes
+pub fn loader(
+    source: SourceFiles,
+    remote: Arc<RemoteIncludes>,
+) -> impl Fn(&str) -> Result<Option<String>, Error> + Send + Sync + 'static {
+    move |name| match source.load(name) {
+        Some(content) => Ok(Some(content)),
+        None => remote.load(name),
+    }
+}
+
+/// Resolves includes from remote sources like
+/// `{% include "gitlab://gitlab.com/group/snippets@v2#licenses/apache.txt" %}`.
+///
+/// Sources are opened through the regular source machinery (hence cached) and fetched only once
+/// per run. Only remote sources are allowed, templates can not include arbitrary local files.
+pub struct RemoteIncludes {
+    options: SourceOptions,
+    sources: Mutex<HashMap<String, HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl RemoteIncludes {
+    pub fn new(options: SourceOptions) -> Self {
+        Self {
+            options,
+            sources: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Loader for the template environment. Names which are not remote includes are left to
+    /// other loaders (not found).
+    pub fn load(&self, name: &str) -> Result<Option<String>, Error> {
+        let Some((source, path)) = split_include(name) else {
+            return Ok(None);
+        };
+        let path = sanitize_path(path).map_err(|msg| invalid_include(name, msg))?;
+
+        let mut sources = self.sources.lock().expect("include cache is not poisoned");
+        if !sources.contains_key(source) {
+            let files = open_source(source, &self.options)
+                .and_then(|files| {
+                    files
+                        .map(|file| file.map(|f| (f.path, f.content)))
+                        .collect::<Result<HashMap<_, _>>>()
+                })
+                .map_err(|e| invalid_include(name, format!("{:#}", e)))?;
+            sources.insert(source.to_owned(), files);
+        }
+
+        match sources[source].get(&path) {
+            Some(content) => String::from_utf8(content.clone())
+                .map(Some)
+                .map_err(|_| invalid_include(name, "included file is not valid UTF8".to_owned())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Split `scheme://source#path` into source and path. Only URL sources qualify.
+fn split_include(name: &str) -> Option<(&str, &str)> {
+    let (source, path) = name.split_once('#')?;
+    let url = Url::parse(source).ok()?;
+    // a single letter scheme is a windows drive, not a remote source
+    (url.scheme().len() > 1).then_some((source, path))
+}
+
+fn sanitize_path(path: &str) -> Result<PathBuf, String> {
+    let mut sanitized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir | Component::RootDir => continue,
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(format!("invalid path '{}' containing ..", path));
+            }
+        }
+    }
+    if sanitized.as_os_str().is_empty() {
+        return Err("include path cannot be empty".to_owned());
+    }
+    Ok(sanitized)
+}
+
+fn invalid_include(name: &str, msg: String) -> Error {
+    Error::new(
+        ErrorKind::InvalidOperation,
+        format!("failed to include '{}': {}", name, msg),
+    )
+}
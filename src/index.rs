@@ -0,0 +1,112 @@
+//! Index of the parameters each template file references, built from the parsed templates. It is
+//! used for unused-parameter warnings, `rte update --only-changed`, `rte vars` and `rte info`.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::template::{SyntaxMode, TemplateConfig, TemplateFile, environment};
+
+/// Parameters referenced by a template file (its path and content)
+#[derive(Debug, Default, Serialize)]
+pub struct FileVariables {
+    /// Parameter paths like `name` or `database.host`
+    pub parameters: BTreeSet<String>,
+    /// The file uses the parameter object as a whole, e.g. `{{ values | tojson }}`
+    pub all_parameters: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct VariableIndex {
+    pub files: BTreeMap<PathBuf, FileVariables>,
+}
+
+impl VariableIndex {
+    /// Build the index. Only the paths of `verbatim` files are parsed, their content is not
+    /// rendered. Files which fail to parse reference nothing, the error shows when rendering.
+    /// Handlebars templates are not parsed, their files count as using all parameters.
+    pub fn build(
+        config: &TemplateConfig,
+        files: &[TemplateFile],
+        verbatim: &HashSet<PathBuf>,
+    ) -> Self {
+        let env = environment(config);
+        let mut index = Self::default();
+        for file in files {
+            let content = match verbatim.contains(&file.path) {
+                true => None,
+                false => std::str::from_utf8(&file.content).ok(),
+            };
+            let mut variables = FileVariables::default();
+            if let SyntaxMode::Handlebars = config.syntax {
+                variables.all_parameters = true;
+                index.files.insert(file.path.clone(), variables);
+                continue;
+            }
+            for source in [file.path.to_str(), content].into_iter().flatten() {
+                let Ok(template) = env.template_from_str(source) else {
+                    continue;
+                };
+                for variable in template.undeclared_variables(true) {
+                    // computed values can be derived from any parameter
+                    if !config.computed.is_empty() && variable == "computed" {
+                        variables.all_parameters = true;
+                        continue;
+                    }
+                    match &config.root_value {
+                        Some(root) if variable == *root => variables.all_parameters = true,
+                        Some(root) => {
+                            if let Some(parameter) = variable.strip_prefix(&format!("{}.", root)) {
+                                variables.parameters.insert(parameter.to_owned());
+                            }
+                        }
+                        None => {
+                            variables.parameters.insert(variable);
+                        }
+                    }
+                }
+            }
+            index.files.insert(file.path.clone(), variables);
+        }
+        index
+    }
+
+    /// Files which reference a parameter, itself, one of its fields or the whole parameter object
+    pub fn files_referencing<'a>(
+        &'a self,
+        parameter: &'a str,
+    ) -> impl Iterator<Item = &'a PathBuf> {
+        self.files
+            .iter()
+            .filter(move |(_, variables)| variables.references(parameter))
+            .map(|(path, _)| path)
+    }
+
+    pub fn is_referenced(&self, parameter: &str) -> bool {
+        self.files_referencing(parameter).next().is_some()
+    }
+
+    /// All referenced parameters with the files referencing them
+    pub fn parameters(&self) -> BTreeMap<&str, Vec<&PathBuf>> {
+        let mut parameters: BTreeMap<&str, Vec<&PathBuf>> = BTreeMap::new();
+        for (path, variables) in &self.files {
+            for parameter in &variables.parameters {
+                parameters.entry(parameter).or_default().push(path);
+            }
+        }
+        parameters
+    }
+}
+
+impl FileVariables {
+    pub fn references(&self, parameter: &str) -> bool {
+        self.all_parameters
+            || self.parameters.iter().any(|p| {
+                p == parameter
+                    || p.strip_prefix(parameter)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::source::SourceOptions;
+use crate::{github, gitlab, http};
+
+/// Name of the lock file in the destination, next to the answers
+pub const LOCK_FILE: &str = "rte.lock";
+
+/// Commit a GitLab or GitHub source was rendered from, so later renders and updates use exactly
+/// the same template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+    /// Source as given, with the ref which was resolved
+    pub source: String,
+    /// Commit SHA the ref pointed to
+    pub commit: String,
+    /// Digest of the template files. Archives generated by GitLab and GitHub are not byte for
+    /// byte stable, hence the files are hashed rather than the archive.
+    pub sha256: String,
+}
+
+impl Lock {
+    /// Load the lock of a destination, None if there is none
+    pub fn load(dest: &Path) -> Result<Option<Self>> {
+        let path = dest.join(LOCK_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read lock: {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .map(Some)
+            .with_context(|| format!("Failed to parse lock: {}", path.display()))
+    }
+
+    pub fn save(&self, dest: &Path) -> Result<()> {
+        let path = dest.join(LOCK_FILE);
+        let content = serde_yaml::to_string(self).context("Failed to serialize lock")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write lock: {}", path.display()))
+    }
+
+    /// The source with its ref replaced by the locked commit
+    pub fn pinned_source(&self) -> Result<String> {
+        pin(&self.source, &self.commit)
+    }
+}
+
+/// The branch or tag of a GitLab or GitHub source
+pub fn git_ref(source: &str) -> Option<String> {
+    match Url::parse(source).as_ref().map(Url::scheme) {
+        Ok("gitlab") => gitlab::GitlabSource::parse(source).ok()?.git_ref,
+        Ok("github") => github::GitHubSource::parse(source).ok()?.git_ref,
+        _ => None,
+    }
+}
+
+/// Whether the source is a repository whose ref can be pinned to a commit (not a release asset)
+pub fn is_lockable(source: &str) -> bool {
+    match Url::parse(source).as_ref().map(Url::scheme) {
+        Ok("gitlab") => gitlab::GitlabSource::parse(source).is_ok_and(|s| s.asset.is_none()),
+        Ok("github") => github::GitHubSource::parse(source).is_ok_and(|s| s.asset.is_none()),
+        _ => false,
+    }
+}
+
+/// Resolve the ref of a GitLab or GitHub source to the commit SHA it points to
+pub fn resolve_commit(source: &str, options: &SourceOptions) -> Result<String> {
+    options.hosts.check_source(source)?;
+    let client = http::client(options.redirects, &options.hosts)?;
+    match Url::parse(source).as_ref().map(Url::scheme) {
+        Ok("gitlab") => {
+            let gitlab = gitlab::GitlabSource::parse(source)?;
+            gitlab::resolve_commit(&client, &gitlab, options.gitlab_token(&gitlab.host))
+        }
+        Ok("github") => {
+            let github = github::GitHubSource::parse(source)?;
+            github::resolve_commit(&client, &github, options.github_token(&github.host))
+        }
+        _ => anyhow::bail!("source '{}' can not be locked", source),
+    }
+}
+
+/// Replace the ref of a GitLab or GitHub source by a commit
+pub fn pin(source: &str, commit: &str) -> Result<String> {
+    match Url::parse(source).as_ref().map(Url::scheme) {
+        Ok("gitlab") => {
+            let gitlab = gitlab::GitlabSource::parse(source)?;
+            let mut pinned = format!(
+                "gitlab://{}/{}@{}",
+                gitlab.host, gitlab.project_path, commit
+            );
+            if let Some(path) = &gitlab.path {
+                pinned.push_str(&format!("?path={}", urlencoding::encode(path)));
+            }
+            Ok(pinned)
+        }
+        Ok("github") => {
+            let github = github::GitHubSource::parse(source)?;
+            Ok(format!(
+                "github://{}/{}/{}@{}",
+                github.host, github.owner, github.repo, commit
+            ))
+        }
+        _ => anyhow::bail!("source '{}' can not be locked", source),
+    }
+}
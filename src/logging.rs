@@ -0,0 +1,71 @@
+//! Log of what rte does (the sources it fetches, the files it renders and skips) on stderr, so
+//! stdout stays clean for piping. Status messages (e.g. the URL of a created repository) and
+//! warnings are shown by default, -v adds debug and -vv trace messages, --quiet leaves only
+//! errors and --log-level sets the level directly.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Names of the levels of --log-level
+pub const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+static LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+pub fn parse_level(name: &str) -> Result<LevelFilter, String> {
+    match LEVELS.contains(&name) {
+        true => name.parse().map_err(|e| format!("{}", e)),
+        false => Err(format!("expected one of {}", LEVELS.join(", "))),
+    }
+}
+
+/// Level of the options, --log-level wins over --quiet and -v
+pub fn level(verbose: u8, quiet: bool, log_level: Option<LevelFilter>) -> LevelFilter {
+    match (log_level, quiet, verbose) {
+        (Some(level), _, _) => level,
+        (None, true, _) => LevelFilter::ERROR,
+        (None, false, 0) => LevelFilter::INFO,
+        (None, false, 1) => LevelFilter::DEBUG,
+        (None, false, _) => LevelFilter::TRACE,
+    }
+}
+
+/// Log messages up to the level to stderr
+pub fn init(level: LevelFilter) {
+    if LEVEL.set(level).is_err() {
+        return;
+    }
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .event_format(StatusFormat)
+        .init();
+}
+
+/// Status messages as they are, other messages prefixed with their level
+struct StatusFormat;
+
+impl<S, N> FormatEvent<S, N> for StatusFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = *event.metadata().level();
+        if level != Level::INFO {
+            write!(writer, "{:>5} ", level)?;
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
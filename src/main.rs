@@ -1,186 +1,2546 @@
+mod answers;
+mod asset;
+mod backstage;
+mod budget;
+mod builtin;
+mod bundle;
+mod cache;
+mod cancel;
+mod cargo_generate;
+mod charset;
+mod clock;
+mod complete;
+mod compression;
+mod config;
+mod copier;
+mod dedup;
 mod dir;
+mod dryrun;
+mod encode;
+mod envvars;
+mod eol;
+mod exec;
+mod expect;
+mod features;
+mod filelock;
+mod fingerprint;
+mod format;
+mod fsync;
+mod gcs;
+mod git;
 mod github;
 mod gitlab;
+mod glob;
+mod hbs;
+mod hosts;
+mod http;
+mod include;
+mod index;
+mod lock;
+mod logging;
+mod manifest;
+mod manpage;
+mod merge;
+mod message;
+mod overlay;
+mod params;
+mod progress;
+mod prompt;
+mod publish;
+mod random;
+mod refs;
+mod registry;
+mod rename;
+mod report;
+mod rollback;
+mod rteignore;
+mod s3;
+mod secrets;
+mod session;
+mod source;
+mod sprig;
+mod stats;
 mod tar;
 mod template;
+mod verify;
+mod warnings;
+mod workspace;
+mod zip;
 
-use std::fs::{self, File};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use flate2::read::GzDecoder;
+use clap::builder::TypedValueParser;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use clap_complete::env::CompleteEnv;
 use url::Url;
 
-use crate::dir::{read_dir_iter, write_to_directory};
-use crate::tar::{TarFileIter, is_tar_gz, write_to_tar_gz};
-use crate::template::{SyntaxMode, TemplateConfig, TemplateFile, TemplatedFileIter};
+use crate::answers::Answers;
+use crate::backstage::BackstageTemplate;
+use crate::budget::{SizeBudget, parse_size};
+use crate::cache::Cache;
+use crate::cargo_generate::CargoGenerateTemplate;
+use crate::config::UserConfig;
+use crate::copier::CopierTemplate;
+use crate::dedup::{Dedup, LinkMode};
+use crate::dir::{ConflictStrategy, WriteOptions, write_to_directory};
+use crate::dryrun::Preview;
+use crate::encode::Formats;
+use crate::eol::{LineEnding, LineEndings};
+use crate::expect::Expectations;
+use crate::filelock::FileLock;
+use crate::format::OutputFormat;
+use crate::hosts::HostPolicy;
+use crate::http::RedirectPolicy;
+use crate::include::{RemoteIncludes, SourceFiles};
+use crate::index::VariableIndex;
+use crate::lock::{LOCK_FILE, Lock};
+use crate::manifest::{Deprecation, Manifest, ParameterSpec, split_manifest};
+use crate::merge::{MergeRule, Merger, parse_merge_rule};
+use crate::message::{Messages, TemplateInfo};
+use crate::overlay::{Layer, merge_layers};
+use crate::params::{
+    ParameterFile, parse_key_json, parse_key_path, parse_key_value, parse_parameter_file,
+};
+use crate::progress::{Phase, ProgressFormat};
+use crate::prompt::PromptStyle;
+use crate::publish::{MergeRequest, Visibility};
+use crate::random::Random;
+use crate::registry::{Registry, RegistryEntry};
+use crate::rename::Renames;
+use crate::report::{ResultFormat, SkipReason};
+use crate::session::Session;
+use crate::source::{FileIter, SourceOptions, open_source};
+use crate::tar::{write_tar_gz, write_to_tar_gz};
+use crate::template::{
+    Delimiters, FileKind, SyntaxMode, TemplateConfig, TemplateFile, TemplatedFileIter, Undefined,
+    Whitespace,
+};
+use crate::warnings::{Severity, WarningKind, WarningLevel, parse_warning_level};
+use crate::zip::write_to_zip;
+
+/// Destination which streams the output to stdout
+const STDOUT: &str = "-";
 
 #[derive(Parser)]
 #[command(
     version,
-    about = "Rusty Template Executor - bootstrap code projects based on templates"
+    about = "Rusty Template Executor - bootstrap code projects based on templates",
+    after_long_help = manpage::long_help(),
+    subcommand_negates_reqs = true
 )]
 struct Cli {
-    /// Path to parameter file (can be used multiple times, later files override earlier)
-    #[arg(short, long = "parameters")]
-    parameters: Vec<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to parameter file (can be used multiple times, later files override earlier), `-`
+    /// reads the parameters from stdin. Append `:NAMESPACE` to mount the file under a key instead
+    /// (e.g. -p team.yaml:team).
+    #[arg(
+        short,
+        long = "parameters",
+        value_name = "FILE[:NAMESPACE]",
+        value_parser = parse_parameter_file,
+        global = true
+    )]
+    parameters: Vec<ParameterFile>,
 
-    /// Set a template parameter (can be used multiple times, always overrides file parameters)
-    #[arg(short, long = "set", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    /// Set a template parameter (can be used multiple times, always overrides file parameters).
+    /// A dotted key sets a nested value, e.g. db.port=5432.
+    #[arg(
+        short,
+        long = "set",
+        value_name = "KEY=VALUE",
+        value_parser = parse_key_value,
+        add = ArgValueCompleter::new(complete_set),
+        global = true
+    )]
     set: Vec<(String, String)>,
 
+    /// Set a template parameter to a JSON value, e.g. --set-json 'ports=[80, 443]' (can be used
+    /// multiple times, applied after --set)
+    #[arg(long = "set-json", value_name = "KEY=JSON", value_parser = parse_key_json, global = true)]
+    set_json: Vec<(String, serde_json::Value)>,
+
+    /// Set a template parameter to the content of a file, e.g. --set-file license=LICENSE (can be
+    /// used multiple times, applied after --set-json)
+    #[arg(long = "set-file", value_name = "KEY=PATH", value_parser = parse_key_path, global = true)]
+    set_file: Vec<(String, PathBuf)>,
+
+    /// Set a secret template parameter, which is masked in the output and not recorded (can be
+    /// used multiple times, applied last)
+    #[arg(
+        long = "set-secret",
+        value_name = "KEY=VALUE",
+        value_parser = parse_key_value,
+        global = true
+    )]
+    set_secret: Vec<(String, String)>,
+
+    /// Write the parameters the templates are rendered with (merged, with defaults and derived
+    /// values, the computed values under `computed`) to a JSON file, to render with exactly the
+    /// same inputs later using --context
+    #[arg(long = "export-context", value_name = "FILE", global = true)]
+    export_context: Option<PathBuf>,
+
+    /// Render with the parameters of a file written by --export-context as they are, without
+    /// merging, prompting or deriving values
+    #[arg(
+        long = "context",
+        value_name = "FILE",
+        conflicts_with_all = [
+            "parameters",
+            "set",
+            "set_json",
+            "set_file",
+            "features",
+            "no_default_features"
+        ],
+        global = true
+    )]
+    context: Option<PathBuf>,
+
     /// Write into an already existing directory as destination. Otherwise execution
     /// aborts if directory already exists.
-    #[arg(short, long = "force", default_value_t = false)]
+    #[arg(short, long = "force", default_value_t = false, global = true)]
     force: bool,
 
+    /// What to do with files which already exist with other content in the destination: skip,
+    /// overwrite, backup (keep the file and write the rendered one as FILE.new) or prompt. Writes
+    /// into an existing directory like --force and prints a summary at the end.
+    #[arg(long = "on-conflict", value_name = "STRATEGY", global = true)]
+    on_conflict: Option<ConflictStrategy>,
+
+    /// Keep the files written so far if rendering into a directory fails. By default the
+    /// destination is rolled back: a new destination is removed, in an existing one the written
+    /// files are removed or get their previous content back.
+    #[arg(long = "keep-partial", default_value_t = false, global = true)]
+    keep_partial: bool,
+
+    /// Render a new member of the Cargo workspace the destination is in: it is added to the
+    /// members of the workspace's Cargo.toml and to the member index of its README, if any
+    #[arg(long = "workspace", default_value_t = false, global = true)]
+    workspace: bool,
+
+    /// Write even if files which would change have uncommitted changes. By default rte refuses
+    /// to overwrite uncommitted work if the destination is in a git worktree.
+    #[arg(long = "allow-dirty", default_value_t = false, global = true)]
+    allow_dirty: bool,
+
+    /// Print progress events for wrappers: 'json' prints one JSON object per line (phase, file,
+    /// done, cancelled or error) on stdout, or on stderr if the output goes to stdout ('-',
+    /// --dry-run)
+    #[arg(long = "progress", value_name = "FORMAT", global = true)]
+    progress: Option<ProgressFormat>,
+
+    /// Print the result of a render on stdout: 'json' prints the source with the ref and commit
+    /// it resolved to, the rendered and skipped files, warnings and errors as JSON object
+    #[arg(long = "output", value_name = "FORMAT", global = true)]
+    output_format: Option<ResultFormat>,
+
+    /// Sync written files and directories to disk and replace files via rename, falling back to
+    /// copying if the filesystem does not support it. Use this for destinations on network
+    /// filesystems (NFS/SMB).
+    #[arg(long = "fsync", default_value_t = false, global = true)]
+    fsync: bool,
+
+    /// Link rendered files with the same content instead of writing them again: 'hardlink' or
+    /// 'reflink' (copy-on-write clone, falls back to copying). Only applies to directory
+    /// destinations.
+    #[arg(long = "dedup", value_name = "MODE", global = true)]
+    dedup: Option<LinkMode>,
+
+    /// Emit a size-budget warning for rendered files larger than this (e.g. 512k, 10M)
+    #[arg(long = "max-file-size", value_name = "SIZE", value_parser = parse_size, global = true)]
+    max_file_size: Option<u64>,
+
+    /// Emit a size-budget warning if the rendered output is larger than this in total (e.g. 1G)
+    #[arg(long = "max-output-size", value_name = "SIZE", value_parser = parse_size, global = true)]
+    max_output_size: Option<u64>,
+
+    /// Format of the destination: dir, tar-gz or zip [default: by the extension of the
+    /// destination, .tar.gz or .zip, otherwise dir]
+    #[arg(long = "format", value_name = "FORMAT", global = true)]
+    format: Option<OutputFormat>,
+
+    /// Render the template without writing anything. Prints the rendered size of each file and,
+    /// if the destination already exists, the number of added/removed lines per file.
+    #[arg(long = "dry-run", default_value_t = false, global = true)]
+    dry_run: bool,
+
+    /// Store a fingerprint of the template, the parameters and the options in the destination
+    /// directory (.rte-fingerprint). A render with the same fingerprint is skipped.
+    #[arg(long = "fingerprint", default_value_t = false, global = true)]
+    fingerprint: bool,
+
+    /// Fail if the template is deprecated instead of warning
+    #[arg(long = "strict-deprecations", default_value_t = false, global = true)]
+    strict_deprecations: bool,
+
+    /// Render the successor of a deprecated template instead, without asking
+    #[arg(long = "use-successor", default_value_t = false, global = true)]
+    use_successor: bool,
+
+    /// Render even if the fingerprint in the destination matches
+    #[arg(
+        long = "force-render",
+        default_value_t = false,
+        requires = "fingerprint",
+        global = true
+    )]
+    force_render: bool,
+
+    /// Write a JSON report of the files in the output and the files which were skipped, with the
+    /// reason why (e.g. ignored, outside of the template path, unchanged on update)
+    #[arg(long = "report", value_name = "FILE", global = true)]
+    report: Option<PathBuf>,
+
+    /// Resolve the ref of the source again instead of using the commit pinned in rte.lock of the
+    /// destination
+    #[arg(long = "update-lock", default_value_t = false, global = true)]
+    update_lock: bool,
+
+    /// Do not ask for parameters declared by the template which are not set, even if stdin is a
+    /// terminal
+    #[arg(long = "no-input", default_value_t = false, global = true)]
+    no_input: bool,
+
+    /// Ask one plain line at a time and list choices numbered, without anything else on the line,
+    /// for screen readers and dumb terminals (the default with TERM=dumb)
+    #[arg(long = "simple-prompts", default_value_t = false, global = true)]
+    simple_prompts: bool,
+
+    /// Language of the prompts and parameter descriptions, e.g. de or de_CH [default: from
+    /// LC_ALL, LC_MESSAGES or LANG]
+    #[arg(long = "locale", env = "RTE_LOCALE", global = true)]
+    locale: Option<String>,
+
+    /// Time templates get from now() instead of the current time: seconds since the epoch, an
+    /// RFC 3339 time or a date, for golden tests and reproducible builds
+    #[arg(
+        long = "frozen-time",
+        value_name = "TIME",
+        env = "SOURCE_DATE_EPOCH",
+        value_parser = clock::parse_time,
+        global = true
+    )]
+    frozen_time: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Environment variable templates can read with env(), in addition to those declared in the
+    /// manifest. A trailing * allows all variables with the prefix, e.g. CI_* (can be used
+    /// multiple times).
+    #[arg(long = "allow-env", value_name = "VAR", global = true)]
+    allow_env: Vec<String>,
+
+    /// Allow the filters of the template which run external commands (exec in the manifest)
+    #[arg(long = "allow-exec", default_value_t = false, global = true)]
+    allow_exec: bool,
+
+    /// Seed of uuid4(), random_string() and random_hex() in templates, so they return the same
+    /// values on every run, e.g. for golden tests. Never use it for real secrets.
+    #[arg(long = "seed", value_name = "N", global = true)]
+    seed: Option<u64>,
+
+    /// Record source and parameters in .rte-answers.yaml in the destination, needed for
+    /// `rte update`
+    #[arg(long = "record-answers", default_value_t = false, global = true)]
+    record_answers: bool,
+
+    /// Record the inputs of this render (arguments, the source pinned to its commit and all
+    /// parameters including the answers to prompts) in a file which `rte replay` reproduces
+    #[arg(long = "record", value_name = "FILE", global = true)]
+    record: Option<PathBuf>,
+
+    /// Initialize a git repository in the destination and commit the rendered files. Only
+    /// applies to directory destinations.
+    #[arg(long = "git-init", default_value_t = false, global = true)]
+    git_init: bool,
+
+    /// Visibility of the repository created for a gitlab:// or github:// destination
+    #[arg(
+        long = "visibility",
+        value_name = "VISIBILITY",
+        default_value_t = Visibility::Private,
+        global = true
+    )]
+    visibility: Visibility,
+
+    /// Render onto a new branch of the existing gitlab:// or github:// destination repository
+    /// and open a merge (pull) request instead of creating the repository
+    #[arg(long = "merge-request", default_value_t = false, global = true)]
+    merge_request: bool,
+
+    /// Title of the merge request, a template like the commit message
+    #[arg(
+        long = "mr-title",
+        value_name = "TITLE",
+        default_value = "Render {{ template.source }}",
+        requires = "merge_request",
+        global = true
+    )]
+    mr_title: String,
+
+    /// Description of the merge request, a template like the commit message
+    #[arg(
+        long = "mr-description",
+        value_name = "DESCRIPTION",
+        default_value = "",
+        requires = "merge_request",
+        global = true
+    )]
+    mr_description: String,
+
+    /// Message of the commit with --git-init or a repository destination. It is a template with
+    /// the parameters and the source under `template` (e.g. "Bootstrap {{ values.name }} from
+    /// {{ template.source }}"). [default: "Initial commit", the title with --merge-request]
+    #[arg(long = "git-commit-message", value_name = "MESSAGE", global = true)]
+    git_commit_message: Option<String>,
+
+    /// Name of the initial branch with --git-init or the branch of the merge request, a template
+    /// like the commit message [default: init.defaultBranch of the git configuration, rte/<time>
+    /// with --merge-request]
+    #[arg(long = "git-branch", value_name = "BRANCH", global = true)]
+    git_branch: Option<String>,
+
+    /// Run the verification commands of the template manifest in the destination after
+    /// rendering and fail if one of them fails. Only applies to directory destinations.
+    #[arg(long = "verify", default_value_t = false, global = true)]
+    verify: bool,
+
     /// Use Backstage software template syntax (${{ }} instead of {{ }})
-    #[arg(long = "backstage", default_value_t = false)]
+    #[arg(long = "backstage", default_value_t = false, global = true)]
     backstage: bool,
 
-    /// Pass parameters at root level instead of under 'values' key
-    #[arg(long = "parameters-on-root", default_value_t = false)]
-    parameters_on_root: bool,
+    /// Template syntax: jinja, backstage (like --backstage) or handlebars for templates written
+    /// for Yeoman- or hygen-style tooling [default: jinja, backstage for Backstage templates]
+    #[arg(
+        long = "syntax",
+        value_name = "SYNTAX",
+        value_parser = clap::builder::PossibleValuesParser::new(SyntaxMode::NAMES)
+            .map(|name| SyntaxMode::from_name(&name)),
+        conflicts_with = "delimiters",
+        global = true
+    )]
+    syntax: Option<SyntaxMode>,
+
+    /// What references to undefined values do: strict fails the render, lenient renders them
+    /// as empty and chainable also attributes of undefined values [default: strict or the
+    /// undefined of the manifest]
+    #[arg(long = "undefined", value_name = "MODE", global = true)]
+    undefined: Option<Undefined>,
+
+    /// Render undefined values as empty, like --undefined lenient
+    #[arg(
+        long = "allow-undefined",
+        default_value_t = false,
+        conflicts_with = "undefined",
+        global = true
+    )]
+    allow_undefined: bool,
+
+    /// Line endings of the rendered text files: lf, crlf, native (crlf on Windows) or keep those
+    /// of the template. line_endings of the manifest overrides them per file.
+    #[arg(
+        long = "line-endings",
+        value_name = "ENDINGS",
+        default_value = "keep",
+        global = true
+    )]
+    line_endings: LineEnding,
+
+    /// Add the functions of the sprig library of Helm charts (ternary, trunc, b64enc, dict, ...)
+    /// [default: the sprig of the manifest]
+    #[arg(long = "sprig", default_value_t = false, global = true)]
+    sprig: bool,
+
+    /// Remove the first newline after a block tag like {% if %} [default: the whitespace of the
+    /// manifest]
+    #[arg(long = "trim-blocks", default_value_t = false, global = true)]
+    trim_blocks: bool,
+
+    /// Remove spaces and tabs before a block tag at the start of a line [default: the whitespace
+    /// of the manifest]
+    #[arg(long = "lstrip-blocks", default_value_t = false, global = true)]
+    lstrip_blocks: bool,
+
+    /// Remove the newline at the end of rendered files
+    #[arg(
+        long = "strip-trailing-newline",
+        default_value_t = false,
+        global = true
+    )]
+    strip_trailing_newline: bool,
+
+    /// Delimiters instead of those of Jinja, as pairs of variable, block and comment delimiters
+    /// (e.g. '<< >> <% %> <# #>'), for templates which produce Jinja, Helm or GitHub Actions files
+    #[arg(
+        long = "delimiters",
+        value_name = "DELIMITERS",
+        value_parser = Delimiters::parse,
+        global = true
+    )]
+    delimiters: Option<Delimiters>,
+
+    /// Pass parameters at root level instead of under 'values' key, =false passes them under the
+    /// key although the user configuration sets parameters_on_root
+    #[arg(
+        long = "parameters-on-root",
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        global = true
+    )]
+    parameters_on_root: Option<bool>,
+
+    /// Key under which the parameters are passed to the templates
+    #[arg(
+        long = "root-key",
+        default_value = "values",
+        conflicts_with = "parameters_on_root",
+        global = true
+    )]
+    root_key: String,
 
     /// GitLab personal access token (can also use GITLAB_TOKEN env var)
-    #[arg(long = "gitlab-token", env = "GITLAB_TOKEN", hide_env_values = true)]
+    #[arg(
+        long = "gitlab-token",
+        env = "GITLAB_TOKEN",
+        hide_env_values = true,
+        global = true
+    )]
     gitlab_token: Option<String>,
 
     /// GitHub personal access token (can also use GITHUB_TOKEN env var)
-    #[arg(long = "github-token", env = "GITHUB_TOKEN", hide_env_values = true)]
+    #[arg(
+        long = "github-token",
+        env = "GITHUB_TOKEN",
+        hide_env_values = true,
+        global = true
+    )]
     github_token: Option<String>,
 
+    /// Google Cloud Storage OAuth access token (can also use GOOGLE_OAUTH_ACCESS_TOKEN env var)
+    #[arg(
+        long = "gcs-token",
+        env = "GOOGLE_OAUTH_ACCESS_TOKEN",
+        hide_env_values = true,
+        global = true
+    )]
+    gcs_token: Option<String>,
+
+    /// Maximum number of redirects followed when downloading remote sources
+    #[arg(long = "max-redirects", default_value_t = http::DEFAULT_MAX_REDIRECTS, global = true)]
+    max_redirects: usize,
+
+    /// Fail on redirects to another host when downloading remote sources, so that credentials
+    /// are only sent to the host of the source. Note that GitHub archives are served from
+    /// another host.
+    #[arg(
+        long = "no-cross-host-redirects",
+        default_value_t = false,
+        global = true
+    )]
+    no_cross_host_redirects: bool,
+
+    /// Only use cached archives of remote sources, fail if a source is not cached
+    #[arg(
+        long = "offline",
+        default_value_t = false,
+        conflicts_with = "no_cache",
+        global = true
+    )]
+    offline: bool,
+
+    /// Always download remote sources and do not cache them
+    #[arg(long = "no-cache", default_value_t = false, global = true)]
+    no_cache: bool,
+
+    /// Cache directory for remote sources [default: ~/.cache/rte]
+    #[arg(long = "cache-dir", env = "RTE_CACHE_DIR", global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// Download cached remote sources again once they are older than this (e.g. 30m, 12h, 7d)
+    #[arg(
+        long = "cache-max-age",
+        default_value = "1d",
+        value_parser = cache::parse_duration,
+        global = true
+    )]
+    cache_max_age: Duration,
+
+    /// Evict the oldest cached sources once the cache grows beyond this size in MiB
+    #[arg(long = "cache-max-size", default_value_t = 1024, global = true)]
+    cache_max_size: u64,
+
     /// Template path within the source. Mainly if source points to a tar.gz, Gitlab or Github you
     /// can use this option to specify the subpath under which the template resides.
-    #[arg(long = "template-path")]
+    #[arg(long = "template-path", global = true)]
     template_path: Option<String>,
 
-    /// Source template (directory, .tar.gz archive, gitlab://, or github:// URL)
-    source: String,
+    /// Only transfer the template path of GitLab and GitHub sources instead of the whole
+    /// repository: GitLab archives are filtered by path, GitHub repositories are fetched shallow
+    /// and sparse with git
+    #[arg(long = "sparse", default_value_t = false, global = true)]
+    sparse: bool,
+
+    /// Template features to enable (comma separated or used multiple times)
+    #[arg(
+        long = "features",
+        value_name = "FEATURES",
+        value_delimiter = ',',
+        global = true
+    )]
+    features: Vec<String>,
+
+    /// Do not enable the default features of the template
+    #[arg(long = "no-default-features", default_value_t = false, global = true)]
+    no_default_features: bool,
+
+    /// Additional template source layered over the source (can be used multiple times). Files of
+    /// later overlays replace files with the same path of the source and earlier overlays.
+    #[arg(long = "overlay", value_name = "SOURCE", global = true)]
+    overlay: Vec<String>,
+
+    /// Equivalent source (e.g. a mirror) tried in order if the source can not be opened. If none
+    /// can be opened, expired cached copies are used (can be used multiple times).
+    #[arg(long = "fallback", value_name = "SOURCE", global = true)]
+    fallback: Vec<String>,
+
+    /// Copy the files matching a gitignore-like pattern without rendering their content, in
+    /// addition to the [raw] patterns of the template's .rteignore (can be used multiple times)
+    #[arg(long = "raw-glob", value_name = "PATTERN", global = true)]
+    raw_glob: Vec<String>,
+
+    /// Rename files and directories named dot_* to .* and cargo_toml to Cargo.toml, in addition to
+    /// the renames of the template's rte.yaml
+    #[arg(long, global = true)]
+    rename_defaults: bool,
+
+    /// Set the severity of a warning class: KIND=allow|warn|deny, KIND is one of unused-parameter,
+    /// binary-passthrough, overwritten-file, overlay-override, merge-conflict, size-budget,
+    /// deprecated, source-fallback or all (can be used multiple times)
+    #[arg(
+        short = 'W',
+        long = "warn",
+        value_name = "KIND=SEVERITY",
+        value_parser = parse_warning_level,
+        env = "RTE_WARNINGS",
+        value_delimiter = ',',
+        global = true
+    )]
+    warn: Vec<WarningLevel>,
+
+    /// Fail on binary files (not valid UTF8 or with null bytes) instead of copying them without
+    /// rendering
+    #[arg(long = "strict-text", default_value_t = false, global = true)]
+    strict_text: bool,
+
+    /// Show the excerpt of the template around a failing line and the values it referenced in
+    /// errors of templates
+    #[arg(long = "verbose-errors", default_value_t = false, global = true)]
+    verbose_errors: bool,
+
+    /// Key to sign bundles with (`rte bundle`) and to verify them with when used as source. With
+    /// a key, unsigned bundles are rejected.
+    #[arg(
+        long = "bundle-key",
+        env = "RTE_BUNDLE_KEY",
+        hide_env_values = true,
+        global = true
+    )]
+    bundle_key: Option<String>,
+
+    /// Show more of what rte does on stderr: -v debug and -vv trace messages
+    #[arg(short, long = "verbose", action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only show errors, no warnings or status messages
+    #[arg(short, long = "quiet", conflicts_with = "verbose", global = true)]
+    quiet: bool,
+
+    /// Level of the messages on stderr: off, error, warn, info (default), debug or trace
+    #[arg(
+        long = "log-level",
+        value_name = "LEVEL",
+        value_parser = logging::parse_level,
+        global = true
+    )]
+    log_level: Option<tracing::level_filters::LevelFilter>,
+
+    /// User configuration with defaults for options [default: ~/.config/rte/config.yaml]
+    #[arg(long = "config", env = "RTE_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// The loaded user configuration
+    #[arg(skip)]
+    user: UserConfig,
+
+    /// Template registry file [default: ~/.config/rte/templates.yaml]
+    #[arg(long = "registry", env = "RTE_REGISTRY", global = true)]
+    registry: Option<PathBuf>,
+
+    /// Source template (directory, tar archive, gitlab://, github://, s3:// or gs:// URL or
+    /// the name of a template in the registry)
+    #[arg(required = true)]
+    source: Option<String>,
 
-    /// Destination for rendered template (directory or .tar.gz archive)
-    destination: PathBuf,
+    /// Destination for rendered template (directory, .tar.gz or .zip archive, - to write a
+    /// .tar.gz archive to stdout, gitlab://host/group/project or github://host/owner/repo to
+    /// create a new repository)
+    #[arg(required = true)]
+    destination: Option<PathBuf>,
 }
 
-fn parse_key_value(s: &str) -> Result<(String, String), String> {
-    let pos = s.find('=').ok_or("expected format: KEY=VALUE")?;
-    Ok((s[..pos].to_string(), s[pos + 1..].to_string()))
+#[derive(Subcommand)]
+enum Command {
+    /// Render a template into a destination, also possible without the subcommand (`rte SOURCE
+    /// DESTINATION`)
+    Render {
+        /// Source template (directory, tar archive, gitlab://, github://, s3:// or gs:// URL or
+        /// the name of a template in the registry)
+        source: String,
+
+        /// Destination for rendered template (directory, .tar.gz or .zip archive, - to write a
+        /// .tar.gz archive to stdout, gitlab://host/group/project or github://host/owner/repo to
+        /// create a new repository)
+        destination: PathBuf,
+    },
+    /// Render a template without writing it and report all errors, e.g. to check parameter files
+    /// in CI
+    Validate {
+        /// Source template
+        source: String,
+    },
+    /// List the files a template renders with the given parameters
+    Ls {
+        /// Source template
+        source: String,
+    },
+    /// Show how rendering a template would change a destination as unified diff
+    Diff {
+        /// Source template
+        source: String,
+
+        /// Destination rendered before (directory, .tar.gz or .zip archive)
+        destination: PathBuf,
+    },
+    /// List the parameters a template references and the files referencing them
+    Vars {
+        /// Source template
+        source: String,
+    },
+    /// Show the kind, features, assets and verification commands of a template and the
+    /// parameters each file references
+    Info {
+        /// Source template
+        source: String,
+
+        /// Print JSON for tooling
+        #[arg(long = "json", default_value_t = false)]
+        json: bool,
+    },
+    /// Show statistics about the files of a template: types, files with template expressions,
+    /// the largest files, the deepest paths and the parameters each file references
+    Stats {
+        /// Source template
+        source: String,
+
+        /// Number of files listed as largest, deepest and with the most parameters
+        #[arg(long = "top", value_name = "N", default_value_t = 10)]
+        top: usize,
+
+        /// Print JSON for tooling
+        #[arg(long = "json", default_value_t = false)]
+        json: bool,
+    },
+    /// Render the template of a destination again with the recorded answers (see
+    /// --record-answers). Parameters given with -p and --set override the recorded ones. Changes
+    /// are merged three-way with the files in the destination.
+    Update {
+        /// Destination rendered before with --record-answers
+        destination: PathBuf,
+
+        /// Update to another ref of a gitlab:// or github:// source (e.g. a newer tag). The
+        /// changes between the commit in rte.lock and the ref are merged into the destination.
+        #[arg(long = "to", value_name = "REF")]
+        to: Option<String>,
+
+        /// Only render the files which reference parameters that changed since the last render
+        #[arg(long = "only-changed", default_value_t = false)]
+        only_changed: bool,
+
+        /// Merge strategy for files matching GLOB: ours, theirs, union or three-way (default).
+        /// Can be used multiple times, the first matching rule wins.
+        #[arg(long = "merge", value_name = "GLOB=STRATEGY", value_parser = parse_merge_rule)]
+        merge: Vec<MergeRule>,
+    },
+    /// Render again exactly like a run recorded with --record: with the same arguments, the
+    /// source at the recorded commit and the recorded parameters, without prompting
+    Replay {
+        /// Session file written by --record
+        session: PathBuf,
+
+        /// Render into this destination instead of the recorded one
+        destination: Option<PathBuf>,
+    },
+    /// Pack a template into a single .rtepkg file which can be used as source. The bundle pins
+    /// the origin of the template and a digest of its files and is signed with --bundle-key.
+    Bundle {
+        /// Source template
+        source: String,
+
+        /// Bundle file to write (.rtepkg)
+        output: PathBuf,
+    },
+    /// List the branches and tags of a GitLab or GitHub source which can be used as @ref
+    Refs {
+        /// Source template (gitlab:// or github:// URL or registry name)
+        source: String,
+
+        /// Print JSON for tooling
+        #[arg(long = "json", default_value_t = false)]
+        json: bool,
+    },
+    /// Print the script which registers completions in the shell, e.g. `source <(rte completions
+    /// bash)`. The keys and values of --set are completed from the source given before them.
+    Completions {
+        /// Shell to complete in
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(complete::shells()))]
+        shell: String,
+    },
+    /// Write the man pages of rte, for packagers
+    #[command(hide = true)]
+    Mangen {
+        /// Directory to write rte.1 and the pages of the commands to, rte.1 is printed without
+        out_dir: Option<PathBuf>,
+    },
+    /// Manage named templates in the local template registry
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegistryCommand {
+    /// List all registered templates
+    List,
+    /// Register a template source under a name
+    Add {
+        /// Name of the template
+        name: String,
+
+        /// Source of the template
+        source: String,
+
+        /// Template path within the source
+        #[arg(long = "template-path")]
+        template_path: Option<String>,
+
+        /// Equivalent source tried if the source can not be opened (can be used multiple times)
+        #[arg(long = "fallback", value_name = "SOURCE")]
+        fallback: Vec<String>,
+
+        /// Default parameter for the template (can be used multiple times)
+        #[arg(short, long = "set", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        set: Vec<(String, String)>,
+    },
+    /// Remove a template from the registry
+    Remove {
+        /// Name of the template
+        name: String,
+    },
+}
+
+fn registry_path(cli: &Cli) -> Result<PathBuf> {
+    match &cli.registry {
+        Some(path) => Ok(path.clone()),
+        None => registry::default_path().context("Failed to determine the home directory"),
+    }
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    CompleteEnv::with_factory(Cli::command)
+        .var(complete::COMPLETE_VAR)
+        .complete();
+    let mut cli = Cli::parse();
+    logging::init(logging::level(cli.verbose, cli.quiet, cli.log_level));
+    let result = UserConfig::load(cli.config.as_deref()).and_then(|user| {
+        cli.user = user;
+        run(&cli)
+    });
+    if cli.output_format == Some(ResultFormat::Json) && renders(&cli) {
+        let error = result
+            .as_ref()
+            .err()
+            .map(|e| secrets::mask(&format!("{:#}", e)));
+        report::print_result(error)?;
+    }
+    if let Err(e) = &result {
+        if cancel::is_cancelled(e) {
+            tracing::warn!("cancelled");
+            std::process::exit(cancel::EXIT_CODE);
+        }
+        // errors of templates show the values they referenced
+        tracing::error!("{}", secrets::mask(&format!("{:?}", e)));
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-    // Read and merge parameters from files (later files override earlier)
-    let mut params = serde_json::Map::new();
-    for path in &cli.parameters {
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read parameters file: {}", path.display()))?;
-        let file_params: serde_json::Value = serde_yaml::from_str(&content)
-            .with_context(|| format!("Failed to parse parameters file: {}", path.display()))?;
-        if let serde_json::Value::Object(map) = file_params {
-            params.extend(map);
+fn run(cli: &Cli) -> Result<()> {
+    match &cli.command {
+        Some(Command::Registry { command }) => run_registry(&registry_path(cli)?, command),
+        Some(Command::Update {
+            destination,
+            to,
+            only_changed,
+            merge,
+        }) => {
+            let mut answers = Answers::load(destination)?;
+            if let Some(to) = to {
+                if !lock::is_lockable(&answers.source) {
+                    anyhow::bail!(
+                        "--to requires a gitlab:// or github:// source, the destination was rendered from '{}'",
+                        answers.source
+                    );
+                }
+                answers.source = lock::pin(&answers.source, to)?;
+            }
+            let update = UpdateOptions {
+                answers: &answers,
+                only_changed: *only_changed,
+                merge,
+            };
+            let result = render(cli, &answers.source, destination, Some(update), 0);
+            progress::finish(&result);
+            result
+        }
+        Some(Command::Render {
+            source,
+            destination,
+        })
+        | Some(Command::Diff {
+            source,
+            destination,
+        }) => {
+            let result = render(cli, source, destination, None, 0);
+            progress::finish(&result);
+            result
+        }
+        Some(Command::Validate { source }) | Some(Command::Ls { source }) => {
+            // nothing is written, the destination only has to not exist
+            let destination =
+                tempfile::tempdir().context("Failed to create a temporary directory")?;
+            let result = render(cli, source, &destination.path().join("output"), None, 0);
+            progress::finish(&result);
+            result
+        }
+        Some(Command::Vars { source }) => run_vars(cli, source),
+        Some(Command::Info { source, json }) => run_info(cli, source, *json),
+        Some(Command::Stats { source, top, json }) => run_stats(cli, source, *top, *json),
+        Some(Command::Refs { source, json }) => run_refs(cli, source, *json),
+        Some(Command::Replay {
+            session,
+            destination,
+        }) => run_replay(cli, session, destination.as_deref()),
+        Some(Command::Bundle { source, output }) => run_bundle(cli, source, output),
+        Some(Command::Completions { shell }) => {
+            complete::write_registration(shell, &mut std::io::stdout())
+        }
+        Some(Command::Mangen { out_dir }) => match out_dir {
+            Some(dir) => manpage::write_to(Cli::command(), dir),
+            None => manpage::write(Cli::command(), &mut std::io::stdout()),
+        },
+        // the form before the render subcommand
+        None => {
+            let result = render(
+                cli,
+                cli.source.as_deref().expect("source is required"),
+                cli.destination.as_deref().expect("destination is required"),
+                None,
+                0,
+            );
+            progress::finish(&result);
+            result
         }
     }
+}
 
-    // Apply --set key=value overrides (always have precedence)
-    for (key, value) in &cli.set {
-        params.insert(key.clone(), serde_json::Value::String(value.clone()));
+/// Whether the command renders a template, the others have no result for --output
+fn renders(cli: &Cli) -> bool {
+    matches!(
+        cli.command,
+        None | Some(
+            Command::Render { .. }
+                | Command::Validate { .. }
+                | Command::Ls { .. }
+                | Command::Diff { .. }
+                | Command::Update { .. }
+                | Command::Replay { .. }
+        )
+    )
+}
+
+/// Names of the secret parameters: those declared as secret (or password) and --set-secret
+fn secret_names(cli: &Cli, manifest: &Manifest) -> Vec<String> {
+    manifest
+        .parameters
+        .iter()
+        .filter(|(_, spec)| spec.is_secret())
+        .map(|(name, _)| name.clone())
+        .chain(cli.set_secret.iter().map(|(key, _)| key.clone()))
+        .collect()
+}
+
+/// Parameters for --export-context without the secrets, with the computed values under
+/// `computed` like the templates get them
+fn exported_context(
+    params: &serde_json::Value,
+    config: &TemplateConfig,
+    secret_names: &[String],
+) -> serde_json::Value {
+    let mut exported = params.clone();
+    if let serde_json::Value::Object(exported) = &mut exported {
+        for name in secret_names {
+            secrets::remove(exported, name);
+        }
+        if !config.computed.is_empty() {
+            // computed values derived from secrets contain them
+            let mut computed = serde_json::Value::Object(config.computed.clone());
+            secrets::mask_value(&mut computed);
+            exported.insert("computed".to_owned(), computed);
+        }
     }
+    exported
+}
 
-    let params = serde_json::Value::Object(params);
+/// Mask the values of the secret parameters in the output from now on
+fn configure_secrets(names: &[String], params: &serde_json::Map<String, serde_json::Value>) {
+    secrets::configure(
+        names
+            .iter()
+            .filter_map(|name| secrets::get(params, name))
+            .flat_map(secrets::values_of),
+    );
+}
 
-    // Determine source type: URL scheme or local path
-    let template_source: Box<dyn Iterator<Item = Result<TemplateFile>>> =
-        match Url::parse(&cli.source) {
-            Ok(url) => match url.scheme() {
-                "gitlab" => Box::new(gitlab::fetch_archive(
-                    &cli.source,
-                    cli.gitlab_token.as_deref(),
-                )?),
-                "github" => Box::new(github::fetch_archive(
-                    &cli.source,
-                    cli.github_token.as_deref(),
-                )?),
-                scheme => {
-                    anyhow::bail!("unknown url scheme '{}'", scheme)
-                }
-            },
-            Err(_) => {
-                // Not a valid URL, treat as local path
-                let source_path = PathBuf::from(&cli.source);
-                if source_path.is_dir() {
-                    Box::new(read_dir_iter(&source_path))
-                } else {
-                    let file = File::open(&source_path).with_context(|| {
-                        format!("Failed to open archive: {}", source_path.display())
-                    })?;
-                    let decoder = GzDecoder::new(file);
-                    Box::new(TarFileIter::new(decoder)?)
+/// Run a render recorded with --record again. --set of this run adds parameters which are not
+/// recorded, like passwords.
+fn run_replay(outer: &Cli, path: &Path, destination: Option<&Path>) -> Result<()> {
+    let session = Session::load(path)?;
+    if session.rte_version != env!("CARGO_PKG_VERSION") {
+        tracing::warn!(
+            "the session was recorded with rte {}, this is rte {}",
+            session.rte_version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+    let mut cli = Cli::try_parse_from(std::iter::once("rte".to_owned()).chain(session.args))
+        .with_context(|| format!("Failed to parse the arguments of {}", path.display()))?;
+    match cli.command.take() {
+        Some(Command::Render {
+            source,
+            destination,
+        }) => {
+            cli.source = Some(source);
+            cli.destination = Some(destination);
+        }
+        Some(_) => anyhow::bail!("{} does not record a render", path.display()),
+        None => {}
+    }
+
+    // the recorded parameters are all parameters of the run, nothing is asked
+    let parameters = tempfile::Builder::new()
+        .prefix("rte-session")
+        .suffix(".yaml")
+        .tempfile()
+        .context("Failed to create a temporary parameter file")?;
+    let content = serde_yaml::to_string(&session.parameters)
+        .context("Failed to serialize the recorded parameters")?;
+    std::fs::write(parameters.path(), content)
+        .context("Failed to write the recorded parameters to a temporary file")?;
+    if cli.context.is_some() {
+        cli.context = Some(parameters.path().to_path_buf());
+    } else {
+        cli.parameters = vec![ParameterFile {
+            path: parameters.path().to_path_buf(),
+            namespace: None,
+        }];
+    }
+    cli.set.extend(outer.set.iter().cloned());
+    cli.set_json.extend(outer.set_json.iter().cloned());
+    cli.set_file.extend(outer.set_file.iter().cloned());
+    cli.set_secret.extend(outer.set_secret.iter().cloned());
+    cli.no_input = true;
+    cli.user = outer.user.clone();
+    cli.source = Some(session.source);
+    cli.template_path = session.template_path;
+    if let Some(destination) = destination {
+        cli.destination = Some(destination.to_path_buf());
+    }
+    run(&cli)
+}
+
+fn run_registry(path: &Path, command: &RegistryCommand) -> Result<()> {
+    let mut registry = Registry::load(path)?;
+    match command {
+        RegistryCommand::List => {
+            for (name, entry) in &registry.templates {
+                match &entry.template_path {
+                    Some(template_path) => {
+                        println!("{}\t{} ({})", name, entry.source, template_path)
+                    }
+                    None => println!("{}\t{}", name, entry.source),
                 }
             }
+            return Ok(());
+        }
+        RegistryCommand::Add {
+            name,
+            source,
+            template_path,
+            fallback,
+            set,
+        } => {
+            let entry = RegistryEntry {
+                source: source.clone(),
+                template_path: template_path.clone(),
+                fallbacks: fallback.clone(),
+                parameters: set
+                    .iter()
+                    .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                    .collect(),
+            };
+            if registry.add(name.clone(), entry).is_some() {
+                tracing::info!("replaced existing template '{}'", name);
+            }
+        }
+        RegistryCommand::Remove { name } => {
+            registry.remove(name)?;
+        }
+    }
+    registry.save(path)
+}
+
+/// Template source resolved from the registry
+struct ResolvedSource {
+    source: String,
+    template_path: Option<String>,
+    /// Sources tried in order if the source can not be opened
+    fallbacks: Vec<String>,
+    /// Default parameters of the registry entry
+    parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Resolve names from the registry unless the source is a URL or an existing path
+fn resolve_source(
+    cli: &Cli,
+    source: &str,
+    template_path: Option<String>,
+) -> Result<ResolvedSource> {
+    let is_name = Url::parse(source).is_err() && !Path::new(source).exists();
+    // aliases of the user configuration name sources or registered templates
+    let source = match cli.user.aliases.get(source) {
+        Some(aliased) if is_name => aliased.as_str(),
+        _ => source,
+    };
+    if Url::parse(source).is_err() && !Path::new(source).exists() {
+        let registry = Registry::load(&registry_path(cli)?)?;
+        if let Some(entry) = registry.get(source) {
+            let mut fallbacks = entry.fallbacks.clone();
+            fallbacks.extend(cli.fallback.iter().cloned());
+            return Ok(ResolvedSource {
+                source: entry.source.clone(),
+                template_path: template_path.or_else(|| entry.template_path.clone()),
+                fallbacks,
+                parameters: entry.parameters.clone(),
+            });
+        }
+    }
+    Ok(ResolvedSource {
+        source: source.to_owned(),
+        template_path,
+        fallbacks: cli.fallback.clone(),
+        parameters: serde_json::Map::new(),
+    })
+}
+
+/// Directory of the cache, unless turned off
+fn cache_dir(cli: &Cli) -> Option<PathBuf> {
+    cli.cache_dir
+        .clone()
+        .or_else(|| cli.user.cache_dir.clone())
+        .or_else(cache::default_dir)
+}
+
+/// Directory of the destination locks: the cache directory, also with --no-cache, or the temporary
+/// directory without a home directory
+fn lock_dir(cli: &Cli) -> PathBuf {
+    cache_dir(cli).unwrap_or_else(|| std::env::temp_dir().join("rte"))
+}
+
+/// Severities of the warning classes: the user configuration, overridden by -W and RTE_WARNINGS
+fn warning_levels(cli: &Cli) -> Vec<WarningLevel> {
+    // checked when the configuration is loaded
+    let mut levels = cli.user.warning_levels().unwrap_or_default();
+    levels.extend(cli.warn.iter().copied());
+    levels
+}
+
+fn source_options(cli: &Cli) -> Result<SourceOptions> {
+    Ok(SourceOptions {
+        gitlab_token: cli.gitlab_token.clone(),
+        github_token: cli.github_token.clone(),
+        gcs_token: cli.gcs_token.clone(),
+        cache: if cli.no_cache {
+            None
+        } else {
+            cache_dir(cli)
+                .map(|dir| Cache::new(dir, cli.cache_max_age, cli.cache_max_size * 1024 * 1024))
+        },
+        offline: cli.offline,
+        bundle_key: cli.bundle_key.clone(),
+        redirects: RedirectPolicy {
+            max_redirects: cli.max_redirects,
+            same_host: cli.no_cross_host_redirects,
+        },
+        hosts: HostPolicy::load()?,
+        host_tokens: cli.user.tokens.clone(),
+    })
+}
+
+/// Template files with the metadata which is not part of the output
+struct LoadedTemplate {
+    /// Digest of the files of the source (before overlays)
+    digest: String,
+    manifest: Manifest,
+    backstage: Option<BackstageTemplate>,
+    copier: Option<CopierTemplate>,
+    cargo_generate: Option<CargoGenerateTemplate>,
+    files: Vec<TemplateFile>,
+    /// Files whose content is copied without rendering
+    verbatim: HashSet<PathBuf>,
+}
+
+impl LoadedTemplate {
+    fn config(&self, cli: &Cli) -> TemplateConfig {
+        let syntax = match cli.delimiters.as_ref().or(self.manifest.syntax.as_ref()) {
+            Some(delimiters) => SyntaxMode::Custom(delimiters.clone()),
+            None if cli.syntax.is_some() => cli.syntax.clone().unwrap_or_default(),
+            None if cli.backstage || self.backstage.is_some() => SyntaxMode::Backstage,
+            None => cli
+                .user
+                .syntax
+                .as_deref()
+                .map_or(SyntaxMode::Jinja, SyntaxMode::from_name),
+        };
+
+        // copier and cargo-generate templates get the parameters on the root level
+        let root_value = if cli
+            .parameters_on_root
+            .unwrap_or(cli.user.parameters_on_root)
+            || self.copier.is_some()
+            || self.cargo_generate.is_some()
+        {
+            None
+        } else {
+            Some(cli.root_key.clone())
         };
 
+        let undefined = match (cli.undefined, cli.allow_undefined) {
+            (Some(undefined), _) => undefined,
+            (None, true) => Undefined::Lenient,
+            (None, false) => self.manifest.undefined.unwrap_or_default(),
+        };
+
+        let mut whitespace = self.manifest.whitespace;
+        whitespace.trim_blocks |= cli.trim_blocks;
+        whitespace.lstrip_blocks |= cli.lstrip_blocks;
+        whitespace.keep_trailing_newline &= !cli.strip_trailing_newline;
+
+        TemplateConfig {
+            syntax,
+            root_value,
+            undefined,
+            whitespace,
+            sprig: cli.sprig || self.manifest.sprig,
+            encodings: self.manifest.encodings.clone(),
+            verbose_errors: cli.verbose_errors,
+            // set once the parameters and the source are known
+            computed: serde_json::Map::new(),
+            rte: None,
+            now: cli.frozen_time.unwrap_or_else(chrono::Utc::now),
+            random: Random::new(cli.seed),
+            allowed_env: self
+                .manifest
+                .env
+                .iter()
+                .chain(&cli.allow_env)
+                .cloned()
+                .collect(),
+            exec: self.manifest.exec.clone(),
+            allow_exec: cli.allow_exec,
+        }
+    }
+
+    /// Parameters as the template gets them: Backstage templates get the values of their
+    /// fetch:template step instead of the parameters, copier templates the answers to their
+    /// questions
+    fn finalize_parameters(
+        &self,
+        mut params: serde_json::Map<String, serde_json::Value>,
+        destination: &Path,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        self.manifest.apply_parameter_specs(&mut params)?;
+        if let Some(backstage) = &self.backstage {
+            backstage.apply_parameters(&mut params)?;
+            params = backstage.values(&params)?;
+        }
+        if let Some(copier) = &self.copier {
+            copier.answer(&mut params)?;
+        }
+        if let Some(cargo_generate) = &self.cargo_generate {
+            cargo_generate.answer(&mut params, destination)?;
+        }
+        Ok(params)
+    }
+
+    /// Parameters to complete --set with: the declared ones with their choices, then the ones
+    /// the files reference
+    fn completion_parameters(&self, cli: &Cli) -> Vec<complete::Parameter> {
+        let locales = prompt::locales(cli.locale.as_deref());
+        let mut parameters = Vec::new();
+        for (name, spec) in &self.manifest.parameters {
+            let help = spec.description.as_ref().or(spec.prompt.as_ref());
+            complete::add(
+                &mut parameters,
+                complete::Parameter {
+                    name: name.clone(),
+                    choices: spec.choices.clone(),
+                    help: help.map(|help| help.get(&locales).to_owned()),
+                },
+            );
+        }
+        let declared = [
+            self.backstage.as_ref().map(|t| t.parameter_choices()),
+            self.copier.as_ref().map(|t| t.parameter_choices()),
+            self.cargo_generate.as_ref().map(|t| t.parameter_choices()),
+        ];
+        for (name, choices) in declared.into_iter().flatten().flatten() {
+            let help = None;
+            complete::add(
+                &mut parameters,
+                complete::Parameter {
+                    name,
+                    choices,
+                    help,
+                },
+            );
+        }
+        let index = VariableIndex::build(&self.config(cli), &self.files, &self.verbatim);
+        for name in index.parameters().into_keys() {
+            let parameter = complete::Parameter {
+                name: name.to_owned(),
+                choices: Vec::new(),
+                help: None,
+            };
+            complete::add(&mut parameters, parameter);
+        }
+        parameters
+    }
+}
+
+/// Complete the value of --set from the source on the command line which is completed. Errors
+/// result in no candidates, the completion must not print anything else.
+fn complete_set(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
+    let parameters = completion_parameters(current).unwrap_or_default();
+    complete::candidates(&parameters, &current.to_string_lossy())
+}
+
+fn completion_parameters(current: &std::ffi::OsStr) -> Result<Vec<complete::Parameter>> {
+    let matches = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(complete::command_line(current))?;
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    cli.user = UserConfig::load(cli.config.as_deref())?;
+    let (source, template_path) = match &cli.command {
+        Some(Command::Update { destination, .. }) => {
+            let answers = Answers::load(destination)?;
+            (answers.source, answers.template_path)
+        }
+        Some(
+            Command::Render { source, .. }
+            | Command::Validate { source }
+            | Command::Ls { source }
+            | Command::Diff { source, .. },
+        ) => (source.clone(), cli.template_path.clone()),
+        None => match &cli.source {
+            Some(source) => (source.clone(), cli.template_path.clone()),
+            None => return Ok(Vec::new()),
+        },
+        Some(_) => return Ok(Vec::new()),
+    };
+    warnings::configure(&[WarningLevel {
+        kind: None,
+        severity: Severity::Allow,
+    }]);
+    let source = resolve_source(&cli, &source, template_path)?;
+    let template = load_template(&cli, &source, &source_options(&cli)?)?;
+    Ok(template.completion_parameters(&cli))
+}
+
+/// Open the source and strip the template path
+fn open_template_path(
+    cli: &Cli,
+    source: &ResolvedSource,
+    source_options: &SourceOptions,
+) -> Result<FileIter> {
+    let template_source = open_with_fallbacks(cli, source, source_options)?;
+
     // Filter and strip template_path if specified
-    let template_source: Box<dyn Iterator<Item = Result<TemplateFile>>> = match &cli.template_path {
+    let template_source: FileIter = match &source.template_path {
         Some(prefix) => {
             let prefix = PathBuf::from(prefix);
-            Box::new(
-                template_source.filter_map(move |entry| match entry {
-                    Ok(mut file) => {
-                        // Check if file path starts with the prefix
-                        if file.path.starts_with(&prefix) {
-                            // Strip the prefix from the path
-                            match file.path.strip_prefix(&prefix) {
-                                Ok(stripped) => {
-                                    file.path = stripped.to_path_buf();
-                                    Some(Ok(file))
-                                }
-                                Err(_) => Some(Err(anyhow::anyhow!(
-                                    "Failed to strip prefix '{}' from path: {}",
-                                    prefix.display(),
-                                    file.path.display()
-                                ))),
+            Box::new(template_source.filter_map(move |entry| match entry {
+                Ok(mut file) => {
+                    // Check if file path starts with the prefix
+                    if file.path.starts_with(&prefix) {
+                        // Strip the prefix from the path
+                        match file.path.strip_prefix(&prefix) {
+                            Ok(stripped) => {
+                                file.path = stripped.to_path_buf();
+                                Some(Ok(file))
                             }
-                        } else {
-                            // Skip files not under the template path
-                            None
+                            Err(_) => Some(Err(anyhow::anyhow!(
+                                "Failed to strip prefix '{}' from path: {}",
+                                prefix.display(),
+                                file.path.display()
+                            ))),
                         }
+                    } else {
+                        // Skip files not under the template path
+                        report::skip(
+                            file.path,
+                            SkipReason::OutsideTemplatePath {
+                                template_path: prefix.clone(),
+                            },
+                        );
+                        None
                     }
-                    Err(e) => Some(Err(e)),
-                })
-            )
+                }
+                Err(e) => Some(Err(e)),
+            }))
         }
         None => template_source,
     };
+    Ok(template_source)
+}
 
-    //
-    // Configure templating
-    //
-    let syntax = if cli.backstage {
-        SyntaxMode::Backstage
+/// Open the source or, if that fails, the first of its fallbacks which can be opened. If none
+/// can be opened, cached copies are used even if they expired.
+fn open_with_fallbacks(
+    cli: &Cli,
+    source: &ResolvedSource,
+    source_options: &SourceOptions,
+) -> Result<FileIter> {
+    let open = |candidate: &str, options: &SourceOptions| match &source.template_path {
+        Some(template_path) if cli.sparse => source::open_sparse(candidate, template_path, options),
+        _ => open_source(candidate, options),
+    };
+    let error = match open(&source.source, source_options) {
+        Ok(files) => return Ok(files),
+        Err(e) if source.fallbacks.is_empty() => return Err(e),
+        Err(e) => e,
+    };
+    let mut failed = vec![(&source.source, error)];
+    for fallback in &source.fallbacks {
+        match open(fallback, source_options) {
+            Ok(files) => {
+                warnings::emit(
+                    WarningKind::SourceFallback,
+                    format!(
+                        "failed to open '{}', using fallback '{}'",
+                        source.source, fallback
+                    ),
+                )?;
+                return Ok(files);
+            }
+            Err(e) => failed.push((fallback, e)),
+        }
+    }
+    if source_options.cache.is_some() && !source_options.offline {
+        // offline the cache hands out expired entries as well
+        let cached = SourceOptions {
+            offline: true,
+            ..source_options.clone()
+        };
+        for candidate in std::iter::once(&source.source).chain(&source.fallbacks) {
+            if let Ok(files) = open(candidate, &cached) {
+                warnings::emit(
+                    WarningKind::SourceFallback,
+                    format!(
+                        "no source could be opened, using the cached '{}'",
+                        candidate
+                    ),
+                )?;
+                return Ok(files);
+            }
+        }
+    }
+    let mut message = "none of the sources could be opened".to_owned();
+    for (candidate, e) in failed {
+        message.push_str(&format!("\n  {}: {:#}", candidate, e));
+    }
+    anyhow::bail!(message)
+}
+
+/// Open the source, apply the template path and overlays and load the template metadata
+fn load_template(
+    cli: &Cli,
+    source: &ResolvedSource,
+    source_options: &SourceOptions,
+) -> Result<LoadedTemplate> {
+    let files =
+        open_template_path(cli, source, source_options)?.collect::<Result<Vec<TemplateFile>>>()?;
+    let digest = bundle::digest(&files);
+    let template_source: FileIter = Box::new(files.into_iter().map(Ok));
+
+    // Layer overlays over the template source
+    let template_source: FileIter = if cli.overlay.is_empty() {
+        template_source
     } else {
-        SyntaxMode::Jinja
+        let mut layers = vec![Layer {
+            name: source.source.clone(),
+            files: template_source,
+        }];
+        for overlay in &cli.overlay {
+            layers.push(Layer {
+                name: overlay.clone(),
+                files: open_source(overlay, source_options)?,
+            });
+        }
+        let merged = merge_layers(layers)?;
+        for o in &merged.overrides {
+            warnings::emit(
+                WarningKind::OverlayOverride,
+                format!(
+                    "overlay '{}' overrides '{}' from '{}'",
+                    o.by,
+                    o.path.display(),
+                    o.overridden
+                ),
+            )?;
+        }
+        Box::new(merged.files.into_iter().map(Ok))
     };
 
-    let root_value = if cli.parameters_on_root {
+    // Load the manifest before rendering, it is not part of the output
+    let files = template_source.collect::<Result<Vec<TemplateFile>>>()?;
+    let (manifest, files) = split_manifest(files)?;
+    let raw_globs: Vec<String> = manifest.raw.iter().chain(&cli.raw_glob).cloned().collect();
+    let (ignore, files) = rteignore::apply_ignore(files, &manifest.ignore, &raw_globs)?;
+    let (backstage, files) = backstage::split_template(files)?;
+    let copier = match backstage {
+        Some(_) => None,
+        None => copier::split_template(files.as_slice())?,
+    };
+    let cargo_generate = match (&backstage, &copier) {
+        (None, None) => cargo_generate::split_template(files.as_slice())?,
+        _ => None,
+    };
+    let (mut files, mut verbatim) = match (&copier, &cargo_generate) {
+        (Some(template), _) => template.prepare_files(files),
+        (_, Some(template)) => template.prepare_files(files),
+        _ => (files, HashSet::new()),
+    };
+    verbatim.extend(rteignore::strip_raw_suffixes(&mut files)?);
+    if let Some(ignore) = &ignore {
+        verbatim.extend(rteignore::raw_paths(ignore, &files));
+    }
+    Renames::new(
+        &manifest.rename,
+        manifest.rename_defaults || cli.rename_defaults,
+    )
+    .apply(&mut files, &mut verbatim)?;
+
+    Ok(LoadedTemplate {
+        digest,
+        manifest,
+        backstage,
+        copier,
+        cargo_generate,
+        files,
+        verbatim,
+    })
+}
+
+fn run_vars(cli: &Cli, source: &str) -> Result<()> {
+    warnings::configure(&warning_levels(cli));
+    let source = resolve_source(cli, source, cli.template_path.clone())?;
+    let template = load_template(cli, &source, &source_options(cli)?)?;
+    let index = VariableIndex::build(&template.config(cli), &template.files, &template.verbatim);
+
+    for (parameter, files) in index.parameters() {
+        let files: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+        println!("{}\t{}", parameter, files.join(", "));
+    }
+    Ok(())
+}
+
+fn run_info(cli: &Cli, source: &str, json: bool) -> Result<()> {
+    warnings::configure(&warning_levels(cli));
+    let source = resolve_source(cli, source, cli.template_path.clone())?;
+    let template = load_template(cli, &source, &source_options(cli)?)?;
+    let index = VariableIndex::build(&template.config(cli), &template.files, &template.verbatim);
+
+    let kind = if template.backstage.is_some() {
+        "backstage"
+    } else if template.copier.is_some() {
+        "copier"
+    } else if template.cargo_generate.is_some() {
+        "cargo-generate"
+    } else {
+        "rte"
+    };
+
+    if json {
+        let declared: BTreeMap<&String, &ParameterSpec> = template
+            .manifest
+            .parameters
+            .iter()
+            .map(|(name, spec)| (name, spec))
+            .collect();
+        let info = serde_json::json!({
+            "source": source.source,
+            "template_path": source.template_path,
+            "kind": kind,
+            "name": template.manifest.name,
+            "description": template.manifest.description,
+            "deprecated": template.manifest.deprecated,
+            "features": template.manifest.features,
+            "assets": template.manifest.assets,
+            "verify": template.manifest.verify,
+            "env": template.manifest.env,
+            "exec": template.manifest.exec.iter().cloned().collect::<BTreeMap<_, _>>(),
+            "computed": template.manifest.computed.iter().cloned().collect::<BTreeMap<_, _>>(),
+            "declared_parameters": declared,
+            "files": index,
+            "parameters": index.parameters(),
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    println!("source: {}", source.source);
+    if let Some(template_path) = &source.template_path {
+        println!("template path: {}", template_path);
+    }
+    println!("kind: {}", kind);
+    if let Some(name) = &template.manifest.name {
+        println!("name: {}", name);
+    }
+    if let Some(description) = &template.manifest.description {
+        println!("description: {}", description);
+    }
+    if let Some(deprecation) = &template.manifest.deprecated {
+        let message = deprecation.message.as_deref().unwrap_or("yes");
+        match &deprecation.successor {
+            Some(successor) => println!("deprecated: {} (successor: {})", message, successor),
+            None => println!("deprecated: {}", message),
+        }
+    }
+    for (feature, enables) in &template.manifest.features {
+        println!("feature: {} [{}]", feature, enables.join(", "));
+    }
+    for asset in &template.manifest.assets {
+        println!("asset: {} ({})", asset.path.display(), asset.url);
+    }
+    for command in &template.manifest.verify {
+        println!("verify: {}", command);
+    }
+    for name in &template.manifest.env {
+        println!("env: {}", name);
+    }
+    for (name, command) in &template.manifest.exec {
+        println!("exec: {} ({})", name, command.join(" "));
+    }
+    for (name, text) in &template.manifest.computed {
+        println!("computed: {} ({})", name, text);
+    }
+    let locales = prompt::locales(cli.locale.as_deref());
+    for (name, spec) in &template.manifest.parameters {
+        let mut line = name.clone();
+        let details: Vec<String> = spec
+            .kind
+            .map(|kind| kind.to_string())
+            .into_iter()
+            .chain(spec.default_text().map(|d| format!("default: {}", d)))
+            .collect();
+        if !details.is_empty() {
+            line.push_str(&format!(" ({})", details.join(", ")));
+        }
+        if let Some(description) = &spec.description {
+            line.push_str(&format!(" - {}", description.get(&locales)));
+        }
+        println!("parameter: {}", line);
+    }
+    println!("files:");
+    for (path, variables) in &index.files {
+        let mut parameters: Vec<&str> = variables.parameters.iter().map(String::as_str).collect();
+        if variables.all_parameters {
+            parameters.insert(0, "*");
+        }
+        println!("  {}: {}", path.display(), parameters.join(", "));
+    }
+    Ok(())
+}
+
+fn run_stats(cli: &Cli, source: &str, top: usize, json: bool) -> Result<()> {
+    warnings::configure(&warning_levels(cli));
+    let source = resolve_source(cli, source, cli.template_path.clone())?;
+    let template = load_template(cli, &source, &source_options(cli)?)?;
+    let config = template.config(cli);
+    let index = VariableIndex::build(&config, &template.files, &template.verbatim);
+    let stats = stats::collect(&template.files, &template.verbatim, &config, &index, top);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        stats::print(&stats);
+    }
+    Ok(())
+}
+
+fn run_refs(cli: &Cli, source: &str, json: bool) -> Result<()> {
+    let source = resolve_source(cli, source, None)?;
+    let refs = refs::list(&source.source, &source_options(cli)?)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&refs)?);
+        return Ok(());
+    }
+    for r in refs {
+        println!("{}\t{}\t{}", r.kind, r.name, r.commit);
+    }
+    Ok(())
+}
+
+fn run_bundle(cli: &Cli, source: &str, output: &Path) -> Result<()> {
+    let source = resolve_source(cli, source, cli.template_path.clone())?;
+    let files = open_template_path(cli, &source, &source_options(cli)?)?
+        .collect::<Result<Vec<TemplateFile>>>()?;
+    let count = files.len();
+    let metadata = bundle::write_bundle(
+        output,
+        &source.source,
+        source.template_path.as_deref(),
+        files,
+        cli.bundle_key.as_deref(),
+    )?;
+    tracing::info!(
+        "bundled {} files into {} ({}{})",
+        count,
+        output.display(),
+        metadata.digest,
+        if cli.bundle_key.is_some() {
+            ", signed"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+/// Settings of `rte update`
+struct UpdateOptions<'a> {
+    /// Answers of the render which is updated
+    answers: &'a Answers,
+    /// Only render files referencing changed parameters
+    only_changed: bool,
+    /// Merge strategies given on the command line
+    merge: &'a [MergeRule],
+}
+
+/// Successors of deprecated templates followed at most, in case they point at each other
+const MAX_SUCCESSORS: usize = 10;
+
+/// What is printed instead of writing the destination: with --dry-run and for the subcommands
+/// which only look at the rendered files
+fn preview(cli: &Cli) -> Option<Preview> {
+    match &cli.command {
+        Some(Command::Validate { .. }) => Some(Preview::Validate),
+        Some(Command::Ls { .. }) => Some(Preview::List),
+        Some(Command::Diff { .. }) => Some(Preview::Diff),
+        _ if cli.dry_run => Some(Preview::Stats),
+        _ => None,
+    }
+}
+
+/// Render a source into a destination, with `update` an earlier render is updated. `successors`
+/// counts the deprecated templates replaced by their successor so far.
+fn render(
+    cli: &Cli,
+    source: &str,
+    destination: &Path,
+    update: Option<UpdateOptions>,
+    successors: usize,
+) -> Result<()> {
+    let preview = preview(cli);
+    warnings::configure(&warning_levels(cli));
+    let json_result = cli.output_format == Some(ResultFormat::Json);
+    if json_result && destination == Path::new(STDOUT) {
+        anyhow::bail!("--output json can not be used with - as destination");
+    }
+    progress::configure(
+        cli.progress,
+        preview.is_some() || destination == Path::new(STDOUT) || json_result,
+    );
+    progress::phase(Phase::Load);
+    let previous = update.as_ref().map(|u| u.answers);
+    let remote = publish::remote_destination(destination)?;
+    if remote.is_some() && previous.is_some() {
+        anyhow::bail!("rte update requires a local destination");
+    }
+    if cli.on_conflict.is_some() && previous.is_some() {
+        anyhow::bail!(
+            "rte update merges with existing files, use --merge instead of --on-conflict"
+        );
+    }
+
+    // concurrent runs into the same destination (e.g. CI matrix jobs) wait for each other
+    let destination_lock =
+        if preview.is_some() || remote.is_some() || destination == Path::new(STDOUT) {
+            None
+        } else {
+            Some(FileLock::exclusive(&filelock::destination_lock_path(
+                &lock_dir(cli),
+                destination,
+            ))?)
+        };
+
+    let template_path = cli
+        .template_path
+        .clone()
+        .or_else(|| previous.and_then(|p| p.template_path.clone()));
+    let mut answers = Answers {
+        source: source.to_owned(),
+        template_path: template_path.clone(),
+        parameters: serde_json::Map::new(),
+    };
+
+    // registry parameters are defaults, parameter files and --set override them
+    let mut source = resolve_source(cli, source, template_path)?;
+    let mut params = source.parameters.clone();
+
+    // Recorded parameters of an update override the registry defaults
+    if let Some(previous) = previous {
+        params.extend(previous.parameters.clone());
+    }
+
+    // Read and merge parameters from files (later files override earlier, maps are merged)
+    params::merge_files(&mut params, &cli.parameters)?;
+
+    // Apply --set key=value overrides (always have precedence)
+    params::apply_overrides(&mut params, &cli.set, &cli.set_json, &cli.set_file)?;
+    for (key, value) in &cli.set_secret {
+        params::insert_dotted(&mut params, key, serde_json::Value::String(value.clone()));
+    }
+    secrets::configure(cli.set_secret.iter().map(|(_, value)| value.clone()));
+
+    // an exported context replaces the parameters as the templates get them, secrets are not
+    // exported and set again
+    let context = cli
+        .context
+        .as_deref()
+        .map(params::load_context)
+        .transpose()?
+        .map(|mut context| {
+            for (key, value) in &cli.set_secret {
+                params::insert_dotted(&mut context, key, serde_json::Value::String(value.clone()));
+            }
+            context
+        });
+
+    let source_options = source_options(cli)?;
+
+    // GitLab and GitHub sources are pinned to the commit in the lock of the destination. If
+    // answers are recorded, a new lock pins the commit the ref points to now.
+    let record = cli.record_answers || previous.is_some();
+    let recorded_lock = Lock::load(destination)?;
+    let mut lock = match recorded_lock.clone() {
+        Some(lock) if !cli.update_lock && lock.source == source.source => Some(lock),
+        _ if (record || cli.record.is_some())
+            && !cli.offline
+            && lock::is_lockable(&source.source) =>
+        {
+            Some(Lock {
+                source: source.source.clone(),
+                commit: lock::resolve_commit(&source.source, &source_options)?,
+                sha256: String::new(),
+            })
+        }
+        _ => None,
+    };
+    if let Some(lock) = &lock {
+        source.source = lock.pinned_source()?;
+    }
+
+    let template = load_template(cli, &source, &source_options)?;
+    tracing::debug!("loaded {} files of {}", template.files.len(), source.source);
+    let mut config = template.config(cli);
+    let secret_names = secret_names(cli, &template.manifest);
+    configure_secrets(&secret_names, context.as_ref().unwrap_or(&params));
+
+    // an update stays with the recorded source, a new render can switch to the successor
+    if let Some(deprecation) = &template.manifest.deprecated
+        && let Some(successor) =
+            check_deprecation(cli, &answers.source, deprecation, previous.is_none())?
+    {
+        if successors >= MAX_SUCCESSORS {
+            anyhow::bail!(
+                "more than {} successors of deprecated templates",
+                MAX_SUCCESSORS
+            );
+        }
+        drop(destination_lock);
+        return render(cli, &successor, destination, None, successors + 1);
+    }
+
+    // an update to another commit merges the changes between the template at the recorded commit
+    // and the new one, so the base is rendered from the template at the recorded commit
+    let base_template = match (previous, &recorded_lock, &lock) {
+        (Some(_), Some(recorded), Some(lock)) if recorded.commit != lock.commit => {
+            if update.as_ref().is_some_and(|u| u.only_changed) {
+                anyhow::bail!("--only-changed can not be used to update to another commit");
+            }
+            tracing::info!(
+                "updating the template from {} to {}",
+                recorded.commit,
+                lock.commit
+            );
+            let base_source = ResolvedSource {
+                source: recorded.pinned_source()?,
+                template_path: source.template_path.clone(),
+                fallbacks: Vec::new(),
+                parameters: serde_json::Map::new(),
+            };
+            Some(load_template(cli, &base_source, &source_options)?)
+        }
+        _ => None,
+    };
+
+    if let Some(lock) = &mut lock {
+        if lock.sha256.is_empty() {
+            lock.sha256 = template.digest.clone();
+        } else if lock.sha256 != template.digest {
+            anyhow::bail!(
+                "the template files of '{}' at {} differ from {}, use --update-lock to lock the template again",
+                lock.source,
+                lock.commit,
+                LOCK_FILE
+            );
+        }
+    }
+
+    // ask for the declared parameters which are still missing
+    if context.is_none() && !cli.no_input && std::io::stdin().is_terminal() {
+        prompt::ask_missing(
+            &template.manifest.parameters,
+            &mut params,
+            &prompt::locales(cli.locale.as_deref()),
+            PromptStyle::detect(cli.simple_prompts),
+            &mut std::io::stdin().lock(),
+            &mut std::io::stderr(),
+        )?;
+    }
+    // from here on Ctrl-C stops the render and cleans up instead of ending rte right away
+    cancel::install();
+    answers.parameters = context.clone().unwrap_or_else(|| params.clone());
+    for name in &secret_names {
+        secrets::remove(&mut answers.parameters, name);
+    }
+    if let Some(path) = &cli.record {
+        let session = Session {
+            rte_version: env!("CARGO_PKG_VERSION").to_owned(),
+            args: session::recorded_args(std::env::args().skip(1), &secret_names),
+            source: source.source.clone(),
+            template_path: source.template_path.clone(),
+            commit: lock.as_ref().map(|lock| lock.commit.clone()),
+            parameters: answers.parameters.clone(),
+        };
+        session.save(path)?;
+    }
+
+    // parameters given by the user, as opposed to values rte adds like features
+    let mut user_parameters: Vec<String> = match &context {
+        Some(_) => Vec::new(),
+        None => params.keys().cloned().collect(),
+    };
+    if template.cargo_generate.is_some() {
+        // placeholders like project-name are rewritten to project_name
+        for parameter in &mut user_parameters {
+            *parameter = parameter.replace('-', "_");
+        }
+    }
+
+    if let Some(backstage) = &template.backstage {
+        for parameter in &user_parameters {
+            if !backstage.declared_parameters().any(|p| p == parameter) {
+                warnings::emit(
+                    WarningKind::UnusedParameter,
+                    format!(
+                        "parameter '{}' is not declared by the Backstage template",
+                        parameter
+                    ),
+                )?;
+            }
+        }
+        user_parameters.clear();
+    }
+
+    // secrets are not recorded, the recorded answers get those of this run
+    let base = base_template.as_ref().unwrap_or(&template);
+    let mut previous_params = previous
+        .map(|previous| {
+            let mut parameters = previous.parameters.clone();
+            for name in &secret_names {
+                if let Some(value) = secrets::get(&params, name)
+                    && secrets::get(&parameters, name).is_none()
+                {
+                    params::insert_dotted(&mut parameters, name, value.clone());
+                }
+            }
+            base.finalize_parameters(parameters, destination)
+        })
+        .transpose()
+        .context("Failed to apply the recorded answers to the template")?;
+    let mut params = match context {
+        Some(context) => context,
+        None => template.finalize_parameters(params, destination)?,
+    };
+    configure_secrets(&secret_names, &params);
+
+    let changed = match (&update, &previous_params) {
+        (Some(update), Some(previous_params)) if update.only_changed => {
+            Some(Answers::changed_parameters(previous_params, &params))
+        }
+        _ => None,
+    };
+
+    let resolve_features = |manifest: &Manifest| {
+        if manifest.features.is_empty() && cli.features.is_empty() {
+            return Ok(None);
+        }
+        features::resolve(&manifest.features, &cli.features, !cli.no_default_features).map(Some)
+    };
+    if cli.context.is_none()
+        && let Some(features) = resolve_features(&template.manifest)?
+    {
+        params.insert("features".to_owned(), serde_json::Value::Object(features));
+    }
+    if let Some(previous_params) = &mut previous_params
+        && let Some(features) = resolve_features(&base.manifest)?
+    {
+        previous_params.insert("features".to_owned(), serde_json::Value::Object(features));
+    }
+    let params = serde_json::Value::Object(params);
+    let template_info = TemplateInfo {
+        source: answers.source.clone(),
+        template_path: source.template_path.clone(),
+        commit: lock.as_ref().map(|lock| lock.commit.clone()),
+        rte_version: env!("CARGO_PKG_VERSION").to_owned(),
+    };
+    config.rte = Some(template_info.render_context(&template.files, config.now));
+    config.computed = template.manifest.computed_values(&params, &config)?;
+    if let Some(path) = &cli.export_context {
+        params::save_context(path, &exported_context(&params, &config, &secret_names))?;
+    }
+    report::source(report::Source {
+        source: answers.source.clone(),
+        resolved: source.source.clone(),
+        git_ref: lock::git_ref(&answers.source),
+        commit: template_info.commit.clone(),
+        template_path: source.template_path.clone(),
+    });
+
+    // renders with the same inputs write the same files, a destination having them is up to date
+    let fingerprint = if cli.fingerprint && remote.is_none() {
+        let inputs = fingerprint::Inputs {
+            source_digest: &template.digest,
+            files_digest: bundle::digest(&template.files),
+            parameters: &params,
+            root_value: config.root_value.as_deref(),
+            backstage: matches!(config.syntax, SyntaxMode::Backstage),
+            delimiters: cli.delimiters.as_ref(),
+            handlebars: matches!(config.syntax, SyntaxMode::Handlebars),
+            undefined: (config.undefined != Undefined::Strict).then_some(config.undefined),
+            whitespace: (config.whitespace != Whitespace::default()).then_some(config.whitespace),
+            sprig: config.sprig,
+            line_endings: (cli.line_endings != LineEnding::Keep).then_some(cli.line_endings),
+            rte_version: env!("CARGO_PKG_VERSION"),
+        };
+        Some(inputs.fingerprint()?)
+    } else {
         None
+    };
+    if let Some(fingerprint) = &fingerprint
+        && !cli.force_render
+        && preview.is_none()
+        && destination.is_dir()
+        && fingerprint::load(destination)?.as_ref() == Some(fingerprint)
+    {
+        tracing::info!(
+            "{} is up to date ({}), use --force-render to render anyway",
+            destination.display(),
+            fingerprint
+        );
+        return save_report(cli);
+    }
+
+    // the commit message and branch name are rendered before anything is written
+    let messages = Messages::new(&config, &params, template_info.clone())?;
+    let message = template
+        .manifest
+        .message
+        .as_deref()
+        .map(|message| messages.render("the message of rte.yaml", message))
+        .transpose()?;
+    let expectations = Expectations::new(&template.manifest.expect, &messages)?;
+    let formats = Formats::new(&template.manifest.formats)?;
+    let line_endings = LineEndings::new(cli.line_endings, &template.manifest.line_endings)?;
+    let commits = cli.git_init || remote.is_some();
+    if !commits && (cli.git_commit_message.is_some() || cli.git_branch.is_some()) {
+        anyhow::bail!(
+            "--git-commit-message and --git-branch require --git-init or a repository destination"
+        );
+    }
+    let branch = cli
+        .git_branch
+        .as_deref()
+        .map(|branch| messages.render("--git-branch", branch))
+        .transpose()?;
+    let merge_request = if cli.merge_request {
+        if remote.is_none() {
+            anyhow::bail!("--merge-request requires a gitlab:// or github:// destination");
+        }
+        Some(MergeRequest {
+            branch: branch.clone().unwrap_or_else(publish::default_branch),
+            title: messages.render("--mr-title", &cli.mr_title)?,
+            description: messages.render("--mr-description", &cli.mr_description)?,
+        })
+    } else {
+        None
+    };
+    let git_commit = if commits {
+        let message = match (&cli.git_commit_message, &merge_request) {
+            (Some(message), _) => messages.render("--git-commit-message", message)?,
+            (None, Some(merge_request)) => merge_request.title.clone(),
+            (None, None) => git::DEFAULT_COMMIT_MESSAGE.to_owned(),
+        };
+        Some((message, branch))
     } else {
-        Some("values".to_owned())
+        None
+    };
+
+    let index = VariableIndex::build(&config, &template.files, &template.verbatim);
+    warn_unused_parameters(&index, &user_parameters)?;
+
+    // files which do not reference any of the changed parameters would render the same
+    let mut files = template.files;
+    let source_files = SourceFiles::new(&files);
+    template.manifest.remove_partials(&mut files)?;
+    template
+        .manifest
+        .apply_file_conditions(&mut files, &params, &config)?;
+    if let Some(cargo_generate) = &template.cargo_generate {
+        cargo_generate.apply_conditionals(&mut files, &params)?;
+    }
+    if let Some(changed) = &changed {
+        files.retain(|file| {
+            let references = index.files.get(&file.path).is_some_and(|variables| {
+                changed
+                    .iter()
+                    .any(|parameter| variables.references(parameter))
+            });
+            if !references {
+                report::skip(&file.path, SkipReason::ParametersUnchanged);
+            }
+            references
+        });
+        tracing::info!(
+            "{} changed parameters ({}), rendering {} files",
+            changed.len(),
+            changed.join(", "),
+            files.len()
+        );
+    }
+
+    // includes of files of the source and from other sources, e.g.
+    // {% include "gitlab://host/group/project@v2#LICENSE" %}
+    let includes = Arc::new(RemoteIncludes::new(source_options.clone()));
+
+    // the base of the merge is the template rendered with the recorded answers
+    let merger = match (&update, previous_params) {
+        (Some(update), Some(previous_params)) => {
+            let includes = includes.clone();
+            let previous_params = serde_json::Value::Object(previous_params);
+            let (base_files, base_source_files, base_config, base_verbatim) = match base_template {
+                Some(base) => {
+                    // the time of this render, the generator starts over like in the recorded one
+                    let mut config = TemplateConfig {
+                        now: config.now,
+                        ..base.config(cli)
+                    };
+                    config.rte = Some(template_info.render_context(&base.files, config.now));
+                    config.computed = base.manifest.computed_values(&previous_params, &config)?;
+                    let mut files = base.files;
+                    let source_files = SourceFiles::new(&files);
+                    base.manifest.remove_partials(&mut files)?;
+                    base.manifest
+                        .apply_file_conditions(&mut files, &previous_params, &config)?;
+                    if let Some(cargo_generate) = &base.cargo_generate {
+                        cargo_generate.apply_conditionals(&mut files, &previous_params)?;
+                    }
+                    (files, source_files, config, base.verbatim)
+                }
+                None => {
+                    let mut config = config.clone();
+                    config.computed = template
+                        .manifest
+                        .computed_values(&previous_params, &config)?;
+                    (
+                        files.clone(),
+                        source_files.clone(),
+                        config,
+                        template.verbatim.clone(),
+                    )
+                }
+            };
+            let base = TemplatedFileIter::with_config(
+                base_files.into_iter().map(Ok),
+                previous_params,
+                base_config,
+            )
+            .with_verbatim(base_verbatim)
+            .with_strict_text(cli.strict_text)
+            .with_loader(include::loader(base_source_files, includes))
+            .map(|file| file.map(|file| (file.path, file.content)))
+            .collect::<Result<HashMap<_, _>>>()
+            .context("Failed to render the template with the recorded answers")?;
+
+            let mut rules = update.merge.to_vec();
+            rules.extend(template.manifest.merge.iter().cloned());
+            Some(Merger::new(destination, &rules, base)?)
+        }
+        _ => None,
     };
 
-    let templated_files = TemplatedFileIter::with_config(
-        template_source,
-        params,
-        TemplateConfig { syntax, root_value },
+    let rendered = files.len();
+    tracing::debug!(
+        "rendering {} files into {}",
+        rendered,
+        secrets::mask(&destination.display().to_string())
     );
+    let templated_files = TemplatedFileIter::with_config(files.into_iter().map(Ok), params, config)
+        .with_verbatim(template.verbatim)
+        .with_strict_text(cli.strict_text)
+        .with_collect_errors(preview.is_some())
+        .with_loader(include::loader(source_files, includes))
+        .map(move |file| {
+            let file = file?;
+            if file.kind != FileKind::File {
+                return Ok(file);
+            }
+            let file = formats.apply(file)?;
+            let file = line_endings.apply(file);
+            expectations.check(&file)?;
+            Ok(file)
+        });
+
+    // assets are placed into the output as they are
+    let assets = match changed {
+        Some(_) => Vec::new(),
+        None => template.manifest.assets,
+    };
+    let asset_options = source_options.clone();
+    let total = rendered + assets.len();
+    let templated_files = templated_files
+        .chain(
+            assets
+                .into_iter()
+                .map(move |asset| asset::fetch(&asset, &asset_options)),
+        )
+        .enumerate()
+        .map(move |(index, file)| {
+            if let Ok(file) = &file {
+                progress::file(&file.path, index + 1, total);
+            }
+            file
+        });
 
-    if is_tar_gz(&cli.destination) {
-        write_to_tar_gz(&cli.destination, templated_files)?;
+    // on update the rendered files are merged with the destination, unchanged ones are skipped
+    let templated_files: FileIter = match merger {
+        Some(merger) => Box::new(
+            templated_files
+                .filter_map(move |file| file.and_then(|file| merger.merge(file)).transpose()),
+        ),
+        None => Box::new(templated_files),
+    };
+
+    let mut budget = SizeBudget::new(cli.max_file_size, cli.max_output_size);
+    let templated_files = templated_files.map(move |file| {
+        cancel::check()?;
+        let file = file?;
+        budget.check(&file)?;
+        report::output(&file);
+        Ok(file)
+    });
+
+    // `-` streams a tar.gz archive to stdout
+    let to_stdout = destination == Path::new(STDOUT);
+    let format = match cli.format {
+        Some(format) => format,
+        None if to_stdout => OutputFormat::TarGz,
+        None => OutputFormat::detect(destination),
+    };
+    progress::phase(Phase::Render);
+    if let Some(preview) = preview {
+        let with_diff = preview == Preview::Diff;
+        let dry_run = dryrun::collect_stats(destination, format, templated_files, with_diff)?;
+        // with --output json the files are part of the result
+        match preview {
+            _ if json_result => {}
+            Preview::Stats => dryrun::print_stats(&dry_run.files),
+            Preview::Diff => dryrun::print_diff(&dry_run.files),
+            Preview::List => dryrun::print_list(&dry_run.files),
+            Preview::Validate => {}
+        }
+        for error in &dry_run.errors {
+            let message = secrets::mask(&format!("{:#}", error));
+            tracing::error!("{}", message);
+            report::error(message);
+        }
+        save_report(cli)?;
+        if !dry_run.errors.is_empty() {
+            anyhow::bail!("{} errors while rendering", dry_run.errors.len());
+        }
+        if preview == Preview::Validate && !json_result {
+            println!("{} files rendered without errors", dry_run.files.len());
+        }
+        return Ok(());
+    }
+
+    if format != OutputFormat::Dir && cli.verify {
+        anyhow::bail!("--verify requires a directory as destination");
+    }
+    if format != OutputFormat::Dir && (cli.git_init || remote.is_some()) {
+        anyhow::bail!("--git-init and repository destinations require the dir format");
+    }
+    let workspace_root = if cli.workspace {
+        if format != OutputFormat::Dir || remote.is_some() {
+            anyhow::bail!("--workspace requires a directory as destination");
+        }
+        Some(workspace::find_root(destination)?)
     } else {
-        write_to_directory(&cli.destination, templated_files, cli.force)?;
+        None
+    };
+    if cli.git_init && destination.join(".git").exists() {
+        anyhow::bail!(
+            "--git-init: '{}' already is a git repository",
+            destination.display()
+        );
+    }
+    if to_stdout {
+        if format != OutputFormat::TarGz {
+            anyhow::bail!("only tar-gz archives can be written to stdout");
+        }
+        let stdout = std::io::stdout();
+        if stdout.is_terminal() {
+            anyhow::bail!("refusing to write a tar.gz archive to a terminal, redirect stdout");
+        }
+        let mut stdout = write_tar_gz(stdout.lock(), templated_files)?;
+        stdout.flush().context("Failed to write to stdout")?;
+        print_message(message.as_deref());
+        return save_report(cli);
+    }
+    match format {
+        OutputFormat::TarGz => remove_partial_archive(
+            destination,
+            write_to_tar_gz(destination, templated_files, cli.fsync),
+        )?,
+        OutputFormat::Zip => remove_partial_archive(
+            destination,
+            write_to_zip(destination, templated_files, cli.fsync),
+        )?,
+        OutputFormat::Dir => {
+            // repository destinations are rendered into a temporary directory and pushed from
+            // there, for a merge request into a clone of the repository
+            let staging = match &remote {
+                Some(_) => {
+                    Some(tempfile::tempdir().context("Failed to create a temporary directory")?)
+                }
+                None => None,
+            };
+            let dir = staging
+                .as_ref()
+                .map_or(destination, |staging| staging.path());
+            let base = match (&remote, &merge_request) {
+                (Some(remote), Some(merge_request)) => Some(publish::checkout(
+                    remote,
+                    dir,
+                    merge_request,
+                    &source_options,
+                )?),
+                _ => None,
+            };
+            let options = WriteOptions {
+                force: cli.force || previous.is_some() || staging.is_some(),
+                sync: cli.fsync,
+                dedup: cli.dedup.map(Dedup::new),
+                on_conflict: cli.on_conflict,
+                keep_partial: cli.keep_partial,
+                allow_dirty: cli.allow_dirty || staging.is_some(),
+            };
+            write_to_directory(dir, templated_files, &options)?;
+            if let Some(root) = &workspace_root {
+                workspace::add_member(root, dir)?;
+            }
+            if let Some(fingerprint) = &fingerprint {
+                fingerprint::save(dir, fingerprint)?;
+            }
+            if record {
+                answers.save(dir)?;
+                if let Some(lock) = &lock {
+                    lock.save(dir)?;
+                }
+            }
+            cancel::check()?;
+            if git_commit.is_some() {
+                progress::phase(Phase::Commit);
+            }
+            match (&git_commit, &base) {
+                (Some((message, _)), Some(_)) => {
+                    if !git::commit_all(dir, message)? {
+                        anyhow::bail!("the rendered files do not change the repository");
+                    }
+                }
+                (Some((message, branch)), None) => {
+                    git::init_commit(dir, message, branch.as_deref())?
+                }
+                (None, _) => {}
+            }
+            if cli.verify {
+                progress::phase(Phase::Verify);
+                verify::run(dir, &template.manifest.verify)?;
+            }
+            if remote.is_some() {
+                cancel::check()?;
+                progress::phase(Phase::Publish);
+            }
+            match (&remote, &merge_request, &base) {
+                (Some(remote), Some(merge_request), Some(base)) => {
+                    let url = publish::open_merge_request(
+                        remote,
+                        dir,
+                        base,
+                        merge_request,
+                        &source_options,
+                    )?;
+                    tracing::info!("opened {}", url);
+                }
+                (Some(remote), _, _) => {
+                    let url = publish::publish(remote, dir, cli.visibility, &source_options)?;
+                    tracing::info!("created {}", url);
+                }
+                (None, _, _) => {}
+            }
+        }
     }
 
+    print_message(message.as_deref());
+    save_report(cli)
+}
+
+/// Remove an archive which could not be written completely
+fn remove_partial_archive(path: &Path, result: Result<()>) -> Result<()> {
+    if result.is_err() && path.is_file() {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+/// Print the post-render message of the template
+fn print_message(message: Option<&str>) {
+    if let Some(message) = message.map(str::trim).filter(|m| !m.is_empty()) {
+        tracing::info!("{}", message);
+    }
+}
+
+/// Warn about a deprecated template, or fail with --strict-deprecations. Returns the successor if
+/// it should be rendered instead: with --use-successor or if the user chooses to.
+fn check_deprecation(
+    cli: &Cli,
+    source: &str,
+    deprecation: &Deprecation,
+    can_switch: bool,
+) -> Result<Option<String>> {
+    let mut message = format!("template '{}' is deprecated", source);
+    if let Some(reason) = &deprecation.message {
+        message.push_str(&format!(": {}", reason));
+    }
+    if let Some(successor) = &deprecation.successor {
+        message.push_str(&format!(" (successor: {})", successor));
+    }
+    let successor = deprecation.successor.as_ref().filter(|_| can_switch);
+    if let Some(successor) = successor
+        && cli.use_successor
+    {
+        tracing::warn!("{}, rendering {} instead", message, successor);
+        return Ok(Some(successor.clone()));
+    }
+    if cli.strict_deprecations {
+        anyhow::bail!("{} (--strict-deprecations)", message);
+    }
+    warnings::emit(WarningKind::Deprecated, &message)?;
+
+    if let Some(successor) = successor
+        && !cli.no_input
+        && std::io::stdin().is_terminal()
+        && prompt::confirm(
+            &format!("Render the successor {} instead?", successor),
+            &mut std::io::stdin().lock(),
+            &mut std::io::stderr(),
+        )?
+    {
+        return Ok(Some(successor.clone()));
+    }
+    Ok(None)
+}
+
+/// Write the files written and skipped to the report file, if one is requested
+fn save_report(cli: &Cli) -> Result<()> {
+    match &cli.report {
+        Some(path) => report::save(path),
+        None => Ok(()),
+    }
+}
+
+/// Emit a warning for every parameter which no template references
+fn warn_unused_parameters(index: &VariableIndex, parameters: &[String]) -> Result<()> {
+    for parameter in parameters {
+        if !index.is_referenced(parameter) {
+            warnings::emit(
+                WarningKind::UnusedParameter,
+                format!("parameter '{}' is not used by any template", parameter),
+            )?;
+        }
+    }
     Ok(())
 }
 
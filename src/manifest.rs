@@ -0,0 +1,570 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use minijinja::Environment;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use crate::asset::Asset;
+use crate::charset::{EncodingRule, Encodings};
+use crate::encode::FormatRule;
+use crate::eol::LineEndingRule;
+use crate::expect::Expectation;
+use crate::glob;
+use crate::merge::MergeRule;
+use crate::prompt::Localized;
+use crate::report::{self, SkipReason};
+use crate::template::{
+    Delimiters, SyntaxMode, TemplateConfig, TemplateFile, Undefined, Whitespace, environment,
+};
+
+/// Name of the manifest file in the template root
+pub const MANIFEST_FILE: &str = "rte.yaml";
+
+/// Files which are only included or extended by other files, in addition to the partials of the
+/// manifest
+const DEFAULT_PARTIALS: &[&str] = &["/_partials/", "/_base/"];
+
+/// Template manifest (rte.yaml)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// Name of the template, shown by `rte info`
+    pub name: Option<String>,
+    /// What the template is for, shown by `rte info`
+    pub description: Option<String>,
+    /// Features the template can be rendered with, mapped to the features they enable in turn.
+    /// The feature `default` lists the features enabled unless --no-default-features is set.
+    pub features: BTreeMap<String, Vec<String>>,
+    /// External files which are downloaded into the output
+    pub assets: Vec<Asset>,
+    /// Commands run in the destination with --verify (e.g. `cargo check`)
+    pub verify: Vec<String>,
+    /// Merge strategies per file for `rte update`, after those given on the command line
+    pub merge: Vec<MergeRule>,
+    /// Parameters of the template in the order they are asked for
+    #[serde(deserialize_with = "ordered_map")]
+    pub parameters: Vec<(String, ParameterSpec)>,
+    /// Marks the template as deprecated
+    pub deprecated: Option<Deprecation>,
+    /// Files which are only rendered if their condition is true
+    pub files: Vec<FileCondition>,
+    /// Patterns of files left out of the output, in addition to those of .rteignore
+    pub ignore: Vec<String>,
+    /// Patterns of files copied without rendering, in addition to those of .rteignore
+    pub raw: Vec<String>,
+    /// Printed after a successful render, rendered like --git-commit-message
+    pub message: Option<String>,
+    /// Assertions on the content of the rendered files
+    pub expect: Vec<Expectation>,
+    /// Config formats the rendered files are checked against
+    pub formats: Vec<FormatRule>,
+    /// Line endings of the rendered files per pattern, instead of those of --line-endings
+    pub line_endings: Vec<LineEndingRule>,
+    /// Encodings of text files which are not UTF-8, e.g. latin1 files of legacy templates
+    pub encodings: Vec<EncodingRule>,
+    /// Environment variables the templates read with env(), `PREFIX*` for all with a prefix
+    pub env: Vec<String>,
+    /// Patterns of files which other files include, import or extend and which are not part of
+    /// the output, in addition to `_partials/` and `_base/`
+    pub partials: Vec<String>,
+    /// Delimiters of the templates instead of those of Jinja, --delimiters overrides them
+    pub syntax: Option<Delimiters>,
+    /// What references to undefined values do, for templates with optional values. --undefined
+    /// overrides it.
+    pub undefined: Option<Undefined>,
+    /// Whitespace around block tags, --trim-blocks, --lstrip-blocks and
+    /// --strip-trailing-newline override it
+    pub whitespace: Whitespace,
+    /// Add the functions of the sprig library of Helm charts, like --sprig
+    pub sprig: bool,
+    /// Filters which pipe the value through an external command, as command and arguments. They
+    /// only run with --allow-exec.
+    #[serde(deserialize_with = "ordered_map")]
+    pub exec: Vec<(String, Vec<String>)>,
+    /// Names of files and directories mapped to their names in the output, e.g. `dot_*: .*`. The
+    /// first matching entry applies.
+    #[serde(deserialize_with = "ordered_map")]
+    pub rename: Vec<(String, String)>,
+    /// Apply the built-in renames (`dot_*` to `.*`, `cargo_toml` to `Cargo.toml`) after those of
+    /// `rename`
+    pub rename_defaults: bool,
+    /// Values derived from the parameters, as expressions or templates like the conditions of
+    /// files. Templates get them as `computed`, each one can use those before it.
+    #[serde(deserialize_with = "ordered_map")]
+    pub computed: Vec<(String, String)>,
+}
+
+/// Type of a parameter value, which also selects how it is asked for. Values given as text, like
+/// those of --set or the prompt, are converted to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterType {
+    String,
+    /// Asked as yes/no question
+    Boolean,
+    Integer,
+    Number,
+    /// One of the choices, picked from a numbered list
+    Enum,
+    /// Any number of the choices as a list, comma separated as text
+    Multiselect,
+    /// A string which is not shown while it is typed and not recorded in the answers
+    Password,
+}
+
+impl ParameterType {
+    /// Convert a value to the type, text is parsed
+    pub fn convert(self, value: Value) -> Option<Value> {
+        let is_text = matches!(
+            self,
+            ParameterType::String | ParameterType::Enum | ParameterType::Password
+        );
+        let text = match (self, value) {
+            (_, Value::String(text)) if is_text => return Some(Value::String(text)),
+            (_, Value::Bool(b)) if is_text => return Some(Value::String(b.to_string())),
+            (_, Value::Number(n)) if is_text => return Some(Value::String(n.to_string())),
+            (ParameterType::Multiselect, Value::Array(items)) => {
+                return items
+                    .into_iter()
+                    .map(|item| ParameterType::String.convert(item))
+                    .collect::<Option<Vec<_>>>()
+                    .map(Value::Array);
+            }
+            (ParameterType::Boolean, Value::Bool(b)) => return Some(Value::Bool(b)),
+            (ParameterType::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => {
+                return Some(Value::Number(n));
+            }
+            (ParameterType::Number, Value::Number(n)) => return Some(Value::Number(n)),
+            (_, Value::String(text)) => text,
+            _ => return None,
+        };
+        let text = text.trim();
+        match self {
+            ParameterType::String | ParameterType::Enum | ParameterType::Password => None,
+            ParameterType::Multiselect => Some(Value::Array(
+                text.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(Value::from)
+                    .collect(),
+            )),
+            ParameterType::Boolean => match text.to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Some(Value::Bool(true)),
+                "false" | "no" | "n" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            ParameterType::Integer => text.parse::<i64>().ok().map(Value::from),
+            ParameterType::Number => text
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number),
+        }
+    }
+}
+
+impl std::fmt::Display for ParameterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ParameterType::String => "string",
+            ParameterType::Boolean => "boolean",
+            ParameterType::Integer => "integer",
+            ParameterType::Number => "number",
+            ParameterType::Enum => "enum",
+            ParameterType::Multiselect => "multiselect",
+            ParameterType::Password => "password",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Condition of the files matching a pattern
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileCondition {
+    /// gitignore-like pattern of the files, a directory matches everything below it
+    pub path: String,
+    /// Expression like `values.docker` or template like `{{ values.docker }}`. A template is
+    /// false if it renders to an empty string, `false`, `no`, `0` or `none`.
+    pub when: String,
+}
+
+/// Why a template should not be used anymore and what to use instead
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Deprecation {
+    pub message: Option<String>,
+    /// Source of the template which replaces this one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub successor: Option<String>,
+}
+
+/// Declaration of a template parameter. Texts can have a variant per locale.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParameterSpec {
+    /// Shown before asking for the parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<Localized>,
+    /// Label of the prompt, defaults to the name of the parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<Localized>,
+    /// Values the answer has to be one of
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// Type the value is converted to, values are kept as they are without
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ParameterType>,
+    /// Value used if the parameter is not set, also offered by the prompt. Strings are
+    /// templates rendered with the parameters before, e.g. `{{ name | kebab_case }}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// The value is masked in the output of rte and not recorded, like that of a password
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+    /// Regular expression the whole value has to match, e.g. `[a-z0-9]([-a-z0-9]*[a-z0-9])?`
+    /// for Kubernetes names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Smallest number allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Largest number allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Fewest characters of a text or items of a list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    /// Most characters of a text or items of a list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+}
+
+impl ParameterSpec {
+    /// Whether the value is masked and not recorded in the answers
+    pub fn is_secret(&self) -> bool {
+        self.secret || self.kind == Some(ParameterType::Password)
+    }
+
+    /// What is wrong with a value according to the validation rules, if anything
+    pub fn validate(&self, value: &Value) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(pattern) = &self.pattern
+            && let Some(text) = display_value(value)
+            && !pattern_regex(pattern).is_ok_and(|regex| regex.is_match(&text))
+        {
+            problems.push(format!("must match '{}', got {}", pattern, value));
+        }
+        if self.min.is_some() || self.max.is_some() {
+            match value.as_f64() {
+                Some(number) if self.min.is_some_and(|min| number < min) => problems.push(format!(
+                    "must be at least {}, got {}",
+                    self.min.unwrap_or(number),
+                    value
+                )),
+                Some(number) if self.max.is_some_and(|max| number > max) => problems.push(format!(
+                    "must be at most {}, got {}",
+                    self.max.unwrap_or(number),
+                    value
+                )),
+                Some(_) => {}
+                None => problems.push(format!("must be a number, got {}", value)),
+            }
+        }
+        let length = match value {
+            Value::String(text) => Some((text.chars().count(), "characters")),
+            Value::Array(items) => Some((items.len(), "items")),
+            _ => None,
+        };
+        if let Some((length, unit)) = length {
+            if let Some(min) = self.min_length
+                && length < min
+            {
+                problems.push(format!(
+                    "must have at least {} {}, got {}",
+                    min, unit, length
+                ));
+            }
+            if let Some(max) = self.max_length
+                && length > max
+            {
+                problems.push(format!(
+                    "must have at most {} {}, got {}",
+                    max, unit, length
+                ));
+            }
+        }
+        problems
+    }
+
+    /// The default as written in the manifest, for display
+    pub fn default_text(&self) -> Option<String> {
+        display_value(self.default.as_ref()?)
+    }
+
+    /// The default with string defaults rendered with the parameters given so far
+    pub fn default_value(
+        &self,
+        env: &Environment,
+        params: &Map<String, Value>,
+    ) -> Result<Option<Value>> {
+        match &self.default {
+            Some(Value::String(template)) => env
+                .render_str(template, params)
+                .map(|default| Some(Value::String(default)))
+                .with_context(|| format!("Failed to render default '{}'", template)),
+            default => Ok(default.clone()),
+        }
+    }
+}
+
+/// Environment the defaults are rendered in: Jinja syntax with the parameters on the root, like
+/// the defaults of copier.yml
+pub fn default_environment() -> Environment<'static> {
+    environment(&TemplateConfig {
+        syntax: SyntaxMode::Jinja,
+        root_value: None,
+        ..Default::default()
+    })
+}
+
+/// A value as the prompt shows it
+pub fn display_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+impl Manifest {
+    pub fn parse(content: &[u8]) -> Result<Self> {
+        let manifest: Self = serde_yaml::from_slice(content)
+            .with_context(|| format!("Failed to parse {}", MANIFEST_FILE))?;
+        if let Some(syntax) = &manifest.syntax {
+            syntax
+                .syntax_config()
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("syntax of {}", MANIFEST_FILE))?;
+        }
+        Encodings::new(&manifest.encodings)
+            .with_context(|| format!("encodings of {}", MANIFEST_FILE))?;
+        if let Some((name, _)) = manifest.exec.iter().find(|(_, command)| command.is_empty()) {
+            anyhow::bail!("exec filter '{}' of {} has no command", name, MANIFEST_FILE);
+        }
+        for (name, spec) in &manifest.parameters {
+            if let Some(pattern) = &spec.pattern {
+                pattern_regex(pattern).with_context(|| {
+                    format!("pattern of the parameter '{}' of {}", name, MANIFEST_FILE)
+                })?;
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Add the defaults of the declared parameters which are not set, convert the values of
+    /// typed parameters and validate them. The error lists all invalid values.
+    pub fn apply_parameter_specs(&self, params: &mut Map<String, Value>) -> Result<()> {
+        let env = default_environment();
+        let mut errors = Vec::new();
+        for (name, spec) in &self.parameters {
+            let value = match params.remove(name) {
+                Some(value) => value,
+                None => match spec
+                    .default_value(&env, params)
+                    .with_context(|| format!("Failed to apply the default of '{}'", name))?
+                {
+                    Some(default) => default,
+                    None => continue,
+                },
+            };
+            let value = match spec.kind {
+                Some(kind) => match kind.convert(value.clone()) {
+                    Some(value) => value,
+                    None => {
+                        errors.push(format!(
+                            "parameter '{}' must be of type {}, got {}",
+                            name, kind, value
+                        ));
+                        continue;
+                    }
+                },
+                None => value,
+            };
+            if matches!(
+                spec.kind,
+                Some(ParameterType::Enum | ParameterType::Multiselect)
+            ) {
+                let values = match &value {
+                    Value::Array(items) => items.iter().collect(),
+                    value => vec![value],
+                };
+                for value in values {
+                    if !spec.choices.iter().any(|choice| value == choice) {
+                        errors.push(format!(
+                            "parameter '{}' must be one of {}, got {}",
+                            name,
+                            spec.choices.join(", "),
+                            value
+                        ));
+                    }
+                }
+            }
+            for problem in spec.validate(&value) {
+                errors.push(format!("parameter '{}' {}", name, problem));
+            }
+            params.insert(name.clone(), value);
+        }
+        match errors.as_slice() {
+            [] => Ok(()),
+            [error] => anyhow::bail!("{}", error),
+            errors => anyhow::bail!(
+                "{} invalid parameters:\n  {}",
+                errors.len(),
+                errors.join("\n  ")
+            ),
+        }
+    }
+
+    /// Remove the files whose condition is false for the parameters (as the templates get them)
+    pub fn apply_file_conditions(
+        &self,
+        files: &mut Vec<TemplateFile>,
+        params: &Value,
+        config: &TemplateConfig,
+    ) -> Result<()> {
+        let env = environment(config);
+        let params = config.context(params.clone());
+        let mut excluded = Vec::new();
+        for condition in &self.files {
+            let included = if is_template(&condition.when) {
+                let rendered = env.render_str(&condition.when, &params);
+                rendered.map(|text| {
+                    let text = text.trim().to_lowercase();
+                    !["", "false", "no", "0", "none"].contains(&text.as_str())
+                })
+            } else {
+                env.compile_expression(&condition.when)
+                    .and_then(|e| e.eval(&params))
+                    .map(|value| value.is_true())
+            }
+            .with_context(|| format!("Failed to evaluate condition '{}'", condition.when))?;
+            if !included {
+                excluded.push((
+                    condition,
+                    glob::gitignore_set(std::slice::from_ref(&condition.path))?,
+                ));
+            }
+        }
+        files.retain(
+            |file| match excluded.iter().find(|(_, set)| set.is_match(&file.path)) {
+                Some((condition, _)) => {
+                    report::skip(
+                        &file.path,
+                        SkipReason::Conditional {
+                            condition: condition.when.clone(),
+                        },
+                    );
+                    false
+                }
+                None => true,
+            },
+        );
+        Ok(())
+    }
+
+    /// Evaluate the computed values in order. Templates render to text, expressions keep the type
+    /// of their value (e.g. a list or a number).
+    pub fn computed_values(
+        &self,
+        params: &Value,
+        config: &TemplateConfig,
+    ) -> Result<Map<String, Value>> {
+        let env = environment(config);
+        let mut context = config.context(params.clone());
+        let mut computed = Map::new();
+        for (name, text) in &self.computed {
+            let value = if is_template(text) {
+                env.render_str(text, &context).map(Value::String)
+            } else {
+                env.compile_expression(text)
+                    .and_then(|e| e.eval(&context))
+                    .and_then(|value| {
+                        serde_json::to_value(&value).map_err(|e| {
+                            minijinja::Error::new(
+                                minijinja::ErrorKind::InvalidOperation,
+                                e.to_string(),
+                            )
+                        })
+                    })
+            }
+            .with_context(|| format!("Failed to evaluate the computed value '{}'", name))?;
+            computed.insert(name.clone(), value);
+            if let Value::Object(context) = &mut context {
+                context.insert("computed".to_owned(), Value::Object(computed.clone()));
+            }
+        }
+        Ok(computed)
+    }
+
+    /// Remove the files which are only included or extended by other files from the output
+    pub fn remove_partials(&self, files: &mut Vec<TemplateFile>) -> Result<()> {
+        let patterns: Vec<String> = DEFAULT_PARTIALS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(self.partials.iter().cloned())
+            .collect();
+        let set = glob::gitignore_set(&patterns)?;
+        files.retain(|file| {
+            let partial = set.is_match(&file.path);
+            if partial {
+                report::skip(&file.path, SkipReason::Partial);
+            }
+            !partial
+        });
+        Ok(())
+    }
+}
+
+/// The pattern of a parameter, which has to match the whole value
+fn pattern_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::Regex::new(&format!("^(?:{})$", pattern))
+}
+
+/// Whether a condition or computed value is a template rather than an expression
+fn is_template(text: &str) -> bool {
+    ["{{", "${{", "{%"].iter().any(|start| text.contains(start))
+}
+
+/// Deserialize a mapping keeping the order of its entries
+fn ordered_map<'de, D, T>(deserializer: D) -> Result<Vec<(String, T)>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    serde_yaml::Mapping::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| {
+            let key = serde_yaml::from_value(key).map_err(D::Error::custom)?;
+            let value = serde_yaml::from_value(value).map_err(D::Error::custom)?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Take the manifest out of the template files. The manifest itself is never rendered.
+pub fn split_manifest(files: Vec<TemplateFile>) -> Result<(Manifest, Vec<TemplateFile>)> {
+    let mut manifest = None;
+    let mut rest = Vec::with_capacity(files.len());
+    for file in files {
+        if file.path == Path::new(MANIFEST_FILE) {
+            manifest = Some(Manifest::parse(&file.content)?);
+            report::skip(file.path, SkipReason::Config);
+        } else {
+            rest.push(file);
+        }
+    }
+    Ok((manifest.unwrap_or_default(), rest))
+}
@@ -0,0 +1,102 @@
+//! Man pages (`rte mangen`) for packagers to ship with the binary. The page of rte has sections
+//! about the topics which are not an option of their own, `rte --help` shows them as well.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap_mangen::Man;
+use roff::{Roff, roman};
+
+/// Sections of the page of rte besides the options and commands, as heading and paragraphs
+const TOPICS: [(&str, &[&str]); 3] = [
+    (
+        "SOURCES",
+        &[
+            "A source is a directory, a tar archive (plain or compressed with gzip, xz, bzip2 or \
+             zstd), a zip archive, a single file, a .rtepkg bundle, the name of a template in the \
+             registry or a URL:",
+            "gitlab://host/group/project[@ref][!asset] and github://host/owner/repo[@ref][!asset] \
+             for repositories at a branch, tag or commit, or for the asset of a release (without \
+             @ref of the latest release). s3://bucket/key and gs://bucket/object for archives in \
+             object storage. builtin://NAME for the starter templates shipped with rte.",
+            "--template-path selects a subdirectory of the source, --overlay layers other sources \
+             over it and --fallback names equivalent sources tried if it can not be opened.",
+        ],
+    ),
+    (
+        "PARAMETERS",
+        &[
+            "Parameters are applied in this order, later ones override earlier ones: the default \
+             parameters of a registry entry, the recorded answers of rte update, the parameter \
+             files of -p (nested maps are merged key by key), --set, --set-json, --set-file and \
+             --set-secret.",
+            "Declared parameters of rte.yaml which are still missing get their default or are \
+             asked for (not with --no-input), then they are validated and the derived values \
+             are added. --context replaces all of this with the parameters of an exported \
+             context.",
+            "Templates get the parameters under values (--root-key, --parameters-on-root).",
+        ],
+    ),
+    (
+        "SYNTAX",
+        &[
+            "--syntax jinja (the default) renders templates with MiniJinja. --syntax backstage \
+             (or --backstage) uses ${{ }} for expressions like Backstage software templates. \
+             --syntax handlebars renders Handlebars/Mustache templates.",
+            "--delimiters or syntax in rte.yaml set other delimiters for Jinja, --trim-blocks and \
+             --lstrip-blocks its whitespace control and --undefined what references to undefined \
+             values do.",
+        ],
+    ),
+];
+
+/// The topics as text after the options of `rte --help`
+pub fn long_help() -> String {
+    let topics: Vec<String> = TOPICS
+        .iter()
+        .map(|(heading, paragraphs)| {
+            let mut heading = heading.to_lowercase();
+            heading[..1].make_ascii_uppercase();
+            format!("{}:\n  {}", heading, paragraphs.join("\n\n  "))
+        })
+        .collect();
+    topics.join("\n\n")
+}
+
+/// Write the page of rte, with the topics before the version
+pub fn write(cmd: clap::Command, out: &mut dyn Write) -> Result<()> {
+    // like clap_mangen::generate_to, which writes no page for it
+    let man = Man::new(cmd.disable_help_subcommand(true));
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    man.render_subcommands_section(out)?;
+    let mut roff = Roff::new();
+    for (heading, paragraphs) in TOPICS {
+        roff.control("SH", [heading]);
+        for (i, paragraph) in paragraphs.iter().enumerate() {
+            if i > 0 {
+                roff.control("PP", []);
+            }
+            roff.text([roman(*paragraph)]);
+        }
+    }
+    out.write_all(roff.to_roff().as_bytes())?;
+    man.render_version_section(out)?;
+    Ok(())
+}
+
+/// Write the page of rte (rte.1) and of each command (rte-render.1, rte-registry-add.1, ...)
+/// into a directory
+pub fn write_to(cmd: clap::Command, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    clap_mangen::generate_to(cmd.clone(), dir)
+        .with_context(|| format!("Failed to write the man pages to {}", dir.display()))?;
+    let path = dir.join("rte.1");
+    let mut file = std::fs::File::create(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    write(cmd, &mut file)
+}
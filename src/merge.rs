@@ -0,0 +1,261 @@
+//! Merging of rendered files into a destination on `rte update`.
+//!
+//! The base of the three-way merge is the template rendered with the recorded answers, "ours" is
+//! the file in the destination (possibly edited since) and "theirs" the newly rendered file. The
+//! strategy is selected per file by glob, changes which can not be merged cleanly are written
+//! with conflict markers.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, DiffOp, capture_diff_slices};
+
+use crate::glob;
+use crate::report::{self, SkipReason};
+use crate::template::{FileKind, TemplateFile};
+use crate::warnings::{self, WarningKind};
+
+/// How to combine the file in the destination with the newly rendered one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Keep the file in the destination
+    Ours,
+    /// Take the newly rendered file
+    Theirs,
+    /// Three-way merge, conflicting changes of both sides are kept one after the other
+    Union,
+    /// Three-way merge, conflicting changes are written with conflict markers
+    #[default]
+    ThreeWay,
+}
+
+/// Merge strategy for the files matching a gitignore-like pattern
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MergeRule {
+    pub path: String,
+    pub strategy: MergeStrategy,
+}
+
+/// Parse `GLOB=STRATEGY`, e.g. `Cargo.lock=theirs`
+pub fn parse_merge_rule(s: &str) -> Result<MergeRule, String> {
+    let (path, strategy) = s
+        .rsplit_once('=')
+        .ok_or("expected format: GLOB=ours|theirs|union|three-way")?;
+    Ok(MergeRule {
+        path: path.to_owned(),
+        strategy: MergeStrategy::from_str(strategy, false)?,
+    })
+}
+
+/// Result of a three-way merge of text
+#[derive(Debug, PartialEq)]
+pub struct Merged {
+    pub content: String,
+    /// Number of conflicting hunks
+    pub conflicts: usize,
+}
+
+/// Merges newly rendered files into the destination
+pub struct Merger {
+    destination: PathBuf,
+    rules: Vec<(GlobSet, MergeStrategy)>,
+    /// Files rendered with the recorded answers
+    base: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl Merger {
+    /// The first matching rule selects the strategy, files without a rule are merged three-way
+    pub fn new(
+        destination: &Path,
+        rules: &[MergeRule],
+        base: HashMap<PathBuf, Vec<u8>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            destination: destination.to_path_buf(),
+            rules: rules
+                .iter()
+                .map(|rule| {
+                    Ok((
+                        glob::gitignore_set(std::slice::from_ref(&rule.path))?,
+                        rule.strategy,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            base,
+        })
+    }
+
+    fn strategy(&self, path: &Path) -> MergeStrategy {
+        self.rules
+            .iter()
+            .find(|(set, _)| set.is_match(path))
+            .map(|(_, strategy)| *strategy)
+            .unwrap_or_default()
+    }
+
+    /// Merge a rendered file with the file in the destination. Returns None if the destination
+    /// is kept as it is.
+    pub fn merge(&self, mut file: TemplateFile) -> Result<Option<TemplateFile>> {
+        let dest_path = self.destination.join(&file.path);
+        match file.kind {
+            FileKind::Dir => return Ok((!dest_path.is_dir()).then_some(file)),
+            FileKind::Symlink => {
+                let unchanged = fs::read_link(&dest_path)
+                    .is_ok_and(|target| target.as_os_str().as_encoded_bytes() == file.content);
+                if unchanged {
+                    report::skip(file.path, SkipReason::Unchanged);
+                    return Ok(None);
+                }
+                return Ok(Some(file));
+            }
+            FileKind::File => {}
+        }
+        let ours = match fs::read(&dest_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Some(file)),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read file: {}", dest_path.display()));
+            }
+        };
+        if ours == file.content {
+            report::skip(file.path, SkipReason::Unchanged);
+            return Ok(None);
+        }
+
+        let strategy = self.strategy(&file.path);
+        let union = match strategy {
+            MergeStrategy::Ours => {
+                report::skip(file.path, SkipReason::Conflict { strategy });
+                return Ok(None);
+            }
+            MergeStrategy::Theirs => return Ok(Some(file)),
+            MergeStrategy::Union => true,
+            MergeStrategy::ThreeWay => false,
+        };
+
+        let base = self.base.get(&file.path).map(Vec::as_slice).unwrap_or(b"");
+        let (Ok(base), Ok(ours_text), Ok(theirs)) = (
+            std::str::from_utf8(base),
+            std::str::from_utf8(&ours),
+            std::str::from_utf8(&file.content),
+        ) else {
+            warnings::emit(
+                WarningKind::MergeConflict,
+                format!(
+                    "{} is not valid UTF8 and can not be merged, keeping the destination",
+                    file.path.display()
+                ),
+            )?;
+            report::skip(file.path, SkipReason::Conflict { strategy });
+            return Ok(None);
+        };
+
+        let merged = merge_text(base, ours_text, theirs, union);
+        if merged.conflicts > 0 {
+            warnings::emit(
+                WarningKind::MergeConflict,
+                format!(
+                    "{} conflicts in {}, resolve the conflict markers",
+                    merged.conflicts,
+                    file.path.display()
+                ),
+            )?;
+        }
+        if merged.content.as_bytes() == ours {
+            report::skip(file.path, SkipReason::Unchanged);
+            return Ok(None);
+        }
+        file.content = merged.content.into_bytes();
+        Ok(Some(file))
+    }
+}
+
+/// Three-way merge of text by lines. Changes of only one side are taken, changes of both sides
+/// conflict unless they are identical. With `union` conflicting lines of both sides are kept
+/// (ours first), otherwise they are surrounded by conflict markers.
+pub fn merge_text(base: &str, ours: &str, theirs: &str, union: bool) -> Merged {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let to_ours = matching_lines(&base, &ours);
+    let to_theirs = matching_lines(&base, &theirs);
+
+    let mut merged = Merged {
+        content: String::new(),
+        conflicts: 0,
+    };
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // lines unchanged on both sides
+        if b < base.len() && to_ours[b] == Some(o) && to_theirs[b] == Some(t) {
+            merged.content.push_str(base[b]);
+            (b, o, t) = (b + 1, o + 1, t + 1);
+            continue;
+        }
+
+        // the changed chunk ends at the next base line both sides still have
+        let (end_b, end_o, end_t) = (b..base.len())
+            .find_map(|i| Some((i, to_ours[i]?, to_theirs[i]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        if (end_b, end_o, end_t) == (b, o, t) {
+            break;
+        }
+
+        let (chunk_b, chunk_o, chunk_t) = (&base[b..end_b], &ours[o..end_o], &theirs[t..end_t]);
+        if chunk_o == chunk_b || chunk_o == chunk_t {
+            merged.content.extend(chunk_t.iter().copied());
+        } else if chunk_t == chunk_b {
+            merged.content.extend(chunk_o.iter().copied());
+        } else {
+            merged.conflicts += 1;
+            if !union {
+                merged.content.push_str("<<<<<<< destination\n");
+            }
+            push_lines(&mut merged.content, chunk_o);
+            if !union {
+                merged.content.push_str("=======\n");
+            }
+            push_lines(&mut merged.content, chunk_t);
+            if !union {
+                merged.content.push_str(">>>>>>> template\n");
+            }
+        }
+        (b, o, t) = (end_b, end_o, end_t);
+    }
+    merged
+}
+
+/// For every line of `base` the index of the same line in `other`, if it was kept
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matching = vec![None; base.len()];
+    for op in capture_diff_slices(Algorithm::Myers, base, other) {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = op
+        {
+            for i in 0..len {
+                matching[old_index + i] = Some(new_index + i);
+            }
+        }
+    }
+    matching
+}
+
+/// Append lines, making sure the last one ends with a newline so markers start on their own line
+fn push_lines(content: &mut String, lines: &[&str]) {
+    for line in lines {
+        content.push_str(line);
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+}
@@ -0,0 +1,73 @@
+//! Texts around the output like commit messages and branch names. They are templates as well,
+//! rendered with the parameters of the template files and information about the template under
+//! `template` (e.g. `{{ values.name }}: bootstrap from {{ template.source }}`).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use minijinja::Environment;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::template::{FileKind, TemplateConfig, TemplateFile, environment};
+
+/// Where the rendered template comes from
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateInfo {
+    pub source: String,
+    pub template_path: Option<String>,
+    /// Commit the source is pinned to in rte.lock
+    pub commit: Option<String>,
+    /// Version of rte
+    pub rte_version: String,
+}
+
+impl TemplateInfo {
+    /// What template files get as `rte`: where the template comes from, the time of the render
+    /// and the paths of all files in the template (before their names are rendered)
+    pub fn render_context(&self, files: &[TemplateFile], now: DateTime<Utc>) -> Value {
+        let mut paths: Vec<String> = files
+            .iter()
+            .filter(|file| file.kind == FileKind::File)
+            .map(|file| file.path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        paths.sort();
+        serde_json::json!({
+            "version": self.rte_version,
+            "source": self.source,
+            "template_path": self.template_path,
+            "commit": self.commit,
+            "timestamp": now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "files": paths,
+        })
+    }
+}
+
+/// Renders texts with the parameters of the template
+pub struct Messages {
+    env: Environment<'static>,
+    context: Value,
+}
+
+impl Messages {
+    /// The parameters are passed like to the template files (under the root key, if any). The
+    /// template information is not set if a parameter on the root is named `template`.
+    pub fn new(config: &TemplateConfig, params: &Value, template: TemplateInfo) -> Result<Self> {
+        let mut context = config.context(params.clone());
+        if let Value::Object(context) = &mut context {
+            context
+                .entry("template")
+                .or_insert(serde_json::to_value(template)?);
+        }
+        Ok(Self {
+            env: environment(config),
+            context,
+        })
+    }
+
+    /// Render a text, `name` is used in errors (e.g. the option it was given with)
+    pub fn render(&self, name: &str, text: &str) -> Result<String> {
+        self.env
+            .render_str(text, &self.context)
+            .with_context(|| format!("Failed to render {}", name))
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::source::FileIter;
+use crate::template::TemplateFile;
+
+/// A named source taking part in a layered render
+pub struct Layer {
+    pub name: String,
+    pub files: FileIter,
+}
+
+/// A file of an earlier layer which got replaced by a later layer
+#[derive(Debug, PartialEq)]
+pub struct Override {
+    pub path: PathBuf,
+    pub overridden: String,
+    pub by: String,
+}
+
+/// Result of merging layers: the files in order of first appearance and all overrides
+pub struct Merged {
+    pub files: Vec<TemplateFile>,
+    pub overrides: Vec<Override>,
+}
+
+/// Merge layers into one set of files. Files of later layers replace files with the same path of
+/// earlier layers. This happens before templating, hence paths are compared unrendered.
+pub fn merge_layers(layers: Vec<Layer>) -> Result<Merged> {
+    let mut files: Vec<TemplateFile> = Vec::new();
+    // path -> (index in files, name of layer which provided the file)
+    let mut index: HashMap<PathBuf, (usize, String)> = HashMap::new();
+    let mut overrides = Vec::new();
+
+    for layer in layers {
+        for file in layer.files {
+            let file = file?;
+            match index.get_mut(&file.path) {
+                Some((pos, provider)) => {
+                    overrides.push(Override {
+                        path: file.path.clone(),
+                        overridden: std::mem::replace(provider, layer.name.clone()),
+                        by: layer.name.clone(),
+                    });
+                    files[*pos] = file;
+                }
+                None => {
+                    index.insert(file.path.clone(), (files.len(), layer.name.clone()));
+                    files.push(file);
+                }
+            }
+        }
+    }
+
+    Ok(Merged { files, overrides })
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+/// A parameter file given with -p, optionally mounted under a namespace (`-p team.yaml:team`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterFile {
+    pub path: PathBuf,
+    pub namespace: Option<String>,
+}
+
+/// Parse `PATH[:NAMESPACE]`. The part after the last colon is only a namespace if it is a plain
+/// key, so Windows paths like `C:\params.yaml` keep working.
+pub fn parse_parameter_file(s: &str) -> Result<ParameterFile, String> {
+    if let Some((path, namespace)) = s.rsplit_once(':')
+        && !path.is_empty()
+        && !namespace.is_empty()
+        && namespace
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Ok(ParameterFile {
+            path: PathBuf::from(path),
+            namespace: Some(namespace.to_owned()),
+        });
+    }
+    Ok(ParameterFile {
+        path: PathBuf::from(s),
+        namespace: None,
+    })
+}
+
+pub fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    let pos = s.find('=').ok_or("expected format: KEY=VALUE")?;
+    Ok((s[..pos].to_string(), s[pos + 1..].to_string()))
+}
+
+/// Parse `KEY=JSON` of --set-json
+pub fn parse_key_json(s: &str) -> Result<(String, Value), String> {
+    let (key, json) = s.split_once('=').ok_or("expected format: KEY=JSON")?;
+    let value = serde_json::from_str(json).map_err(|e| format!("invalid JSON: {}", e))?;
+    Ok((key.to_owned(), value))
+}
+
+/// Parse `KEY=PATH` of --set-file
+pub fn parse_key_path(s: &str) -> Result<(String, PathBuf), String> {
+    let (key, path) = s.split_once('=').ok_or("expected format: KEY=PATH")?;
+    Ok((key.to_owned(), PathBuf::from(path)))
+}
+
+/// Set a value by a dotted key (`db.port`), creating the maps on the way. Values on the way which
+/// are no maps are replaced.
+pub fn insert_dotted(params: &mut Map<String, Value>, key: &str, value: Value) {
+    let (first, rest) = match key.split_once('.') {
+        Some((first, rest)) if !first.is_empty() && !rest.is_empty() => (first, rest),
+        _ => {
+            params.insert(key.to_owned(), value);
+            return;
+        }
+    };
+    let entry = params
+        .entry(first.to_owned())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !entry.is_object() {
+        *entry = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = entry {
+        insert_dotted(map, rest, value);
+    }
+}
+
+/// Apply --set, --set-json and --set-file in this order
+pub fn apply_overrides(
+    params: &mut Map<String, Value>,
+    set: &[(String, String)],
+    set_json: &[(String, Value)],
+    set_file: &[(String, PathBuf)],
+) -> Result<()> {
+    for (key, value) in set {
+        insert_dotted(params, key, Value::String(value.clone()));
+    }
+    for (key, value) in set_json {
+        insert_dotted(params, key, value.clone());
+    }
+    for (key, path) in set_file {
+        let content = fs::read_to_string(path).with_context(|| {
+            format!("Failed to read the value of '{}': {}", key, path.display())
+        })?;
+        insert_dotted(params, key, Value::String(content));
+    }
+    Ok(())
+}
+
+/// Path of the parameter file which is read from stdin (`-p -`)
+const STDIN: &str = "-";
+
+/// Read and merge parameter files into `params` (later files override earlier)
+pub fn merge_files(params: &mut Map<String, Value>, files: &[ParameterFile]) -> Result<()> {
+    if files.iter().filter(|f| f.path == Path::new(STDIN)).count() > 1 {
+        anyhow::bail!("parameters can only be read once from stdin (-p -)");
+    }
+    for file in files {
+        let path = &file.path;
+        tracing::trace!("reading parameters from {}", path.display());
+        let content = match path == Path::new(STDIN) {
+            true => {
+                let mut content = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut content)
+                    .context("Failed to read parameters from stdin")?;
+                content
+            }
+            false => fs::read_to_string(path)
+                .with_context(|| format!("Failed to read parameters file: {}", path.display()))?,
+        };
+        let file_params: Value = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse parameters file: {}", path.display()))?;
+        let Value::Object(map) = file_params else {
+            continue;
+        };
+
+        match &file.namespace {
+            None => deep_merge(params, map),
+            Some(namespace) => {
+                let mounted = params
+                    .entry(namespace.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                match mounted {
+                    Value::Object(mounted) => deep_merge(mounted, map),
+                    other => *other = Value::Object(map),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merge `overrides` into `params`: maps are merged key by key, so overriding one nested value
+/// keeps its siblings. Everything else, lists included, is replaced as a whole.
+pub fn deep_merge(params: &mut Map<String, Value>, overrides: Map<String, Value>) {
+    for (key, value) in overrides {
+        match (params.get_mut(&key), value) {
+            (Some(Value::Object(existing)), Value::Object(value)) => deep_merge(existing, value),
+            (_, value) => {
+                params.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Read a context written by [`save_context`]. The computed values are left out, they are derived
+/// from the parameters again.
+pub fn load_context(path: &Path) -> Result<Map<String, Value>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read context: {}", path.display()))?;
+    match serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse context: {}", path.display()))?
+    {
+        Value::Object(mut context) => {
+            context.remove("computed");
+            Ok(context)
+        }
+        _ => anyhow::bail!("context {} is not a JSON object", path.display()),
+    }
+}
+
+/// Write the parameters as the templates get them
+pub fn save_context(path: &Path, params: &Value) -> Result<()> {
+    let mut content = serde_json::to_string_pretty(params)?;
+    content.push('\n');
+    fs::write(path, content).with_context(|| format!("Failed to write context: {}", path.display()))
+}
@@ -0,0 +1,111 @@
+//! Machine-readable progress of a render (`--progress json`) for wrappers like GUIs and IDE
+//! plugins: one JSON event per line, on stdout unless stdout carries the output (`-` as
+//! destination or --dry-run), then on stderr.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::cancel;
+use crate::secrets;
+
+/// Format of the progress events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    /// Newline-delimited JSON
+    Json,
+}
+
+/// Step of a render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    /// Fetch the source and load the template
+    Load,
+    /// Render and write the files
+    Render,
+    /// Commit the output (--git-init, repository destinations)
+    Commit,
+    /// Run the verification commands of the template
+    Verify,
+    /// Push to the repository destination
+    Publish,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event {
+    Phase {
+        phase: Phase,
+    },
+    /// A file is rendered, `percent` of the files are done. The path is masked, files might be
+    /// named after secrets.
+    File {
+        path: String,
+        done: usize,
+        total: usize,
+        percent: usize,
+    },
+    Done,
+    /// The run was stopped with Ctrl-C or SIGTERM
+    Cancelled,
+    Error {
+        message: String,
+    },
+}
+
+/// Where events are written, unset without --progress
+static STDERR: OnceLock<bool> = OnceLock::new();
+
+/// Enable progress events for this run
+pub fn configure(format: Option<ProgressFormat>, stderr: bool) {
+    if let Some(ProgressFormat::Json) = format {
+        let _ = STDERR.set(stderr);
+    }
+}
+
+fn emit(event: Event) {
+    let Some(&stderr) = STDERR.get() else {
+        return;
+    };
+    let Ok(mut line) = serde_json::to_vec(&event) else {
+        return;
+    };
+    line.push(b'\n');
+    // a wrapper which stopped reading must not fail the render
+    let _ = if stderr {
+        std::io::stderr().write_all(&line)
+    } else {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&line).and_then(|()| stdout.flush())
+    };
+}
+
+pub fn phase(phase: Phase) {
+    emit(Event::Phase { phase });
+}
+
+/// The `done`th of `total` files is rendered
+pub fn file(path: &Path, done: usize, total: usize) {
+    let percent = (done * 100).checked_div(total).unwrap_or(100).min(100);
+    emit(Event::File {
+        path: secrets::mask(&path.display().to_string()),
+        done,
+        total,
+        percent,
+    });
+}
+
+/// Report the end of the run
+pub fn finish<T>(result: &anyhow::Result<T>) {
+    match result {
+        Ok(_) => emit(Event::Done),
+        Err(e) if cancel::is_cancelled(e) => emit(Event::Cancelled),
+        Err(e) => emit(Event::Error {
+            message: secrets::mask(&format!("{:#}", e)),
+        }),
+    }
+}
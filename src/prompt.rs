@@ -0,0 +1,353 @@
+//! Interactive prompts for the parameters a template declares, with labels and descriptions in
+//! the language of the user. The simple style asks one plain line at a time with numbered
+//! choices, for screen readers and dumb terminals.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::dir::{BACKUP_SUFFIX, ConflictStrategy};
+use crate::manifest::{self, ParameterSpec, ParameterType};
+
+/// Locale used if the text has no variant for the user's locale
+const FALLBACK_LOCALE: &str = "en";
+
+/// How the questions are asked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PromptStyle {
+    /// Label and choices on one line, e.g. `License [MIT/Apache-2.0]: `
+    #[default]
+    Compact,
+    /// The label on its own line, choices as a numbered list which can be answered with the
+    /// number, and no more than one question per line (--simple-prompts)
+    Simple,
+}
+
+impl PromptStyle {
+    /// The simple style if requested or the terminal cannot do more (TERM=dumb)
+    pub fn detect(simple: bool) -> Self {
+        if simple || std::env::var("TERM").is_ok_and(|term| term == "dumb") {
+            PromptStyle::Simple
+        } else {
+            PromptStyle::Compact
+        }
+    }
+}
+
+/// A text which is either the same for all locales or has a variant per locale, e.g.
+/// `{en: Project name, de: Projektname, de-CH: Projektname}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Localized {
+    Text(String),
+    Locales(BTreeMap<String, String>),
+}
+
+impl Localized {
+    /// The variant for the first matching locale, falling back to English and then to any variant
+    pub fn get(&self, locales: &[String]) -> &str {
+        let variants = match self {
+            Localized::Text(text) => return text,
+            Localized::Locales(variants) => variants,
+        };
+        let find = |locale: &str| {
+            variants
+                .iter()
+                .find(|(key, _)| normalize(key).eq_ignore_ascii_case(locale))
+                .map(|(_, text)| text.as_str())
+        };
+        locales
+            .iter()
+            .find_map(|locale| find(locale))
+            .or_else(|| find(FALLBACK_LOCALE))
+            .or_else(|| variants.values().next().map(String::as_str))
+            .unwrap_or_default()
+    }
+}
+
+/// Locales to look up texts in, most specific first: `de_CH.UTF-8` results in `de-CH` and `de`.
+/// Without an explicit locale LC_ALL, LC_MESSAGES and LANG are used.
+pub fn locales(locale: Option<&str>) -> Vec<String> {
+    let locale = match locale {
+        Some(locale) => locale.to_owned(),
+        None => match ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        {
+            Some(locale) => locale,
+            None => return Vec::new(),
+        },
+    };
+
+    // strip encoding and modifier like in de_CH.UTF-8@euro
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    let locale = normalize(locale);
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let mut locales = vec![locale.clone()];
+    if let Some((language, _)) = locale.split_once('-') {
+        locales.push(language.to_owned());
+    }
+    locales
+}
+
+fn normalize(locale: &str) -> String {
+    locale.replace('_', "-")
+}
+
+/// Ask for the declared parameters which are not set yet. An empty answer takes the default of
+/// the parameter, rendered with the answers so far. The answers are strings, except for booleans
+/// (yes/no questions) and multi-selects (lists); other types are converted with the parameters.
+pub fn ask_missing(
+    parameters: &[(String, ParameterSpec)],
+    params: &mut Map<String, Value>,
+    locales: &[String],
+    style: PromptStyle,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<()> {
+    let env = manifest::default_environment();
+    for (name, spec) in parameters {
+        if params.contains_key(name) {
+            continue;
+        }
+        if let Some(description) = &spec.description {
+            writeln!(output, "{}", description.get(locales))?;
+        }
+        let label = spec
+            .prompt
+            .as_ref()
+            .map_or(name.as_str(), |prompt| prompt.get(locales));
+        let kind = spec.kind.unwrap_or(ParameterType::String);
+        let choices = &spec.choices;
+        // enums and multi-selects are picked from a numbered list in both styles
+        let numbered = style == PromptStyle::Simple
+            || matches!(kind, ParameterType::Enum | ParameterType::Multiselect);
+        let default_value = spec.default_value(&env, params)?;
+        let default = default_value
+            .as_ref()
+            .and_then(manifest::display_value)
+            .filter(|_| !spec.is_secret());
+        if style == PromptStyle::Simple {
+            writeln!(output, "{}", label)?;
+        }
+        if numbered {
+            for (number, choice) in choices.iter().enumerate() {
+                writeln!(output, "{}. {}", number + 1, choice)?;
+            }
+        }
+        if style == PromptStyle::Simple
+            && let Some(default) = &default
+        {
+            writeln!(output, "Default: {}", default)?;
+        }
+        let answer = loop {
+            let hint = match (kind, choices.is_empty()) {
+                (ParameterType::Boolean, _) => match default_value
+                    .clone()
+                    .and_then(|d| kind.convert(d))
+                    .as_ref()
+                    .and_then(Value::as_bool)
+                {
+                    Some(true) => " [Y/n]".to_owned(),
+                    Some(false) => " [y/N]".to_owned(),
+                    None => " [y/n]".to_owned(),
+                },
+                (ParameterType::Multiselect, false) => {
+                    format!(" [1-{}, comma separated]", choices.len())
+                }
+                (_, false) if numbered => format!(" [1-{}]", choices.len()),
+                (_, false) => format!(" [{}]", choices.join("/")),
+                (_, true) => String::new(),
+            };
+            let shown_default = match &default {
+                Some(default) if kind != ParameterType::Boolean => format!(" ({})", default),
+                _ => String::new(),
+            };
+            match (style, kind, choices.is_empty()) {
+                (PromptStyle::Compact, _, _) => {
+                    write!(output, "{}{}{}: ", label, hint, shown_default)?
+                }
+                (PromptStyle::Simple, ParameterType::Boolean, _) => {
+                    write!(output, "Answer yes or no: ")?
+                }
+                (PromptStyle::Simple, ParameterType::Multiselect, false) => write!(
+                    output,
+                    "Answer with numbers from 1 to {}, separated by commas: ",
+                    choices.len()
+                )?,
+                (PromptStyle::Simple, _, true) => write!(output, "Answer: ")?,
+                (PromptStyle::Simple, _, false) => {
+                    write!(output, "Answer with a number from 1 to {}: ", choices.len())?
+                }
+            }
+            output.flush()?;
+            let mut line = String::new();
+            let hidden = match spec.is_secret() {
+                true => hide_input(),
+                false => None,
+            };
+            let read = input
+                .read_line(&mut line)
+                .context("Failed to read answer")?;
+            if hidden.is_some() {
+                drop(hidden);
+                // the newline was not echoed either
+                writeln!(output)?;
+            }
+            if read == 0 {
+                anyhow::bail!("no answer for parameter '{}'", name);
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                match (&default_value, kind) {
+                    (Some(default), _) => break default.clone(),
+                    (None, ParameterType::Multiselect) => break Value::Array(Vec::new()),
+                    (None, _) => continue,
+                }
+            }
+            let choose = |text: &str| {
+                let chosen = match numbered {
+                    true => text
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|number| choices.get(number.checked_sub(1)?)),
+                    false => None,
+                };
+                chosen.or_else(|| choices.iter().find(|choice| *choice == text))
+            };
+            match kind {
+                ParameterType::Boolean | ParameterType::Integer | ParameterType::Number => {
+                    match kind.convert(Value::from(line)) {
+                        Some(value) => break value,
+                        None if kind == ParameterType::Boolean => {
+                            writeln!(output, "'{}' is not yes or no.", line)?
+                        }
+                        None => writeln!(output, "'{}' is not a valid {}.", line, kind)?,
+                    }
+                }
+                ParameterType::Multiselect if !choices.is_empty() => {
+                    let items: Vec<&str> = line
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .collect();
+                    match items.iter().find(|item| choose(item).is_none()) {
+                        Some(item) => writeln!(output, "'{}' is not one of the choices.", item)?,
+                        None => {
+                            break Value::Array(
+                                items
+                                    .into_iter()
+                                    .filter_map(choose)
+                                    .map(|choice| Value::from(choice.as_str()))
+                                    .collect(),
+                            );
+                        }
+                    }
+                }
+                _ if choices.is_empty() => break Value::from(line),
+                _ => match choose(line) {
+                    Some(choice) => break Value::from(choice.as_str()),
+                    None => writeln!(output, "'{}' is not one of the choices.", line)?,
+                },
+            }
+        };
+        params.insert(name.clone(), answer);
+    }
+    Ok(())
+}
+
+/// Turns off the echo of the terminal on stdin while it lives, for passwords
+struct HiddenInput {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+/// Stop echoing what is typed, if stdin is a terminal
+fn hide_input() -> Option<HiddenInput> {
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        // SAFETY: termios is plain data filled in by tcgetattr, fd 0 is stdin
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+            let mut hidden = original;
+            hidden.c_lflag &= !libc::ECHO;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &hidden) != 0 {
+                return None;
+            }
+            Some(HiddenInput { original })
+        }
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+impl Drop for HiddenInput {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: restores the settings read by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Ask what to do with a rendered file which exists with other content. Returns the answer and
+/// whether it applies to all remaining files.
+pub fn ask_conflict(
+    path: &Path,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<(ConflictStrategy, bool)> {
+    writeln!(output, "{} exists with other content.", path.display())?;
+    loop {
+        write!(
+            output,
+            "Overwrite (y), keep (n) or write as {} (b)? Uppercase applies to all files: ",
+            BACKUP_SUFFIX
+        )?;
+        output.flush()?;
+        let mut line = String::new();
+        if input
+            .read_line(&mut line)
+            .context("Failed to read answer")?
+            == 0
+        {
+            anyhow::bail!("no answer for existing file '{}'", path.display());
+        }
+        let answer = line.trim();
+        let strategy = match answer.to_ascii_lowercase().as_str() {
+            "y" => ConflictStrategy::Overwrite,
+            "n" => ConflictStrategy::Skip,
+            "b" => ConflictStrategy::Backup,
+            _ => continue,
+        };
+        let all = answer.chars().all(|c| c.is_ascii_uppercase());
+        return Ok((strategy, all));
+    }
+}
+
+/// Ask a yes/no question, no is the default
+pub fn confirm(question: &str, input: &mut impl BufRead, output: &mut impl Write) -> Result<bool> {
+    write!(output, "{} [y/N]: ", question)?;
+    output.flush()?;
+    let mut line = String::new();
+    input
+        .read_line(&mut line)
+        .context("Failed to read answer")?;
+    Ok(matches!(
+        line.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
@@ -0,0 +1,186 @@
+//! Publishing the output as a new repository: with a `gitlab://host/group/project` or
+//! `github://host/owner/repo` destination the project is created with the API of the forge and the
+//! rendered files are pushed as its initial commit. With --merge-request they are pushed to a new
+//! branch of the existing repository instead and a merge (pull) request is opened.
+
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::git;
+use crate::github::{self, GitHubSource};
+use crate::gitlab::{self, GitlabSource};
+use crate::hosts::HostPolicy;
+use crate::http;
+use crate::source::SourceOptions;
+
+/// Visibility of a created repository
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Visibility {
+    #[default]
+    Private,
+    /// Visible to all signed in users (GitLab) or members of the enterprise (GitHub Enterprise)
+    Internal,
+    Public,
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self
+            .to_possible_value()
+            .map(|v| v.get_name().to_owned())
+            .unwrap_or_default();
+        f.write_str(&name)
+    }
+}
+
+/// Repository to create for a remote destination
+#[derive(Debug)]
+pub enum Remote {
+    Gitlab(GitlabSource),
+    GitHub(GitHubSource),
+}
+
+/// Repository to create for a destination, None if the destination is local
+pub fn remote_destination(destination: &Path) -> Result<Option<Remote>> {
+    let Some(destination) = destination.to_str() else {
+        return Ok(None);
+    };
+    if destination.starts_with("gitlab://") {
+        let project = GitlabSource::parse(destination)?;
+        if project.git_ref.is_some() || project.asset.is_some() || project.path.is_some() {
+            anyhow::bail!(
+                "destination '{}' must be a project without @ref, !asset or ?path",
+                destination
+            );
+        }
+        project.namespace()?;
+        Ok(Some(Remote::Gitlab(project)))
+    } else if destination.starts_with("github://") {
+        let repository = GitHubSource::parse(destination)?;
+        if repository.git_ref.is_some() || repository.asset.is_some() {
+            anyhow::bail!(
+                "destination '{}' must be a repository without @ref or !asset",
+                destination
+            );
+        }
+        Ok(Some(Remote::GitHub(repository)))
+    } else {
+        Ok(None)
+    }
+}
+
+impl Remote {
+    /// Token for the forge and the URL to clone and push with it
+    fn credentials<'a>(&self, options: &'a SourceOptions) -> Result<(&'a str, String)> {
+        match self {
+            Remote::Gitlab(project) => {
+                let token = options.gitlab_token(&project.host).context(
+                    "GitLab destinations require a token (--gitlab-token, GITLAB_TOKEN or tokens in config.yaml)",
+                )?;
+                Ok((token, project.push_url(token)))
+            }
+            Remote::GitHub(repository) => {
+                let token = options.github_token(&repository.host).context(
+                    "GitHub destinations require a token (--github-token, GITHUB_TOKEN or tokens in config.yaml)",
+                )?;
+                Ok((token, repository.clone_url(Some(token))))
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Remote::Gitlab(project) => project.project_path.clone(),
+            Remote::GitHub(repository) => format!("{}/{}", repository.owner, repository.repo),
+        }
+    }
+}
+
+/// Create the repository and push the commit of the directory to it. Returns the URL of the
+/// repository.
+pub fn publish(
+    remote: &Remote,
+    dir: &Path,
+    visibility: Visibility,
+    options: &SourceOptions,
+) -> Result<String> {
+    let (token, push_url) = remote.credentials(options)?;
+    // the host policy restricts where templates come from, not where they are published
+    let client = http::client(options.redirects, &HostPolicy::default())?;
+    let web_url = match remote {
+        Remote::Gitlab(project) => gitlab::create_project(&client, project, token, visibility),
+        Remote::GitHub(repository) => {
+            github::create_repository(&client, repository, token, visibility)
+        }
+    }
+    .with_context(|| format!("Failed to create repository {}", remote.name()))?;
+
+    git::push(dir, &push_url, Some(token))
+        .with_context(|| format!("Failed to push to the new repository {}", web_url))?;
+    Ok(web_url)
+}
+
+/// Branch and texts of a merge request (pull request on GitHub)
+#[derive(Debug)]
+pub struct MergeRequest {
+    pub branch: String,
+    pub title: String,
+    pub description: String,
+}
+
+/// Name of the branch of a merge request unless one is given
+pub fn default_branch() -> String {
+    format!("rte/{}", chrono::Local::now().format("%Y%m%d-%H%M%S"))
+}
+
+/// Clone the existing repository into the directory and check out the branch of the merge
+/// request. Returns the default branch the merge request is opened against.
+pub fn checkout(
+    remote: &Remote,
+    dir: &Path,
+    merge_request: &MergeRequest,
+    options: &SourceOptions,
+) -> Result<String> {
+    let (token, url) = remote.credentials(options)?;
+    git::clone_branch(&url, dir, &merge_request.branch, Some(token))
+        .with_context(|| format!("Failed to clone {}", remote.name()))
+}
+
+/// Push the branch of the merge request and open it against the base branch. Returns the URL of
+/// the merge request.
+pub fn open_merge_request(
+    remote: &Remote,
+    dir: &Path,
+    base: &str,
+    merge_request: &MergeRequest,
+    options: &SourceOptions,
+) -> Result<String> {
+    let (token, url) = remote.credentials(options)?;
+    git::push(dir, &url, Some(token))
+        .with_context(|| format!("Failed to push branch {}", merge_request.branch))?;
+
+    let client = http::client(options.redirects, &HostPolicy::default())?;
+    let MergeRequest {
+        branch,
+        title,
+        description,
+    } = merge_request;
+    match remote {
+        Remote::Gitlab(project) => {
+            gitlab::create_merge_request(&client, project, token, branch, base, title, description)
+        }
+        Remote::GitHub(repository) => github::create_pull_request(
+            &client,
+            repository,
+            token,
+            branch,
+            base,
+            title,
+            description,
+        ),
+    }
+    .with_context(|| format!("Failed to open a merge request on {}", remote.name()))
+}
@@ -0,0 +1,78 @@
+//! Random values for templates (`uuid4()`, `random_string(n)`, `random_hex(n)`), e.g. for
+//! scaffolded secrets and unique names in Kubernetes manifests. By default they come from a
+//! generator seeded by the operating system, with --seed they are the same on every run for
+//! golden tests.
+
+use std::sync::{Arc, Mutex};
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+/// Characters of random_string without a character set
+const ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Generator of the random values of a render. Clones share the generator, so the environments
+/// of a render (files, messages, defaults) draw from one sequence and do not repeat values.
+#[derive(Debug, Clone)]
+pub struct Random(Arc<Mutex<StdRng>>);
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Random {
+    /// Without a seed the generator is seeded by the operating system
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Self(Arc::new(Mutex::new(rng)))
+    }
+
+    fn with_rng<T>(&self, f: impl FnOnce(&mut StdRng) -> T) -> T {
+        let mut rng = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut rng)
+    }
+
+    /// Random UUID (version 4)
+    pub fn uuid4(&self) -> String {
+        let mut bytes = [0u8; 16];
+        self.with_rng(|rng| rng.fill_bytes(&mut bytes));
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex = hex::encode(bytes);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+
+    /// `length` random characters of `chars`, letters and digits by default
+    pub fn string(&self, length: usize, chars: Option<&str>) -> Result<String, String> {
+        let chars: Vec<char> = chars.unwrap_or(ALPHANUMERIC).chars().collect();
+        if chars.is_empty() {
+            return Err("random_string needs at least one character to choose from".to_owned());
+        }
+        Ok(self.with_rng(|rng| {
+            (0..length)
+                .map(|_| chars[rng.random_range(0..chars.len())])
+                .collect()
+        }))
+    }
+
+    /// `length` random lowercase hex digits
+    pub fn hex(&self, length: usize) -> String {
+        self.with_rng(|rng| {
+            (0..length)
+                .map(|_| char::from_digit(rng.random_range(0..16), 16).unwrap_or('0'))
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use serde::Serialize;
+use url::Url;
+
+use crate::source::SourceOptions;
+use crate::{github, gitlab, http};
+
+/// Number of branches or tags requested per page from the APIs
+pub const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefKind {
+    Branch,
+    Tag,
+}
+
+impl std::fmt::Display for RefKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefKind::Branch => write!(f, "branch"),
+            RefKind::Tag => write!(f, "tag"),
+        }
+    }
+}
+
+/// A branch or tag of a repository which can be used as @ref of a source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GitRef {
+    pub kind: RefKind,
+    pub name: String,
+    /// Commit the ref points to
+    pub commit: String,
+}
+
+/// List the branches and tags of a gitlab:// or github:// source
+pub fn list(source: &str, options: &SourceOptions) -> Result<Vec<GitRef>> {
+    options.hosts.check_source(source)?;
+    let scheme = Url::parse(source).map(|url| url.scheme().to_owned());
+    let client = http::client(options.redirects, &options.hosts)?;
+    let mut refs = match scheme.as_deref() {
+        Ok("gitlab") => {
+            let gitlab = gitlab::GitlabSource::parse(source)?;
+            gitlab::list_refs(&client, &gitlab, options.gitlab_token(&gitlab.host))?
+        }
+        Ok("github") => {
+            let github = github::GitHubSource::parse(source)?;
+            github::list_refs(&client, &github, options.github_token(&github.host))?
+        }
+        _ => anyhow::bail!(
+            "listing refs is only supported for gitlab:// and github:// sources, got '{}'",
+            source
+        ),
+    };
+    refs.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+    Ok(refs)
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A named template in the registry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    /// Source of the template (any source rte accepts)
+    pub source: String,
+
+    /// Template path within the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_path: Option<String>,
+
+    /// Equivalent sources (e.g. mirrors) tried in order if the source can not be opened
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<String>,
+
+    /// Default parameters, overridden by parameter files and --set
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Local template registry mapping names to template sources
+/// (~/.config/rte/templates.yaml by default)
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Registry {
+    pub templates: BTreeMap<String, RegistryEntry>,
+}
+
+/// Default location of the registry file: $XDG_CONFIG_HOME/rte/templates.yaml or
+/// ~/.config/rte/templates.yaml
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("rte").join("templates.yaml"))
+}
+
+impl Registry {
+    /// Load the registry. A missing registry file is an empty registry.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read registry: {}", path.display()))?;
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse registry: {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create registry directory: {}", parent.display())
+            })?;
+        }
+        let content = serde_yaml::to_string(self).context("Failed to serialize registry")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write registry: {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegistryEntry> {
+        self.templates.get(name)
+    }
+
+    /// Add or replace a template. Returns the previous entry if the name was already taken.
+    pub fn add(&mut self, name: String, entry: RegistryEntry) -> Option<RegistryEntry> {
+        self.templates.insert(name, entry)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<RegistryEntry> {
+        self.templates
+            .remove(name)
+            .with_context(|| format!("template '{}' is not in the registry", name))
+    }
+}
@@ -0,0 +1,96 @@
+//! Renames of file and directory names for template repositories in which some names can not be
+//! stored as they are, e.g. because a packaging pipeline drops dotfiles or treats a nested
+//! Cargo.toml as a crate. The manifest maps names to their output names, a name ending with `*`
+//! maps all names with the prefix:
+//!
+//! ```yaml
+//! rename:
+//!   dot_*: .*
+//!   cargo_toml: Cargo.toml
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::template::TemplateFile;
+
+/// Renames applied with `rename_defaults: true` or --rename-defaults, after those of the manifest
+pub const DEFAULT_RENAMES: &[(&str, &str)] = &[
+    ("dot_*", ".*"),
+    ("cargo_toml", "Cargo.toml"),
+    ("cargo_lock", "Cargo.lock"),
+];
+
+/// Rename rules, the first matching rule applies
+#[derive(Debug, Default)]
+pub struct Renames {
+    rules: Vec<(String, String)>,
+}
+
+impl Renames {
+    pub fn new(manifest: &[(String, String)], defaults: bool) -> Self {
+        let defaults = DEFAULT_RENAMES
+            .iter()
+            .filter(|_| defaults)
+            .map(|(from, to)| (from.to_string(), to.to_string()));
+        Self {
+            rules: manifest
+                .iter()
+                .map(|(from, to)| (from.clone(), to.clone()))
+                .chain(defaults)
+                .collect(),
+        }
+    }
+
+    fn rename_name(&self, name: &str) -> Option<String> {
+        self.rules.iter().find_map(|(from, to)| {
+            match (from.strip_suffix('*'), to.strip_suffix('*')) {
+                (Some(prefix), Some(new_prefix)) => name
+                    .strip_prefix(prefix)
+                    .filter(|rest| !rest.is_empty())
+                    .map(|rest| format!("{}{}", new_prefix, rest)),
+                (Some(prefix), None) => name.starts_with(prefix).then(|| to.clone()),
+                (None, _) => (name == from).then(|| to.clone()),
+            }
+        })
+    }
+
+    /// Path with every renamed component replaced
+    pub fn rename(&self, path: &Path) -> PathBuf {
+        path.components()
+            .map(|component| match component {
+                Component::Normal(name) => match name.to_str().and_then(|n| self.rename_name(n)) {
+                    Some(renamed) => PathBuf::from(renamed),
+                    None => PathBuf::from(name),
+                },
+                component => PathBuf::from(component.as_os_str()),
+            })
+            .collect()
+    }
+
+    /// Rename the files and the paths of the verbatim files. Fails if two files get the same path.
+    pub fn apply(&self, files: &mut [TemplateFile], verbatim: &mut HashSet<PathBuf>) -> Result<()> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+        let mut renamed: HashMap<PathBuf, PathBuf> = HashMap::new();
+        for file in files.iter_mut() {
+            let path = self.rename(&file.path);
+            if let Some(other) = renamed.insert(path.clone(), file.path.clone()) {
+                anyhow::bail!(
+                    "'{}' and '{}' are both renamed to '{}'",
+                    other.display(),
+                    file.path.display(),
+                    path.display()
+                );
+            }
+            if verbatim.remove(&file.path) {
+                verbatim.insert(path.clone());
+            }
+            file.path = path;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! Report of a run (--report): the files written to the destination and the files which were
+//! skipped on the way, each with a machine-readable reason, the source the template was resolved
+//! to and the warnings. `--output json` prints it with the errors of the run on stdout.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::merge::MergeStrategy;
+use crate::secrets;
+use crate::template::TemplateFile;
+
+/// Format of the result printed at the end of a render (--output)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResultFormat {
+    /// The report with the status and errors of the run as JSON object
+    Json,
+}
+
+/// Why a file of the source is not part of the output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// Directory entry of an archive
+    Directory,
+    /// Nothing is left of the path after stripping the leading directories of an archive
+    EmptyAfterStrip,
+    /// The file is not under the template path
+    OutsideTemplatePath { template_path: PathBuf },
+    /// The file configures the template (rte.yaml, copier.yml, ...) and is not rendered
+    Config,
+    /// The file matches an ignore or exclude pattern
+    Ignored { by: String },
+    /// The condition of a conditional ignore is true (or of a conditional file false) for the
+    /// parameters
+    Conditional { condition: String },
+    /// The file is only included by other files (partials of the manifest)
+    Partial,
+    /// A part of the path renders to an empty name
+    EmptyPath,
+    /// The file does not reference any of the parameters which changed (--only-changed)
+    ParametersUnchanged,
+    /// The destination already has the same content
+    Unchanged,
+    /// The file in the destination is kept by the merge strategy
+    Conflict { strategy: MergeStrategy },
+    /// The file exists in the destination with other content and is kept (--on-conflict)
+    Existing,
+}
+
+/// A skipped file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Skipped {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub reason: SkipReason,
+}
+
+/// A file in the output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Written {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Where the template of a render came from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Source {
+    /// Source as given, a path, URL or the name of a registered template
+    pub source: String,
+    /// Source the template was read from: the registered one, pinned to the commit
+    pub resolved: String,
+    /// Branch or tag of a gitlab:// or github:// source
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Commit the ref resolved to
+    pub commit: Option<String>,
+    pub template_path: Option<String>,
+}
+
+/// A warning which was shown (or hidden by --quiet)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Warning {
+    pub kind: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    pub files: Vec<Written>,
+    pub skipped: Vec<Skipped>,
+    pub warnings: Vec<Warning>,
+    /// Errors of files which failed to render, with --dry-run all of them
+    pub errors: Vec<String>,
+}
+
+static REPORT: Mutex<Report> = Mutex::new(Report {
+    source: None,
+    files: Vec::new(),
+    skipped: Vec::new(),
+    warnings: Vec::new(),
+    errors: Vec::new(),
+});
+
+fn with_report(f: impl FnOnce(&mut Report)) {
+    // a poisoned report is still good enough for debugging
+    f(&mut REPORT.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+/// Record that a file is skipped
+pub fn skip(path: impl Into<PathBuf>, reason: SkipReason) {
+    let path = path.into();
+    tracing::trace!(
+        "skipping {}: {:?}",
+        secrets::mask(&path.display().to_string()),
+        reason
+    );
+    with_report(|report| report.skipped.push(Skipped { path, reason }));
+}
+
+/// Record that a file is part of the output
+pub fn output(file: &TemplateFile) {
+    tracing::trace!(
+        "writing {}",
+        secrets::mask(&file.path.display().to_string())
+    );
+    let written = Written {
+        path: file.path.clone(),
+        size: file.content.len() as u64,
+    };
+    with_report(|report| report.files.push(written));
+}
+
+/// Record the source of the template
+pub fn source(source: Source) {
+    with_report(|report| report.source = Some(source));
+}
+
+/// The report as JSON with all paths and messages masked, files might be named after secrets
+fn masked(report: &Report) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(report).context("Failed to serialize the report")?;
+    secrets::mask_value(&mut value);
+    Ok(value)
+}
+
+/// Record a warning, its message is masked already
+pub fn warning(kind: &str, message: String) {
+    let warning = Warning {
+        kind: kind.to_owned(),
+        message,
+    };
+    with_report(|report| report.warnings.push(warning));
+}
+
+/// Record an error of a file, its message is masked already
+pub fn error(message: String) {
+    with_report(|report| report.errors.push(message));
+}
+
+/// Print the report with the status of the run (--output json). The error the run failed with is
+/// added unless the errors of the files were recorded already.
+pub fn print_result(error: Option<String>) -> Result<()> {
+    let mut result = {
+        let mut report = REPORT.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(error) = error
+            && report.errors.is_empty()
+        {
+            report.errors.push(error);
+        }
+        masked(&report)?
+    };
+    if let serde_json::Value::Object(map) = &mut result {
+        let status = match map.get("errors") {
+            Some(serde_json::Value::Array(errors)) if !errors.is_empty() => "error",
+            _ => "ok",
+        };
+        map.insert("status".to_owned(), status.into());
+    }
+    let content =
+        serde_json::to_string_pretty(&result).context("Failed to serialize the result")?;
+    println!("{}", content);
+    Ok(())
+}
+
+/// Write the report recorded so far as JSON
+pub fn save(dest: &Path) -> Result<()> {
+    let content = {
+        let report = REPORT.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::to_vec_pretty(&masked(&report)?).context("Failed to serialize report")?
+    };
+    std::fs::write(dest, content)
+        .with_context(|| format!("Failed to write report: {}", dest.display()))
+}
@@ -0,0 +1,116 @@
+//! Rollback of a directory destination when rendering fails part way (e.g. a template error in
+//! one of many files), so the destination is left as it was before. The files written are
+//! tracked and the previous content of overwritten files is copied aside first.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tempfile::TempDir;
+
+/// Changes made to a destination which are undone by [`Rollback::restore`]
+#[derive(Debug)]
+pub struct Rollback {
+    dest: PathBuf,
+    /// The destination did not exist before, everything in it is removed
+    created_dest: bool,
+    created_files: Vec<PathBuf>,
+    /// Created directories, parents first
+    created_dirs: Vec<PathBuf>,
+    /// Overwritten files and where their previous content was copied to
+    backups: Vec<(PathBuf, PathBuf)>,
+    /// Files recorded already, a file written twice keeps its first record
+    recorded: HashSet<PathBuf>,
+    backup_dir: Option<TempDir>,
+}
+
+impl Rollback {
+    /// Start tracking the changes to a destination, before it is created
+    pub fn new(dest: &Path) -> Self {
+        Self {
+            dest: dest.to_path_buf(),
+            created_dest: !dest.exists(),
+            created_files: Vec::new(),
+            created_dirs: Vec::new(),
+            backups: Vec::new(),
+            recorded: HashSet::new(),
+            backup_dir: None,
+        }
+    }
+
+    /// Record a file in the destination before it is written
+    pub fn record(&mut self, path: &Path) -> Result<()> {
+        if self.created_dest || path == self.dest || !self.recorded.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        let missing: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.dest && !dir.exists())
+            .collect();
+        self.created_dirs
+            .extend(missing.into_iter().rev().map(Path::to_path_buf));
+
+        if !path.is_file() {
+            self.created_files.push(path.to_path_buf());
+            return Ok(());
+        }
+        let backup_dir = match &self.backup_dir {
+            Some(dir) => dir,
+            None => self
+                .backup_dir
+                .insert(tempfile::tempdir().context("Failed to create a temporary directory")?),
+        };
+        let backup = backup_dir.path().join(self.backups.len().to_string());
+        fs::copy(path, &backup).with_context(|| format!("Failed to back up {}", path.display()))?;
+        self.backups.push((path.to_path_buf(), backup));
+        Ok(())
+    }
+
+    /// Record a directory in the destination before it is created
+    pub fn record_dir(&mut self, path: &Path) {
+        if self.created_dest || !self.recorded.insert(path.to_path_buf()) {
+            return;
+        }
+        let missing: Vec<&Path> = path
+            .ancestors()
+            .take_while(|dir| *dir != self.dest && !dir.exists())
+            .collect();
+        self.created_dirs
+            .extend(missing.into_iter().rev().map(Path::to_path_buf));
+    }
+
+    /// Undo the recorded changes. Returns whether anything was removed or restored.
+    pub fn restore(self) -> Result<bool> {
+        if self.created_dest {
+            if !self.dest.exists() {
+                return Ok(false);
+            }
+            fs::remove_dir_all(&self.dest).with_context(|| {
+                format!("Failed to remove destination: {}", self.dest.display())
+            })?;
+            return Ok(true);
+        }
+        let mut changed = false;
+        for path in &self.created_files {
+            if path.symlink_metadata().is_ok() {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+                changed = true;
+            }
+        }
+        for (path, backup) in &self.backups {
+            // the file might be a link to other files (--dedup), which must not change
+            let _ = fs::remove_file(path);
+            fs::copy(backup, path)
+                .with_context(|| format!("Failed to restore {}", path.display()))?;
+            changed = true;
+        }
+        for dir in self.created_dirs.iter().rev() {
+            // other processes might have created files in it meanwhile
+            changed |= fs::remove_dir(dir).is_ok();
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,158 @@
+//! `.rteignore` in the template root: gitignore-like patterns of files which are left out of the
+//! output and, after a `[raw]` line, of files which are copied without rendering their content
+//! (e.g. Helm charts or GitHub Actions workflows with their own `{{ }}` expressions).
+//!
+//! ```text
+//! *.orig
+//! [raw]
+//! .github/workflows/
+//! charts/*/templates/
+//! ```
+//!
+//! Single files can also be marked raw by their name: `deploy.yaml.rte-raw` or `deploy.yaml.raw`
+//! is copied to `deploy.yaml` without rendering.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+
+use crate::glob;
+use crate::report::{self, SkipReason};
+use crate::template::TemplateFile;
+
+/// Name of the ignore file in the template root
+pub const IGNORE_FILE: &str = ".rteignore";
+
+/// Suffixes of files which are copied without rendering, the suffix is removed from the path
+const RAW_SUFFIXES: &[&str] = &[".rte-raw", ".raw"];
+
+/// Parsed patterns of an ignore file, of the manifest and of --raw-glob
+#[derive(Debug)]
+pub struct RteIgnore {
+    ignore: GlobSet,
+    raw: GlobSet,
+}
+
+impl RteIgnore {
+    /// Parse an ignore file. Lines starting with `#` are comments, `[ignore]` and `[raw]` start
+    /// the section the following patterns belong to, the first section is `[ignore]`. The
+    /// given patterns are added to the sections.
+    pub fn parse(content: &str, ignore_globs: &[String], raw_globs: &[String]) -> Result<Self> {
+        let mut ignore = ignore_globs.to_vec();
+        let mut raw = raw_globs.to_vec();
+        let mut section = &mut ignore;
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            match line {
+                "" => {}
+                _ if line.starts_with('#') => {}
+                "[ignore]" => section = &mut ignore,
+                "[raw]" => section = &mut raw,
+                _ if line.starts_with('[') => {
+                    anyhow::bail!(
+                        "{} line {}: unknown section {}, expected [ignore] or [raw]",
+                        IGNORE_FILE,
+                        number + 1,
+                        line
+                    );
+                }
+                pattern => section.push(pattern.to_owned()),
+            }
+        }
+        Ok(Self {
+            ignore: glob::gitignore_set(&ignore)
+                .with_context(|| format!("Failed to parse {}", IGNORE_FILE))?,
+            raw: glob::gitignore_set(&raw)
+                .with_context(|| format!("Failed to parse {}", IGNORE_FILE))?,
+        })
+    }
+
+    /// Whether the file is copied without rendering its content
+    pub fn is_raw(&self, path: &Path) -> bool {
+        self.raw.is_match(path)
+    }
+}
+
+/// Take the ignore file out of the template files and drop the files it or the given patterns
+/// ignore. Returns the parsed patterns (also without an ignore file if patterns are given).
+pub fn apply_ignore(
+    files: Vec<TemplateFile>,
+    ignore_globs: &[String],
+    raw_globs: &[String],
+) -> Result<(Option<RteIgnore>, Vec<TemplateFile>)> {
+    let mut files = files;
+    let ignore = match files
+        .iter()
+        .position(|file| file.path == Path::new(IGNORE_FILE))
+    {
+        Some(index) => {
+            let file = files.remove(index);
+            let content = std::str::from_utf8(&file.content)
+                .with_context(|| format!("{} is not valid UTF8", IGNORE_FILE))?;
+            let ignore = RteIgnore::parse(content, ignore_globs, raw_globs)?;
+            report::skip(file.path, SkipReason::Config);
+            ignore
+        }
+        None if ignore_globs.is_empty() && raw_globs.is_empty() => return Ok((None, files)),
+        None => RteIgnore::parse("", ignore_globs, raw_globs)?,
+    };
+
+    let files = files
+        .into_iter()
+        .filter(|file| {
+            let ignored = ignore.ignore.is_match(&file.path);
+            if ignored {
+                report::skip(
+                    &file.path,
+                    SkipReason::Ignored {
+                        by: IGNORE_FILE.to_owned(),
+                    },
+                );
+            }
+            !ignored
+        })
+        .collect();
+    Ok((Some(ignore), files))
+}
+
+/// Paths of the files which are copied without rendering their content
+pub fn raw_paths(ignore: &RteIgnore, files: &[TemplateFile]) -> HashSet<PathBuf> {
+    files
+        .iter()
+        .filter(|file| ignore.is_raw(&file.path))
+        .map(|file| file.path.clone())
+        .collect()
+}
+
+/// Remove the raw suffix from the paths of the files which have one. Returns the paths of these
+/// files, which are copied without rendering their content.
+pub fn strip_raw_suffixes(files: &mut [TemplateFile]) -> Result<HashSet<PathBuf>> {
+    let paths: HashSet<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+    let mut raw = HashSet::new();
+    for file in files.iter_mut() {
+        let Some(stripped) = strip_raw_suffix(&file.path) else {
+            continue;
+        };
+        if paths.contains(&stripped) {
+            anyhow::bail!(
+                "'{}' is copied to '{}', which is also in the template",
+                file.path.display(),
+                stripped.display()
+            );
+        }
+        file.path = stripped.clone();
+        raw.insert(stripped);
+    }
+    Ok(raw)
+}
+
+fn strip_raw_suffix(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    RAW_SUFFIXES
+        .iter()
+        .filter_map(|suffix| name.strip_suffix(suffix))
+        .find(|stripped| !stripped.is_empty())
+        .map(|stripped| path.with_file_name(stripped))
+}
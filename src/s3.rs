@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use reqwest::blocking::{Client, Response};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// Payload hash used for requests without a body that we do not want to hash
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Parsed S3 URL from s3:// scheme
+/// Format: s3://bucket/key
+#[derive(Debug)]
+pub struct S3Source {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl S3Source {
+    /// Parse a s3:// URL
+    /// Examples:
+    ///   s3://my-bucket/templates/rust-service.tar.gz
+    pub fn parse(source: &str) -> Result<Self> {
+        let url = Url::parse(source).context("Invalid URL format")?;
+
+        let bucket = url
+            .host_str()
+            .context("URL must contain a bucket")?
+            .to_string();
+
+        let key = urlencoding::decode(url.path().trim_start_matches('/'))
+            .context("Object name must be valid UTF8")?;
+        if key.is_empty() {
+            anyhow::bail!("Object key cannot be empty");
+        }
+
+        Ok(Self {
+            bucket,
+            key: key.into_owned(),
+        })
+    }
+
+    /// Build the object URL. With a custom endpoint (S3-compatible storage like MinIO) path-style
+    /// addressing is used, otherwise the virtual-hosted style of AWS.
+    pub fn object_url(&self, region: &str, endpoint: Option<&str>) -> String {
+        let key = encode_key(&self.key);
+        match endpoint {
+            Some(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), self.bucket, key),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                self.bucket, region, key
+            ),
+        }
+    }
+}
+
+/// URI-encode every segment of an object key but keep the slashes
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Credentials from the standard AWS environment variables
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl S3Credentials {
+    /// Read AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN. Returns None if no
+    /// credentials are set, in which case requests are sent unsigned (public buckets).
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+        Some(Self {
+            access_key_id,
+            secret_access_key,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// Compute the AWS Signature Version 4 Authorization header for a request without query
+/// parameters. `headers` must contain all headers which should be signed (including host and
+/// x-amz-date) with lowercase names.
+pub fn sign_v4(
+    credentials: &S3Credentials,
+    region: &str,
+    method: &str,
+    path: &str,
+    headers: &BTreeMap<String, String>,
+    payload_hash: &str,
+    amz_date: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method, path, canonical_headers, signed_headers, payload_hash
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = format!("AWS4{}", credentials.secret_access_key);
+    let key = hmac_sha256(key.as_bytes(), date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, "s3");
+    let key = hmac_sha256(&key, "aws4_request");
+    let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn region() -> String {
+    std::env::var("AWS_REGION")
+        .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+        .unwrap_or_else(|_| "us-east-1".to_owned())
+}
+
+fn endpoint() -> Option<String> {
+    std::env::var("AWS_ENDPOINT_URL_S3")
+        .or_else(|_| std::env::var("AWS_ENDPOINT_URL"))
+        .ok()
+}
+
+/// URL the object of an s3:// source is downloaded from, with the region and endpoint of
+/// [`download`]
+pub fn download_url(source: &str) -> Result<String> {
+    Ok(S3Source::parse(source)?.object_url(&region(), endpoint().as_deref()))
+}
+
+/// Download a (compressed) tar archive object from S3 (or S3-compatible storage).
+/// Region and endpoint are taken from AWS_REGION/AWS_DEFAULT_REGION and AWS_ENDPOINT_URL_S3/
+/// AWS_ENDPOINT_URL.
+pub fn download(client: &Client, source: &str) -> Result<Response> {
+    let source = S3Source::parse(source)?;
+
+    let region = region();
+    let object_url = source.object_url(&region, endpoint().as_deref());
+    let url = Url::parse(&object_url).context("Invalid object URL")?;
+
+    let mut request = client.get(&object_url);
+
+    if let Some(credentials) = S3Credentials::from_env() {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = BTreeMap::from([
+            ("host".to_owned(), host),
+            (
+                "x-amz-content-sha256".to_owned(),
+                UNSIGNED_PAYLOAD.to_owned(),
+            ),
+            ("x-amz-date".to_owned(), amz_date.clone()),
+        ]);
+        if let Some(token) = &credentials.session_token {
+            headers.insert("x-amz-security-token".to_owned(), token.clone());
+        }
+
+        let authorization = sign_v4(
+            &credentials,
+            &region,
+            "GET",
+            url.path(),
+            &headers,
+            UNSIGNED_PAYLOAD,
+            &amz_date,
+        );
+
+        headers.remove("host");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request = request.header("Authorization", authorization);
+    }
+
+    let response = request
+        .send()
+        .with_context(|| format!("Failed to fetch object from {}", object_url))?;
+
+    if !response.status().is_success() {
+        anyhow::bail!(
+            "S3 '{}' returned error {}: {}",
+            object_url,
+            response.status(),
+            response.text().unwrap_or_default()
+        );
+    }
+
+    Ok(response)
+}
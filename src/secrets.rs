@@ -0,0 +1,77 @@
+//! Values of secret parameters (`secret: true` or `type: password` in rte.yaml and --set-secret).
+//! Templates get them like any other parameter, but they are masked in everything rte prints
+//! (errors, warnings, dry-run output) and left out of the recorded answers.
+
+use std::sync::Mutex;
+
+use serde_json::{Map, Value};
+
+/// Shown instead of a secret value
+pub const MASK: &str = "********";
+
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Set the secret values of this render
+pub fn configure(values: impl IntoIterator<Item = String>) {
+    let mut values: Vec<String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+    // longer values first, a secret containing another one is masked as a whole
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    values.dedup();
+    let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    *secrets = values;
+}
+
+/// The text with all secret values replaced by [`MASK`]
+pub fn mask(text: &str) -> String {
+    let secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut text = text.to_owned();
+    for secret in secrets.iter() {
+        text = text.replace(secret.as_str(), MASK);
+    }
+    text
+}
+
+/// Mask all strings of a value, e.g. the paths and messages of the report
+pub fn mask_value(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = mask(s),
+        Value::Array(items) => items.iter_mut().for_each(mask_value),
+        Value::Object(map) => map.values_mut().for_each(mask_value),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// The texts of a parameter value to mask: strings and numbers, also those in lists and maps
+pub fn values_of(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Number(n) => vec![n.to_string()],
+        Value::Array(items) => items.iter().flat_map(values_of).collect(),
+        Value::Object(map) => map.values().flat_map(values_of).collect(),
+        Value::Null | Value::Bool(_) => Vec::new(),
+    }
+}
+
+/// Remove a parameter by a dotted key (`db.password`)
+pub fn remove(params: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    if let Some(value) = params.remove(key) {
+        return Some(value);
+    }
+    let (first, rest) = key.split_once('.')?;
+    match params.get_mut(first)? {
+        Value::Object(map) => remove(map, rest),
+        _ => None,
+    }
+}
+
+/// Value of a parameter by a dotted key
+pub fn get<'a>(params: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    if let Some(value) = params.get(key) {
+        return Some(value);
+    }
+    let (first, rest) = key.split_once('.')?;
+    match params.get(first)? {
+        Value::Object(map) => get(map, rest),
+        _ => None,
+    }
+}
@@ -0,0 +1,102 @@
+//! Recording of the inputs of a run (--record) which `rte replay` reproduces: the arguments, the
+//! source pinned to the commit it resolved to and the parameters including the answers to
+//! prompts. Useful for bug reports against templates and to reproduce a run for an audit.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Inputs of a recorded run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Version of rte which recorded the session
+    pub rte_version: String,
+    /// Command line arguments, without the program and --record
+    pub args: Vec<String>,
+    /// Source as it was rendered, pinned to the commit for GitLab and GitHub sources
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_path: Option<String>,
+    /// Commit the ref of the source resolved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Parameters from the registry, parameter files, --set and prompts. Secrets are left out.
+    #[serde(default)]
+    pub parameters: Map<String, Value>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read session: {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse session: {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_yaml::to_string(self).context("Failed to serialize session")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write session: {}", path.display()))
+    }
+}
+
+/// Options which are not recorded with their value: --record itself and the secrets
+const UNRECORDED: [&str; 2] = ["--record", "--set-secret"];
+
+/// Options which set a parameter, they are not recorded for secret parameters
+const SET_OPTIONS: [&str; 4] = ["--set", "-s", "--set-json", "--set-file"];
+
+/// The arguments of this run without --record, --set-secret and their values. --set, --set-json
+/// and --set-file are left out for the `secrets` (e.g. parameters declared as secret).
+pub fn recorded_args(args: impl IntoIterator<Item = String>, secrets: &[String]) -> Vec<String> {
+    let mut recorded = Vec::new();
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        if UNRECORDED.contains(&arg.as_str()) {
+            args.next();
+        } else if UNRECORDED
+            .iter()
+            .any(|option| arg.starts_with(&format!("{}=", option)))
+        {
+            continue;
+        } else if SET_OPTIONS.contains(&arg.as_str()) {
+            if args.peek().is_some_and(|value| sets_secret(value, secrets)) {
+                args.next();
+            } else {
+                recorded.push(arg);
+            }
+        } else if let Some(value) = set_value(&arg)
+            && sets_secret(value, secrets)
+        {
+            continue;
+        } else {
+            recorded.push(arg);
+        }
+    }
+    recorded
+}
+
+/// Value of an option setting a parameter given in the same argument: `--set=KEY=VALUE` or
+/// `-sKEY=VALUE`
+fn set_value(arg: &str) -> Option<&str> {
+    SET_OPTIONS.iter().find_map(|option| match *option {
+        "-s" => arg
+            .strip_prefix("-s")
+            .map(|value| value.strip_prefix('=').unwrap_or(value)),
+        option => arg.strip_prefix(option)?.strip_prefix('='),
+    })
+}
+
+/// Whether `KEY=VALUE` sets a secret, a part of one (db.password.old) or an object with one (db
+/// for db.password)
+fn sets_secret(value: &str, secrets: &[String]) -> bool {
+    let key = value.split_once('=').map_or(value, |(key, _)| key);
+    secrets.iter().any(|secret| {
+        key == secret
+            || key.starts_with(&format!("{}.", secret))
+            || secret.starts_with(&format!("{}.", key))
+    })
+}
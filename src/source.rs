@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, Response};
+use url::Url;
+
+use crate::cache::Cache;
+use crate::dir::read_dir_iter;
+use crate::format::open_archive;
+use crate::hosts::HostPolicy;
+use crate::http::RedirectPolicy;
+use crate::tar::StripComponents;
+use crate::template::TemplateFile;
+use crate::{builtin, bundle, gcs, github, gitlab, http, s3};
+
+pub type FileIter = Box<dyn Iterator<Item = Result<TemplateFile>>>;
+
+/// Credentials and settings needed to open template sources
+#[derive(Default, Clone)]
+pub struct SourceOptions {
+    pub gitlab_token: Option<String>,
+    pub github_token: Option<String>,
+    pub gcs_token: Option<String>,
+    /// Cache for remote archives, None disables caching
+    pub cache: Option<Cache>,
+    /// Only use cached archives and never download
+    pub offline: bool,
+    /// Key bundles must be signed with
+    pub bundle_key: Option<String>,
+    /// Redirects followed when downloading remote sources
+    pub redirects: RedirectPolicy,
+    /// Hosts remote sources may be fetched from
+    pub hosts: HostPolicy,
+    /// Tokens of the user configuration by host, used without --gitlab-token or --github-token
+    pub host_tokens: BTreeMap<String, String>,
+}
+
+impl SourceOptions {
+    /// Token for a GitLab host: --gitlab-token (or GITLAB_TOKEN), else the one of the host
+    pub fn gitlab_token(&self, host: &str) -> Option<&str> {
+        self.gitlab_token
+            .as_deref()
+            .or_else(|| self.host_tokens.get(host).map(String::as_str))
+    }
+
+    /// Token for a GitHub host: --github-token (or GITHUB_TOKEN), else the one of the host
+    pub fn github_token(&self, host: &str) -> Option<&str> {
+        self.github_token
+            .as_deref()
+            .or_else(|| self.host_tokens.get(host).map(String::as_str))
+    }
+}
+
+/// Open a template source. Determines the source type by URL scheme or treats the source as a
+/// local path (directory or tar archive compressed with gzip, xz, bzip2 or zstd). Bundles
+/// (.rtepkg) are verified and unpacked, `builtin://NAME` opens a template embedded in rte.
+pub fn open_source(source: &str, options: &SourceOptions) -> Result<FileIter> {
+    let (files, is_bundle): (FileIter, bool) = match Url::parse(source) {
+        Ok(url) if url.scheme() == "builtin" => {
+            (builtin::open(url.host_str().unwrap_or_default())?, false)
+        }
+        Ok(url) => {
+            let strip_components = match url.scheme() {
+                "gitlab" => gitlab::GitlabSource::parse(source)?.strip_components(),
+                "github" => github::GitHubSource::parse(source)?.strip_components(),
+                "s3" | "gs" => 0,
+                scheme => {
+                    anyhow::bail!("unknown url scheme '{}'", scheme)
+                }
+            };
+            let (archive, content_type) = fetch(source, url.scheme(), options)?;
+            let files = open_archive(archive, url.path(), content_type.as_deref())?;
+            (
+                Box::new(StripComponents::new(files, strip_components)),
+                bundle::is_bundle(url.path()),
+            )
+        }
+        Err(_) => {
+            // Not a valid URL, treat as local path
+            let source_path = PathBuf::from(source);
+            if source_path.is_dir() {
+                (Box::new(read_dir_iter(&source_path)), false)
+            } else {
+                let file = File::open(&source_path).with_context(|| {
+                    format!("Failed to open archive: {}", source_path.display())
+                })?;
+                (open_archive(file, source, None)?, bundle::is_bundle(source))
+            }
+        }
+    };
+    if is_bundle {
+        return bundle::unpack(files, options.bundle_key.as_deref())
+            .with_context(|| format!("Failed to open bundle '{}'", source));
+    }
+    Ok(files)
+}
+
+/// Open only a directory of a GitLab or GitHub source: GitLab archives are filtered by path,
+/// GitHub repositories are cloned sparse with git. The files keep their path within the
+/// repository.
+pub fn open_sparse(source: &str, path: &str, options: &SourceOptions) -> Result<FileIter> {
+    let scheme = Url::parse(source).map(|url| url.scheme().to_owned());
+    match scheme.as_deref() {
+        Ok("gitlab") => {
+            let separator = if source.contains('?') { '&' } else { '?' };
+            let filtered = format!("{}{}path={}", source, separator, urlencoding::encode(path));
+            open_source(&filtered, options)
+        }
+        Ok("github") => {
+            let github = github::GitHubSource::parse(source)?;
+            if github.asset.is_some() {
+                anyhow::bail!("--sparse can not be used with release assets");
+            }
+            if options.offline {
+                anyhow::bail!("--sparse can not be used with --offline");
+            }
+            options.hosts.check_source(source)?;
+            let token = options.github_token(&github.host);
+            let files = github::sparse_checkout(&github, path, token)?;
+            Ok(Box::new(files.into_iter().map(Ok)))
+        }
+        _ => anyhow::bail!(
+            "--sparse is only supported for gitlab:// and github:// sources, got '{}'",
+            source
+        ),
+    }
+}
+
+/// Get the archive of a remote source and its Content-Type, from the cache if possible
+pub fn fetch(
+    source: &str,
+    scheme: &str,
+    options: &SourceOptions,
+) -> Result<(Box<dyn Read>, Option<String>)> {
+    // checked before the cache, a cached source is no more trustworthy
+    options.hosts.check_source(source)?;
+    if let Some(cache) = &options.cache
+        && let Some((file, content_type)) = cache.open(source, options.offline)?
+    {
+        tracing::debug!("using the cached copy of {}", source);
+        return Ok((Box::new(file), content_type));
+    }
+
+    if options.offline {
+        anyhow::bail!("source '{}' is not cached and --offline is set", source);
+    }
+
+    tracing::debug!("downloading {}", source);
+    let response = download(source, scheme, options)?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    match &options.cache {
+        Some(cache) => {
+            let file = cache.store(source, response, content_type.as_deref())?;
+            Ok((Box::new(file), content_type))
+        }
+        // the response is streamed into the decoder, the archive is never held in memory as a whole
+        None => Ok((Box::new(response), content_type)),
+    }
+}
+
+fn download(source: &str, scheme: &str, options: &SourceOptions) -> Result<Response> {
+    let client = http::client(options.redirects, &options.hosts)?;
+    match scheme {
+        "gitlab" => {
+            let gitlab = gitlab::GitlabSource::parse(source)?;
+            gitlab::download(&client, &gitlab, options.gitlab_token(&gitlab.host))
+        }
+        "github" => {
+            let github = github::GitHubSource::parse(source)?;
+            github::download(&client, &github, options.github_token(&github.host))
+        }
+        "s3" => s3::download(&client, source),
+        "gs" => gcs::download(&client, source, options.gcs_token.as_deref()),
+        "http" | "https" => download_http(&client, source),
+        scheme => anyhow::bail!("unknown url scheme '{}'", scheme),
+    }
+}
+
+/// Plain HTTP(S) download, used for template assets
+fn download_http(client: &Client, url: &str) -> Result<Response> {
+    let response = client
+        .get(url)
+        .send()
+        .with_context(|| format!("Failed to fetch {}", url))?;
+    if !response.status().is_success() {
+        anyhow::bail!("'{}' returned error {}", url, response.status());
+    }
+    Ok(response)
+}
@@ -0,0 +1,170 @@
+//! Functions of the sprig library of Helm charts (--sprig or `sprig: true` in rte.yaml), so
+//! authors coming from Helm do not have to write them in every template. They are filters where
+//! sprig pipes the value (`{{ .Values.tls | ternary "https" "http" }}` becomes
+//! `{{ values.tls | ternary('https', 'http') }}`) and functions otherwise (`list`, `dict`).
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use minijinja::value::{Kwargs, Rest};
+use minijinja::{Environment, ErrorKind, Value};
+use sha2::{Digest, Sha256};
+
+pub fn add_functions(env: &mut Environment) {
+    env.add_filter("default", default);
+    env.add_filter("ternary", ternary);
+    env.add_filter("trunc", trunc);
+    env.add_filter("trimPrefix", |s: &str, prefix: &str| {
+        s.strip_prefix(prefix).unwrap_or(s).to_owned()
+    });
+    env.add_filter("trimSuffix", |s: &str, suffix: &str| {
+        s.strip_suffix(suffix).unwrap_or(s).to_owned()
+    });
+    env.add_filter("trimAll", |s: &str, chars: &str| {
+        s.trim_matches(|c| chars.contains(c)).to_owned()
+    });
+    env.add_filter("quote", |s: &str| format!("{:?}", s));
+    env.add_filter("squote", |s: &str| format!("'{}'", s));
+    env.add_filter("nindent", nindent);
+    env.add_filter("b64enc", |s: &str| STANDARD.encode(s));
+    env.add_filter("b64dec", b64dec);
+    env.add_filter("sha256sum", |s: &str| hex::encode(Sha256::digest(s)));
+    env.add_filter("toYaml", to_yaml);
+    env.add_filter("toJson", minijinja::filters::tojson);
+
+    // lists
+    env.add_function("list", |items: Rest<Value>| Value::from(items.0));
+    env.add_filter("append", |list: Value, item: Value| {
+        Ok::<_, minijinja::Error>(Value::from_iter(list.try_iter()?.chain([item])))
+    });
+    env.add_filter("prepend", |list: Value, item: Value| {
+        Ok::<_, minijinja::Error>(Value::from_iter([item].into_iter().chain(list.try_iter()?)))
+    });
+    env.add_filter("has", |list: Value, item: Value| {
+        Ok::<_, minijinja::Error>(list.try_iter()?.any(|v| v == item))
+    });
+    env.add_filter("without", |list: Value, items: Rest<Value>| {
+        Ok::<_, minijinja::Error>(Value::from_iter(
+            list.try_iter()?.filter(|v| !items.contains(v)),
+        ))
+    });
+    env.add_filter("compact", |list: Value| {
+        Ok::<_, minijinja::Error>(Value::from_iter(list.try_iter()?.filter(Value::is_true)))
+    });
+    env.add_filter("uniq", |list: Value| {
+        let mut unique: Vec<Value> = Vec::new();
+        for item in list.try_iter()? {
+            if !unique.contains(&item) {
+                unique.push(item);
+            }
+        }
+        Ok::<_, minijinja::Error>(Value::from(unique))
+    });
+
+    // dicts
+    env.add_function("dict", dict);
+    env.add_filter("hasKey", |map: Value, key: Value| {
+        Ok::<_, minijinja::Error>(map.try_iter()?.any(|k| k == key))
+    });
+    env.add_filter("keys", |map: Value| {
+        Ok::<_, minijinja::Error>(Value::from_iter(map.try_iter()?))
+    });
+    env.add_filter("omit", |map: Value, keys: Rest<Value>| {
+        entries(&map, |key| !keys.contains(key)).map(Value::from_iter)
+    });
+    env.add_filter("merge", merge);
+}
+
+/// The default if the value is empty (undefined, none, false, 0, "" or an empty list or map)
+fn default(value: Value, default: Option<Value>) -> Value {
+    match value.is_true() {
+        true => value,
+        false => default.unwrap_or_else(|| Value::from("")),
+    }
+}
+
+fn ternary(condition: Value, if_true: Value, if_false: Value) -> Value {
+    match condition.is_true() {
+        true => if_true,
+        false => if_false,
+    }
+}
+
+/// The first `length` characters, the last ones for a negative length
+fn trunc(s: &str, length: i64) -> String {
+    let count = s.chars().count();
+    let keep = (length.unsigned_abs() as usize).min(count);
+    match length < 0 {
+        true => s.chars().skip(count - keep).collect(),
+        false => s.chars().take(keep).collect(),
+    }
+}
+
+/// Every line indented, on a new line, e.g. for YAML blocks
+fn nindent(s: &str, width: usize) -> String {
+    let indent = " ".repeat(width);
+    let lines: Vec<String> = s
+        .lines()
+        .map(|line| format!("{}{}", indent, line))
+        .collect();
+    format!("\n{}", lines.join("\n"))
+}
+
+fn b64dec(s: &str) -> Result<String, minijinja::Error> {
+    STANDARD
+        .decode(s)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| {
+            minijinja::Error::new(
+                ErrorKind::InvalidOperation,
+                format!("'{}' is not base64 encoded text", s),
+            )
+        })
+}
+
+fn to_yaml(value: Value) -> Result<String, minijinja::Error> {
+    serde_yaml::to_string(&value)
+        .map(|yaml| yaml.trim_end().to_owned())
+        .map_err(|e| minijinja::Error::new(ErrorKind::InvalidOperation, e.to_string()))
+}
+
+/// Map of key value pairs, `dict('name', 'demo', 'port', 8080)`, or keyword arguments
+fn dict(pairs: Rest<Value>, kwargs: Kwargs) -> Result<Value, minijinja::Error> {
+    if !pairs.len().is_multiple_of(2) {
+        return Err(minijinja::Error::new(
+            ErrorKind::InvalidOperation,
+            "dict needs pairs of keys and values",
+        ));
+    }
+    let mut map: Vec<(Value, Value)> = pairs
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+    for key in kwargs.args() {
+        map.push((Value::from(key), kwargs.get(key)?));
+    }
+    Ok(Value::from_iter(map))
+}
+
+/// Merge maps, the keys of the first map win
+fn merge(map: Value, others: Rest<Value>) -> Result<Value, minijinja::Error> {
+    let mut merged = entries(&map, |_| true)?;
+    for other in others.iter() {
+        for (key, value) in entries(other, |_| true)? {
+            if !merged.iter().any(|(k, _)| *k == key) {
+                merged.push((key, value));
+            }
+        }
+    }
+    Ok(Value::from_iter(merged))
+}
+
+fn entries(
+    map: &Value,
+    include: impl Fn(&Value) -> bool,
+) -> Result<Vec<(Value, Value)>, minijinja::Error> {
+    map.try_iter()?
+        .filter(|key| include(key))
+        .map(|key| Ok((key.clone(), map.get_item(&key)?)))
+        .collect()
+}
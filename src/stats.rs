@@ -0,0 +1,193 @@
+//! Statistics about the files of a template (`rte stats`), to help authors see what makes a
+//! template large: file types, the largest files, the deepest paths and the parameters each file
+//! references.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::index::VariableIndex;
+use crate::template::{SyntaxMode, TemplateConfig, TemplateFile, is_binary};
+
+/// Number and size of the files of a type
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct TypeStats {
+    pub files: usize,
+    pub size: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileSize {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileDepth {
+    pub path: PathBuf,
+    /// Number of directories the file is in
+    pub depth: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileParameters {
+    pub path: PathBuf,
+    /// Referenced parameters, `*` if the file uses all of them
+    pub parameters: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TemplateStats {
+    pub files: usize,
+    pub size: u64,
+    /// Files whose path or content contains template expressions
+    pub templated: usize,
+    /// Binary files, which are copied as they are
+    pub binary: usize,
+    /// Files per extension, `(none)` for files without one
+    pub types: BTreeMap<String, TypeStats>,
+    pub largest: Vec<FileSize>,
+    pub deepest: Vec<FileDepth>,
+    /// Files referencing parameters, most parameters first
+    pub parameters: Vec<FileParameters>,
+}
+
+/// Collect the statistics, the lists of files are limited to the `top` entries
+pub fn collect(
+    files: &[TemplateFile],
+    verbatim: &HashSet<PathBuf>,
+    config: &TemplateConfig,
+    index: &VariableIndex,
+    top: usize,
+) -> TemplateStats {
+    let mut types: BTreeMap<String, TypeStats> = BTreeMap::new();
+    let (mut templated, mut binary) = (0, 0);
+    for file in files {
+        let extension = file
+            .path
+            .extension()
+            .map_or("(none)".to_owned(), |e| e.to_string_lossy().to_lowercase());
+        let stats = types.entry(extension).or_default();
+        stats.files += 1;
+        stats.size += file.content.len() as u64;
+
+        let content = std::str::from_utf8(&file.content);
+        if is_binary(&file.content) {
+            binary += 1;
+        }
+        let content = content
+            .ok()
+            .filter(|_| !verbatim.contains(&file.path) && !is_binary(&file.content));
+        let path = file.path.to_string_lossy();
+        if [Some(path.as_ref()), content]
+            .into_iter()
+            .flatten()
+            .any(|text| has_expressions(config, text))
+        {
+            templated += 1;
+        }
+    }
+
+    let mut largest: Vec<FileSize> = files
+        .iter()
+        .map(|file| FileSize {
+            path: file.path.clone(),
+            size: file.content.len() as u64,
+        })
+        .collect();
+    largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    largest.truncate(top);
+
+    let mut deepest: Vec<FileDepth> = files
+        .iter()
+        .map(|file| FileDepth {
+            path: file.path.clone(),
+            depth: depth(&file.path),
+        })
+        .collect();
+    deepest.sort_by(|a, b| b.depth.cmp(&a.depth).then_with(|| a.path.cmp(&b.path)));
+    deepest.truncate(top);
+
+    let mut parameters: Vec<FileParameters> = index
+        .files
+        .iter()
+        .map(|(path, variables)| {
+            let mut parameters: Vec<String> = variables.parameters.iter().cloned().collect();
+            if variables.all_parameters {
+                parameters.insert(0, "*".to_owned());
+            }
+            FileParameters {
+                path: path.clone(),
+                parameters,
+            }
+        })
+        .filter(|file| !file.parameters.is_empty())
+        .collect();
+    // sorting is stable, files with the same number stay ordered by path
+    parameters.sort_by_key(|file| std::cmp::Reverse(file.parameters.len()));
+    parameters.truncate(top);
+
+    TemplateStats {
+        files: files.len(),
+        size: files.iter().map(|file| file.content.len() as u64).sum(),
+        templated,
+        binary,
+        types,
+        largest,
+        deepest,
+        parameters,
+    }
+}
+
+/// Whether a text contains the start of an expression, statement or comment
+fn has_expressions(config: &TemplateConfig, text: &str) -> bool {
+    let (variable_start, block_start, comment_start) = match &config.syntax {
+        SyntaxMode::Jinja => ("{{", "{%", "{#"),
+        SyntaxMode::Backstage => ("${{", "{%", "{#"),
+        SyntaxMode::Handlebars => ("{{", "{{#", "{{!"),
+        SyntaxMode::Custom(delimiters) => (
+            delimiters.variable_start.as_str(),
+            delimiters.block_start.as_str(),
+            delimiters.comment_start.as_str(),
+        ),
+    };
+    [variable_start, block_start, comment_start]
+        .iter()
+        .any(|start| text.contains(start))
+}
+
+fn depth(path: &Path) -> usize {
+    path.components().count().saturating_sub(1)
+}
+
+pub fn print(stats: &TemplateStats) {
+    println!(
+        "{} files, {} B total, {} with template expressions, {} binary",
+        stats.files, stats.size, stats.templated, stats.binary
+    );
+    println!("types:");
+    for (extension, types) in &stats.types {
+        println!(
+            "  {:<10} {:>5} files {:>10} B",
+            extension, types.files, types.size
+        );
+    }
+    println!("largest files:");
+    for file in &stats.largest {
+        println!("  {:>10} B  {}", file.size, file.path.display());
+    }
+    println!("deepest paths:");
+    for file in &stats.deepest {
+        println!("  {:>3}  {}", file.depth, file.path.display());
+    }
+    println!("parameters per file:");
+    for file in &stats.parameters {
+        println!(
+            "  {:>3}  {}: {}",
+            file.parameters.len(),
+            file.path.display(),
+            file.parameters.join(", ")
+        );
+    }
+}
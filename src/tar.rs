@@ -1,13 +1,16 @@
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use flate2::write::GzEncoder;
 use flate2::Compression;
+use flate2::write::GzEncoder;
 use tar::{Archive, Builder, Entries};
 
-use crate::template::TemplateFile;
+use crate::fsync;
+use crate::report::{self, SkipReason};
+use crate::template::{FileKind, TemplateFile};
 
 pub fn is_tar_gz(path: &Path) -> bool {
     path.to_string_lossy().ends_with(".tar.gz")
@@ -24,6 +27,10 @@ pub struct TarFileIter<R: Read + 'static> {
     #[allow(dead_code)]
     archive: Box<Archive<R>>,
     entries: Entries<'static, R>,
+    /// Directory entries, the empty ones are returned after the files
+    dirs: Vec<PathBuf>,
+    /// Directories which contain files
+    parents: HashSet<PathBuf>,
 }
 
 impl<R: Read + 'static> TarFileIter<R> {
@@ -41,7 +48,12 @@ impl<R: Read + 'static> TarFileIter<R> {
             (*archive_ptr).entries()?
         };
 
-        Ok(Self { archive, entries })
+        Ok(Self {
+            archive,
+            entries,
+            dirs: Vec::new(),
+            parents: HashSet::new(),
+        })
     }
 }
 
@@ -50,13 +62,18 @@ impl<R: Read + 'static> Iterator for TarFileIter<R> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let entry = match self.entries.next()? {
-                Ok(e) => e,
-                Err(e) => return Some(Err(e.into())),
+            let entry = match self.entries.next() {
+                Some(Ok(e)) => e,
+                Some(Err(e)) => return Some(Err(e.into())),
+                None => return self.next_empty_dir(),
             };
 
-            // Skip directories
+            // directories are created for their files, empty ones are returned at the end
             if entry.header().entry_type().is_dir() {
+                match entry.path() {
+                    Ok(path) => self.dirs.push(path.to_path_buf()),
+                    Err(e) => return Some(Err(e.into())),
+                }
                 continue;
             }
 
@@ -66,16 +83,62 @@ impl<R: Read + 'static> Iterator for TarFileIter<R> {
                 Err(e) => return Some(Err(e.into())),
             };
 
+            if entry.header().entry_type().is_symlink() {
+                let target = match entry.link_name() {
+                    Ok(Some(target)) => target.to_string_lossy().into_owned(),
+                    Ok(None) => String::new(),
+                    Err(e) => return Some(Err(e.into())),
+                };
+                self.parents
+                    .extend(path.ancestors().skip(1).map(Path::to_path_buf));
+                return Some(Ok(TemplateFile {
+                    path,
+                    content: target.into_bytes(),
+                    kind: FileKind::Symlink,
+                    mode: None,
+                }));
+            }
+
+            let mode = entry.header().mode().ok();
             let mut content = Vec::new();
             if let Err(e) = entry.read_to_end(&mut content) {
                 return Some(Err(e.into()));
             }
 
-            return Some(Ok(TemplateFile { path, content }));
+            self.parents
+                .extend(path.ancestors().skip(1).map(Path::to_path_buf));
+            return Some(Ok(TemplateFile {
+                path,
+                content,
+                kind: FileKind::File,
+                mode: mode.map(|mode| mode & 0o777),
+            }));
         }
     }
 }
 
+impl<R: Read + 'static> TarFileIter<R> {
+    fn next_empty_dir(&mut self) -> Option<Result<TemplateFile>> {
+        while let Some(path) = self.dirs.pop() {
+            // `dir/` and `dir` are the same directory
+            let path: PathBuf = path.components().collect();
+            if self.parents.contains(&path) {
+                report::skip(path, SkipReason::Directory);
+                continue;
+            }
+            self.parents
+                .extend(path.ancestors().skip(1).map(Path::to_path_buf));
+            return Some(Ok(TemplateFile {
+                path,
+                content: Vec::new(),
+                kind: FileKind::Dir,
+                mode: None,
+            }));
+        }
+        None
+    }
+}
+
 /// Iterator wrapper that strips leading path components from file paths.
 /// Useful for archives that contain a root folder prefix (e.g., project-branch-sha/).
 pub struct StripComponents<I> {
@@ -103,6 +166,7 @@ impl<I: Iterator<Item = Result<TemplateFile>>> Iterator for StripComponents<I> {
             let components: Vec<_> = file.path.components().collect();
             if components.len() <= self.strip_count {
                 // Skip entries that would become empty after stripping
+                report::skip(file.path, SkipReason::EmptyAfterStrip);
                 continue;
             }
 
@@ -112,36 +176,128 @@ impl<I: Iterator<Item = Result<TemplateFile>>> Iterator for StripComponents<I> {
             return Some(Ok(TemplateFile {
                 path: new_path,
                 content: file.content,
+                kind: file.kind,
+                mode: file.mode,
             }));
         }
     }
 }
 
-pub fn write_to_tar_gz(dest: &Path, files: impl Iterator<Item = Result<TemplateFile>>) -> Result<()> {
-    if let Some(parent) = dest.parent() {
-        if !parent.as_os_str().is_empty() {
-            std::fs::create_dir_all(parent).with_context(|| {
-                format!("Failed to create parent directory: {}", parent.display())
-            })?;
-        }
-    }
+/// Write files into a tar.gz archive. With `sync` the archive is written to a temporary sibling
+/// file, synced to disk and then moved into place.
+pub fn write_to_tar_gz(
+    dest: &Path,
+    files: impl Iterator<Item = Result<TemplateFile>>,
+    sync: bool,
+) -> Result<()> {
+    let target = fsync::archive_target(dest, sync)?;
+    let file = File::create(&target)
+        .with_context(|| format!("Failed to create archive: {}", target.display()))?;
+    let file = write_tar_gz(file, files)?;
 
-    let file = File::create(dest)
-        .with_context(|| format!("Failed to create archive: {}", dest.display()))?;
-    let encoder = GzEncoder::new(file, Compression::default());
-    let mut tar = Builder::new(encoder);
+    if sync {
+        file.sync_all()
+            .with_context(|| format!("failed to sync file: {}", target.display()))?;
+        fsync::persist(&target, dest)?;
+    }
+    Ok(())
+}
 
+/// Write files as tar.gz stream into a writer (e.g. stdout) and return the writer. Each file is
+/// written as soon as it is rendered and dropped afterwards.
+pub fn write_tar_gz<W: Write>(
+    writer: W,
+    files: impl Iterator<Item = Result<TemplateFile>>,
+) -> Result<W> {
+    let mut tar = TarGzWriter::new(writer);
     for file in files {
         let file = file?;
+        match file.kind {
+            FileKind::Dir => tar.append_dir(&file.path)?,
+            FileKind::Symlink => tar.append_symlink(&file.path, &file.content)?,
+            FileKind::File => tar.append(
+                &file.path,
+                file.content.len() as u64,
+                file.mode,
+                file.content.as_slice(),
+            )?,
+        }
+    }
+    tar.finish()
+}
+
+/// Size of the buffer between the gzip encoder and the writer
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Writes entries into a tar.gz stream one by one. The content of an entry is streamed from a
+/// reader, at most [`WRITE_BUFFER_SIZE`] bytes of compressed output are buffered before they are
+/// written, so a slow writer (e.g. a pipe) holds back reading instead of filling up memory.
+pub struct TarGzWriter<W: Write> {
+    tar: Builder<GzEncoder<BufWriter<W>>>,
+}
+
+impl<W: Write> TarGzWriter<W> {
+    pub fn new(writer: W) -> Self {
+        let writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, writer);
+        Self {
+            tar: Builder::new(GzEncoder::new(writer, Compression::default())),
+        }
+    }
+
+    /// Append a file with `size` bytes of content read from `content`, files without a mode are
+    /// readable by everyone and writable by the owner
+    pub fn append(
+        &mut self,
+        path: &Path,
+        size: u64,
+        mode: Option<u32>,
+        content: impl Read,
+    ) -> Result<()> {
         let mut header = tar::Header::new_gnu();
-        header.set_size(file.content.len() as u64);
-        header.set_mode(0o644);
+        header.set_size(size);
+        header.set_mode(mode.unwrap_or(0o644));
         header.set_cksum();
-        tar.append_data(&mut header, &file.path, file.content.as_slice())
-            .with_context(|| format!("Failed to add file to archive: {}", file.path.display()))?;
+        self.tar
+            .append_data(&mut header, path, content)
+            .with_context(|| format!("Failed to add file to archive: {}", path.display()))
     }
 
-    tar.finish()
-        .with_context(|| "Failed to finalize tar archive")?;
-    Ok(())
+    /// Append an empty directory
+    pub fn append_dir(&mut self, path: &Path) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        self.tar
+            .append_data(&mut header, path, std::io::empty())
+            .with_context(|| format!("Failed to add directory to archive: {}", path.display()))
+    }
+
+    /// Append a symlink to the target
+    pub fn append_symlink(&mut self, path: &Path, target: &[u8]) -> Result<()> {
+        let target = std::str::from_utf8(target).context("symlink target is not UTF8")?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        self.tar
+            .append_link(&mut header, path, target)
+            .with_context(|| format!("Failed to add symlink to archive: {}", path.display()))
+    }
+
+    /// Finish the archive and return the writer
+    pub fn finish(self) -> Result<W> {
+        let encoder = self
+            .tar
+            .into_inner()
+            .with_context(|| "Failed to finalize tar archive")?;
+        let writer = encoder
+            .finish()
+            .with_context(|| "Failed to finalize gzip stream")?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .with_context(|| "Failed to write archive")
+    }
 }
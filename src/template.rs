@@ -1,28 +1,396 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
 use minijinja::syntax::SyntaxConfig;
-use minijinja::{Environment, UndefinedBehavior};
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use crate::backstage;
+use crate::charset::{EncodingRule, Encodings};
+use crate::clock;
+use crate::envvars;
+use crate::exec::{self, Filter};
+use crate::hbs::HandlebarsEngine;
+use crate::random::Random;
+use crate::report::{self, SkipReason};
+use crate::sprig;
+use crate::warnings::{self, WarningKind};
+
+#[derive(Debug, Clone)]
 pub struct TemplateFile {
     pub path: PathBuf,
     pub content: Vec<u8>,
+    pub kind: FileKind,
+    /// Unix permissions (e.g. 0o755 for scripts), files without get the default of the output
+    pub mode: Option<u32>,
+}
+
+/// What a template file is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileKind {
+    #[default]
+    File,
+    /// An empty directory of the template (e.g. `migrations/`), it has no content
+    Dir,
+    /// A symbolic link, the content is its target which is rendered like a path
+    Symlink,
 }
 
 /// Syntax mode for template delimiters
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 pub enum SyntaxMode {
     /// Standard Jinja2 syntax: {{ }} and {% %}
     #[default]
     Jinja,
     /// Backstage software templates syntax: ${{ }} and ${% %}
     Backstage,
+    /// Jinja with other delimiters, for templates which produce Jinja, Helm or GitHub Actions
+    /// files themselves
+    Custom(Delimiters),
+    /// Handlebars/Mustache: {{ }}, {{#if}} and {{> partial}}
+    Handlebars,
+}
+
+impl SyntaxMode {
+    /// Names of the modes which can be selected with --syntax
+    pub const NAMES: [&str; 3] = ["jinja", "backstage", "handlebars"];
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "backstage" => SyntaxMode::Backstage,
+            "handlebars" => SyntaxMode::Handlebars,
+            _ => SyntaxMode::Jinja,
+        }
+    }
+}
+
+/// Renders template files in the syntax of a mode
+pub trait Engine: Send + Sync {
+    /// Render a template, `name` (the path of the file) is shown in errors
+    fn render(&self, name: &str, source: &str, params: &serde_json::Value) -> Result<String>;
+
+    /// All errors of a template which fails to render, instead of only the first one
+    fn check(&self, name: &str, source: &str, params: &serde_json::Value) -> Vec<String> {
+        match self.render(name, source, params) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!("{:#}", e)],
+        }
+    }
+
+    /// Set the loader of templates referenced by includes, imports and partials
+    fn set_loader(&mut self, loader: Loader);
+}
+
+/// Resolves templates referenced by other templates by name
+pub type Loader =
+    Arc<dyn Fn(&str) -> Result<Option<String>, minijinja::Error> + Send + Sync + 'static>;
+
+struct JinjaEngine {
+    env: Environment<'static>,
+    /// Show the values the template referenced in errors
+    verbose_errors: bool,
+}
+
+impl Engine for JinjaEngine {
+    fn render(&self, name: &str, source: &str, params: &serde_json::Value) -> Result<String> {
+        self.env
+            .render_named_str(name, source, params)
+            .map_err(|e| anyhow::anyhow!("{}", describe_error(&e, self.verbose_errors)))
+    }
+
+    /// Rendering stops at the first undefined value. To find the next one the template is
+    /// rendered again with an empty text standing in for each undefined value found so far.
+    fn check(&self, name: &str, source: &str, params: &serde_json::Value) -> Vec<String> {
+        let mut params = params.clone();
+        let mut errors = Vec::new();
+        for _ in 0..MAX_CHECKED_ERRORS {
+            let error = match self.env.render_named_str(name, source, &params) {
+                Ok(_) => break,
+                Err(e) => e,
+            };
+            errors.push(describe_error(&error, self.verbose_errors));
+            match undefined_path(&error) {
+                Some(path) if insert_placeholder(&mut params, &path) => {}
+                _ => break,
+            }
+        }
+        errors
+    }
+
+    fn set_loader(&mut self, loader: Loader) {
+        self.env.set_loader(move |name| loader(name));
+    }
+}
+
+/// Errors collected at most for one file, each one renders the file again
+const MAX_CHECKED_ERRORS: usize = 50;
+
+/// The dotted path of the variable of an undefined value error, e.g. `values.cli.command`
+fn undefined_path(error: &minijinja::Error) -> Option<String> {
+    let mut cause = error;
+    while let Some(inner) =
+        std::error::Error::source(cause).and_then(|e| e.downcast_ref::<minijinja::Error>())
+    {
+        cause = inner;
+    }
+    if cause.kind() != ErrorKind::UndefinedError {
+        return None;
+    }
+    let source = cause.template_source()?;
+    let range = cause.range()?;
+    // the range can start at an attribute, the path starts at the variable
+    let is_path = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let start = source.get(..range.start)?.trim_end_matches(is_path).len();
+    let path = source.get(start..range.end)?;
+    let valid = path
+        .split('.')
+        .all(|part| part.starts_with(|c: char| c.is_alphabetic() || c == '_'));
+    valid.then(|| path.to_owned())
+}
+
+/// Set an empty text at a dotted path which is not set yet, returns false if that is not possible
+fn insert_placeholder(params: &mut serde_json::Value, path: &str) -> bool {
+    let mut current = params;
+    let mut parts = path.split('.').peekable();
+    while let Some(part) = parts.next() {
+        let serde_json::Value::Object(map) = current else {
+            return false;
+        };
+        if parts.peek().is_none() {
+            if map.contains_key(part) {
+                return false;
+            }
+            map.insert(part.to_owned(), serde_json::Value::String(String::new()));
+            return true;
+        }
+        current = map
+            .entry(part)
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    }
+    false
 }
 
+/// Create the engine rendering the template files of a configuration
+pub fn engine(config: &TemplateConfig) -> Box<dyn Engine> {
+    match config.syntax {
+        SyntaxMode::Handlebars => Box::new(HandlebarsEngine::new(config.undefined)),
+        _ => Box::new(JinjaEngine {
+            env: environment(config),
+            verbose_errors: config.verbose_errors,
+        }),
+    }
+}
+
+/// An error of a template with where it happened: the file, line and column, the line of the
+/// template and the templates which included it. Verbose errors add the excerpt of the template
+/// around the line and the values it referenced.
+pub fn describe_error(error: &minijinja::Error, verbose: bool) -> String {
+    // the error of an included template is the source of the error of the include
+    let mut stack = vec![error];
+    while let Some(inner) = stack
+        .last()
+        .and_then(|e| std::error::Error::source(*e))
+        .and_then(|e| e.downcast_ref::<minijinja::Error>())
+    {
+        stack.push(inner);
+    }
+    let cause = stack[stack.len() - 1];
+
+    let mut text = match cause.detail() {
+        Some(detail) => format!("{}: {}", cause.kind(), detail),
+        None => cause.kind().to_string(),
+    };
+    if let Some(location) = error_location(cause) {
+        let _ = write!(text, " at {}", location);
+    }
+    if let Some(excerpt) = error_excerpt(cause) {
+        let _ = write!(text, "\n{}", excerpt);
+    }
+    for outer in stack.iter().rev().skip(1) {
+        if let Some(location) = error_location(outer) {
+            let _ = write!(text, "\n  from {}", location);
+        }
+    }
+    if verbose {
+        let _ = write!(text, "{}", cause.display_debug_info());
+    }
+    text
+}
+
+/// `NAME:LINE:COLUMN` of an error
+fn error_location(error: &minijinja::Error) -> Option<String> {
+    let name = error.name()?;
+    let line = error.line()?;
+    match (error.template_source(), error.range()) {
+        (Some(source), Some(range)) => {
+            let start = source.get(..range.start)?;
+            let column = start.rsplit('\n').next().unwrap_or(start).chars().count() + 1;
+            Some(format!("{}:{}:{}", name, line, column))
+        }
+        _ => Some(format!("{}:{}", name, line)),
+    }
+}
+
+/// The line of the template with the error, the failing part marked below it
+fn error_excerpt(error: &minijinja::Error) -> Option<String> {
+    let source = error.template_source()?;
+    let line = error.line()?;
+    let text = source.lines().nth(line.checked_sub(1)?)?;
+    let number = line.to_string();
+    let mut excerpt = format!("  {} | {}", number, text);
+    if let Some(range) = error.range() {
+        let start = source.get(..range.start)?;
+        let before = start.rsplit('\n').next().unwrap_or(start).chars().count();
+        let marked = source.get(range)?.lines().next().unwrap_or_default();
+        let _ = write!(
+            excerpt,
+            "\n  {} | {}{}",
+            " ".repeat(number.len()),
+            " ".repeat(before),
+            "^".repeat(marked.chars().count().max(1))
+        );
+    }
+    Some(excerpt)
+}
+
+/// Delimiters of expressions, statements and comments (--delimiters, `syntax` in the manifest)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Delimiters {
+    pub variable_start: String,
+    pub variable_end: String,
+    pub block_start: String,
+    pub block_end: String,
+    pub comment_start: String,
+    pub comment_end: String,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Self {
+            variable_start: "{{".to_owned(),
+            variable_end: "}}".to_owned(),
+            block_start: "{%".to_owned(),
+            block_end: "%}".to_owned(),
+            comment_start: "{#".to_owned(),
+            comment_end: "#}".to_owned(),
+        }
+    }
+}
+
+impl Delimiters {
+    /// Parse the pairs of variable, block and comment delimiters separated by whitespace, e.g.
+    /// `<< >> <% %>`. Pairs which are not given stay those of Jinja.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        if parts.is_empty() || !parts.len().is_multiple_of(2) || parts.len() > 6 {
+            return Err(format!(
+                "'{}' are not delimiters, expected pairs of variable, block and comment delimiters like '<< >> <% %> <# #>'",
+                s
+            ));
+        }
+        let mut delimiters = Self::default();
+        let pairs = [
+            (&mut delimiters.variable_start, &mut delimiters.variable_end),
+            (&mut delimiters.block_start, &mut delimiters.block_end),
+            (&mut delimiters.comment_start, &mut delimiters.comment_end),
+        ];
+        for ((start, end), pair) in pairs.into_iter().zip(parts.chunks(2)) {
+            *start = pair[0].to_owned();
+            *end = pair[1].to_owned();
+        }
+        delimiters.syntax_config()?;
+        Ok(delimiters)
+    }
+
+    /// Syntax of the template environment, fails for ambiguous delimiters
+    pub fn syntax_config(&self) -> Result<SyntaxConfig, String> {
+        SyntaxConfig::builder()
+            .variable_delimiters(self.variable_start.clone(), self.variable_end.clone())
+            .block_delimiters(self.block_start.clone(), self.block_end.clone())
+            .comment_delimiters(self.comment_start.clone(), self.comment_end.clone())
+            .build()
+            .map_err(|e| format!("invalid delimiters: {}", e))
+    }
+}
+
+/// What a reference to an undefined value does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Undefined {
+    /// Fail the render
+    #[default]
+    Strict,
+    /// Render as empty, but fail on attribute access (`values.optional.field`)
+    Lenient,
+    /// Render as empty, also attributes of undefined values
+    Chainable,
+}
+
+impl From<Undefined> for UndefinedBehavior {
+    fn from(undefined: Undefined) -> Self {
+        match undefined {
+            Undefined::Strict => UndefinedBehavior::Strict,
+            Undefined::Lenient => UndefinedBehavior::Lenient,
+            Undefined::Chainable => UndefinedBehavior::Chainable,
+        }
+    }
+}
+
+/// Whitespace around block tags like `{% if %}`, which otherwise leave blank lines in YAML or
+/// Dockerfiles unless every tag is written as `{%- -%}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Whitespace {
+    /// Remove the first newline after a block tag
+    pub trim_blocks: bool,
+    /// Remove spaces and tabs before a block tag at the start of a line
+    pub lstrip_blocks: bool,
+    /// Keep the newline at the end of a file
+    pub keep_trailing_newline: bool,
+}
+
+impl Default for Whitespace {
+    fn default() -> Self {
+        Self {
+            trim_blocks: false,
+            lstrip_blocks: false,
+            keep_trailing_newline: true,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct TemplateConfig {
     pub syntax: SyntaxMode,
     pub root_value: Option<String>,
+    pub undefined: Undefined,
+    pub whitespace: Whitespace,
+    /// Add the functions of the sprig library of Helm charts
+    pub sprig: bool,
+    /// Encodings of text files which are not UTF-8
+    pub encodings: Vec<EncodingRule>,
+    /// Show the values the template referenced in errors
+    pub verbose_errors: bool,
+    /// Values of the `computed` section of the manifest, passed next to the parameters
+    pub computed: serde_json::Map<String, serde_json::Value>,
+    /// Information about the render and the template, passed next to the parameters as `rte`
+    pub rte: Option<serde_json::Value>,
+    /// Time templates get from `now()`, the same for all files of a render
+    pub now: chrono::DateTime<chrono::Utc>,
+    /// Generator of `uuid4()`, `random_string()` and `random_hex()`, shared by clones
+    pub random: Random,
+    /// Names and `PREFIX*` patterns of the environment variables templates can read with `env()`
+    pub allowed_env: Vec<String>,
+    /// Command-backed filters of the manifest
+    pub exec: Vec<Filter>,
+    /// Whether the command-backed filters may run
+    pub allow_exec: bool,
 }
 
 impl Default for TemplateConfig {
@@ -30,47 +398,420 @@ impl Default for TemplateConfig {
         Self {
             syntax: SyntaxMode::Jinja,
             root_value: Some("values".to_owned()),
+            undefined: Undefined::Strict,
+            whitespace: Whitespace::default(),
+            sprig: false,
+            encodings: Vec::new(),
+            verbose_errors: false,
+            computed: serde_json::Map::new(),
+            rte: None,
+            now: chrono::Utc::now(),
+            random: Random::default(),
+            allowed_env: Vec::new(),
+            exec: Vec::new(),
+            allow_exec: false,
+        }
+    }
+}
+
+impl TemplateConfig {
+    /// What templates are rendered with: the parameters under the root key (if any) and next to
+    /// them the computed values as `computed` and the render information as `rte`. Parameters
+    /// on the root with the same name win.
+    pub fn context(&self, params: serde_json::Value) -> serde_json::Value {
+        let mut context = match &self.root_value {
+            Some(key) => serde_json::json!({ key: params }),
+            None => params,
+        };
+        if let serde_json::Value::Object(context) = &mut context {
+            if !self.computed.is_empty() {
+                context
+                    .entry("computed")
+                    .or_insert_with(|| serde_json::Value::Object(self.computed.clone()));
+            }
+            if let Some(rte) = &self.rte {
+                context.entry("rte").or_insert_with(|| rte.clone());
+            }
+        }
+        context
+    }
+}
+
+/// Create the template environment for a configuration
+pub fn environment(config: &TemplateConfig) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(config.undefined.into());
+    env.set_debug(true);
+    // templates are named after their files, which must not select an escaping (e.g. for .json)
+    env.set_auto_escape_callback(|_| minijinja::AutoEscape::None);
+    env.set_keep_trailing_newline(config.whitespace.keep_trailing_newline);
+    env.set_trim_blocks(config.whitespace.trim_blocks);
+    env.set_lstrip_blocks(config.whitespace.lstrip_blocks);
+
+    // case conversion filters of cargo-generate (liquid), also handy for other templates, e.g.
+    // the crate name, the struct name and the prefix of environment variables of a project
+    env.add_filter("upcase", |s: &str| s.to_uppercase());
+    env.add_filter("downcase", |s: &str| s.to_lowercase());
+    env.add_filter("snake_case", snake_case);
+    env.add_filter("kebab_case", kebab_case);
+    env.add_filter("shouty_snake_case", screaming_snake_case);
+    env.add_filter("screaming_snake_case", screaming_snake_case);
+    env.add_filter("pascal_case", pascal_case);
+    env.add_filter("upper_camel_case", pascal_case);
+    env.add_filter("camel_case", camel_case);
+    env.add_filter("lower_camel_case", camel_case);
+
+    // normalize input in the template instead of in every parameter file, e.g. strip characters
+    // a service name must not have
+    env.add_filter("regex_replace", regex_replace);
+    env.add_filter("regex_match", regex_match);
+    env.add_filter("regex_search", regex_search);
+
+    // copyright years, generation timestamps and CHANGELOG headers
+    let time = config.now;
+    env.add_function("now", move |format: Option<&str>| now(time, format));
+    env.add_filter("strftime", strftime);
+
+    // identifiers and secrets of scaffolded manifests, deterministic with --seed
+    let random = config.random.clone();
+    env.add_function("uuid4", move || random.uuid4());
+    let random = config.random.clone();
+    env.add_function(
+        "random_string",
+        move |length: usize, chars: Option<&str>| {
+            random
+                .string(length, chars)
+                .map_err(|message| minijinja::Error::new(ErrorKind::InvalidOperation, message))
+        },
+    );
+    let random = config.random.clone();
+    env.add_function("random_hex", move |length: usize| random.hex(length));
+
+    // only variables allowed by the manifest or --allow-env
+    let allowed_env = config.allowed_env.clone();
+    env.add_function("env", move |name: &str, default: Option<&str>| {
+        envvars::get(&allowed_env, name, default)
+            .map_err(|message| minijinja::Error::new(ErrorKind::InvalidOperation, message))
+    });
+
+    // declared by the manifest, run with --allow-exec
+    exec::add_filters(&mut env, &config.exec, config.allow_exec);
+
+    if config.sprig {
+        sprig::add_functions(&mut env);
+    }
+
+    if let SyntaxMode::Custom(delimiters) = &config.syntax {
+        // checked when the delimiters are parsed
+        if let Ok(syntax_config) = delimiters.syntax_config() {
+            env.set_syntax(syntax_config);
+        }
+    }
+    if let SyntaxMode::Backstage = config.syntax {
+        // https://github.com/backstage/backstage/blob/9e88165368eafc6744b8c41c9912260e853ec11b/plugins/scaffolder-backend/src/lib/templating/SecureTemplater.ts#L40
+        let syntax_config = SyntaxConfig::builder()
+            .variable_delimiters("${{", "}}")
+            .build()
+            .expect("valid backstage syntax config");
+        env.set_syntax(syntax_config);
+        backstage::add_filters(&mut env);
+    }
+    env
+}
+
+/// Split an identifier like `myProject-name_x` into lowercase words (`my`, `project`, `name`, `x`)
+pub fn snake_case(s: &str) -> String {
+    words(s).join("_")
+}
+
+pub fn kebab_case(s: &str) -> String {
+    words(s).join("-")
+}
+
+pub fn screaming_snake_case(s: &str) -> String {
+    words(s).join("_").to_uppercase()
+}
+
+pub fn pascal_case(s: &str) -> String {
+    words(s).iter().map(|w| capitalize(w)).collect()
+}
+
+pub fn camel_case(s: &str) -> String {
+    let words = words(s);
+    let mut result = words.first().cloned().unwrap_or_default();
+    result.extend(words.iter().skip(1).map(|w| capitalize(w)));
+    result
+}
+
+/// Split a name into lowercase words at non-alphanumeric characters and case changes, an
+/// acronym is one word (`HTTPServer` is `http server`)
+fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+    let mut prev_upper = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_alphanumeric() {
+            prev_lower = false;
+            prev_upper = false;
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let next_lower = chars.peek().is_some_and(|next| next.is_lowercase());
+        if c.is_uppercase() && (prev_lower || (prev_upper && next_lower)) && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
         }
+        prev_lower = c.is_lowercase() || c.is_numeric();
+        prev_upper = c.is_uppercase();
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex, minijinja::Error> {
+    Regex::new(pattern).map_err(|e| {
+        minijinja::Error::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid regular expression '{}'", pattern),
+        )
+        .with_source(e)
+    })
+}
+
+/// Replace all matches, the replacement can reference groups with `$1` or `${name}`
+fn regex_replace(s: &str, pattern: &str, replacement: &str) -> Result<String, minijinja::Error> {
+    Ok(compile_regex(pattern)?
+        .replace_all(s, replacement)
+        .into_owned())
+}
+
+/// Whether the pattern matches anywhere in the value, anchor it with `^` and `$` to match all
+fn regex_match(s: &str, pattern: &str) -> Result<bool, minijinja::Error> {
+    Ok(compile_regex(pattern)?.is_match(s))
+}
+
+/// The first match, or its first group if the pattern has groups. Empty if nothing matches.
+fn regex_search(s: &str, pattern: &str) -> Result<String, minijinja::Error> {
+    let regex = compile_regex(pattern)?;
+    let Some(captures) = regex.captures(s) else {
+        return Ok(String::new());
+    };
+    let found = match regex.captures_len() {
+        1 => captures.get(0),
+        _ => captures.get(1),
+    };
+    Ok(found.map_or("", |m| m.as_str()).to_owned())
+}
+
+/// Time of the render as RFC 3339 in UTC, or formatted like strftime
+fn now(
+    now: chrono::DateTime<chrono::Utc>,
+    format: Option<&str>,
+) -> Result<String, minijinja::Error> {
+    match format {
+        Some(format) => format_time(now, format),
+        None => Ok(now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+    }
+}
+
+/// Format a time (RFC 3339, `YYYY-MM-DD HH:MM:SS`, a date or seconds since the epoch), e.g.
+/// `now() | strftime('%Y')`
+fn strftime(value: minijinja::Value, format: &str) -> Result<String, minijinja::Error> {
+    let time = match value.as_i64() {
+        Some(seconds) => clock::parse_time(&seconds.to_string()),
+        None => clock::parse_time(&value.to_string()),
+    }
+    .map_err(|message| minijinja::Error::new(ErrorKind::InvalidOperation, message))?;
+    format_time(time, format)
+}
+
+fn format_time(
+    time: chrono::DateTime<chrono::Utc>,
+    format: &str,
+) -> Result<String, minijinja::Error> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", time.format(format)).map_err(|_| {
+        minijinja::Error::new(
+            ErrorKind::InvalidOperation,
+            format!("invalid time format '{}'", format),
+        )
+    })?;
+    Ok(formatted)
+}
+
+/// Number of bytes searched for a null byte, like git does to detect binary files
+const BINARY_DETECTION_BYTES: usize = 8000;
+
+/// Whether content is binary: not valid UTF8 or with a null byte at the start. Text editors never
+/// write null bytes, but some binary formats are valid UTF8.
+pub fn is_binary(content: &[u8]) -> bool {
+    let start = &content[..content.len().min(BINARY_DETECTION_BYTES)];
+    start.contains(&0) || std::str::from_utf8(content).is_err()
+}
+
+/// Links followed at most to resolve a symlink target, like the limit of Linux
+const MAX_LINK_HOPS: usize = 40;
+
+/// Symlinks of the output so far. A symlink must not point outside of the output, the output
+/// could not be used on its own and writing through the link would change files elsewhere.
+/// Targets are resolved through the other symlinks of the output, as two links together (e.g.
+/// `sub/link -> ..` and `x -> sub/link/..`) can leave it, and no entry may be written through one.
+#[derive(Debug, Default)]
+pub struct Symlinks {
+    links: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl Symlinks {
+    /// Check the next entry of the output
+    pub fn check(&mut self, file: &TemplateFile) -> Result<()> {
+        if let Some(link) = file
+            .path
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| self.links.contains_key(*ancestor))
+        {
+            anyhow::bail!(
+                "'{}' would be written through the symlink '{}' of the output",
+                file.path.display(),
+                link.display()
+            );
+        }
+        if file.kind != FileKind::Symlink {
+            return Ok(());
+        }
+
+        let target = Path::new(std::str::from_utf8(&file.content).with_context(|| {
+            format!(
+                "the target of the symlink '{}' is not UTF8",
+                file.path.display()
+            )
+        })?);
+        if target.has_root() {
+            anyhow::bail!(
+                "the symlink '{}' points to the absolute path '{}', only relative targets within the output are supported",
+                file.path.display(),
+                target.display()
+            );
+        }
+        self.links.insert(file.path.clone(), target.to_path_buf());
+
+        // a new link can lead an earlier one outside of the output
+        for (path, target) in &self.links {
+            if !self.within_output(path, target) {
+                anyhow::bail!(
+                    "the symlink '{}' points to '{}' outside of the output",
+                    path.display(),
+                    target.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the target of the link at `path` resolves to a path within the output
+    fn within_output(&self, path: &Path, target: &Path) -> bool {
+        let mut resolved = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut rest: VecDeque<Component> = target.components().collect();
+        let mut hops = 0;
+        while let Some(component) = rest.pop_front() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => continue,
+                Component::ParentDir if resolved.pop() => continue,
+                Component::ParentDir | Component::Prefix(_) | Component::RootDir => return false,
+            }
+            if let Some(target) = self.links.get(&resolved) {
+                hops += 1;
+                if hops > MAX_LINK_HOPS {
+                    return false;
+                }
+                resolved.pop();
+                for component in target.components().rev() {
+                    rest.push_front(component);
+                }
+            }
+        }
+        true
     }
 }
 
 /// Iterator that applies templating to files
 pub struct TemplatedFileIter<I> {
     inner: I,
-    env: Environment<'static>,
+    engine: Box<dyn Engine>,
     params: serde_json::Value,
+    /// Files whose content is copied without rendering (only the path is rendered)
+    verbatim: HashSet<PathBuf>,
+    /// Fail on binary files instead of copying them without rendering
+    strict_text: bool,
+    /// Report all errors of a failing file
+    collect_errors: bool,
+    /// Text files which are rendered as UTF-8 and encoded again
+    encodings: Encodings,
+    /// Symlinks rendered so far
+    symlinks: Symlinks,
 }
 
 impl<I> TemplatedFileIter<I> {
     pub fn with_config(inner: I, params: serde_json::Value, config: TemplateConfig) -> Self {
-        let mut env = Environment::new();
-        env.set_undefined_behavior(UndefinedBehavior::Strict);
-        env.set_debug(true);
-        env.set_keep_trailing_newline(true);
-
-        if let SyntaxMode::Backstage = config.syntax {
-            // https://github.com/backstage/backstage/blob/9e88165368eafc6744b8c41c9912260e853ec11b/plugins/scaffolder-backend/src/lib/templating/SecureTemplater.ts#L40
-            let syntax_config = SyntaxConfig::builder()
-                .variable_delimiters("${{", "}}")
-                .build()
-                .expect("valid backstage syntax config");
-            env.set_syntax(syntax_config);
+        let engine = engine(&config);
+        // checked when the manifest is parsed
+        let encodings = Encodings::new(&config.encodings).unwrap_or_default();
 
-            // Add dump filter as alias for tojson (Backstage/Nunjucks compatibility)
-            env.add_filter("dump", minijinja::filters::tojson);
+        let params = config.context(params);
 
-            // There are other filters missing. But some of these depend on the SCM integrations
-            // and hence are not easy to simulate
-            // https://github.com/backstage/backstage/blob/9e88165368eafc6744b8c41c9912260e853ec11b/plugins/scaffolder-backend/src/lib/templating/filters/createDefaultFilters.ts#L26
+        Self {
+            inner,
+            engine,
+            params,
+            verbatim: HashSet::new(),
+            strict_text: false,
+            collect_errors: false,
+            encodings,
+            symlinks: Symlinks::default(),
         }
+    }
 
-        // Wrap params under root_value key if specified
-        let params = match config.root_value {
-            Some(key) => serde_json::json!({ key: params }),
-            None => params,
-        };
+    /// Copy the content of these files without rendering it
+    pub fn with_verbatim(mut self, paths: HashSet<PathBuf>) -> Self {
+        self.verbatim = paths;
+        self
+    }
 
-        Self { inner, env, params }
+    /// Fail on binary files instead of copying them without rendering
+    pub fn with_strict_text(mut self, strict_text: bool) -> Self {
+        self.strict_text = strict_text;
+        self
+    }
+
+    /// Report all errors of a file which fails to render, e.g. every undefined value, instead of
+    /// only the first one
+    pub fn with_collect_errors(mut self, collect_errors: bool) -> Self {
+        self.collect_errors = collect_errors;
+        self
+    }
+
+    /// Set a loader which resolves templates referenced by `{% include %}` and friends
+    pub fn with_loader<F>(mut self, loader: F) -> Self
+    where
+        F: Fn(&str) -> Result<Option<String>, minijinja::Error> + Send + Sync + 'static,
+    {
+        self.engine.set_loader(Arc::new(loader));
+        self
     }
 }
 
@@ -78,62 +819,120 @@ impl<I: Iterator<Item = Result<TemplateFile>>> Iterator for TemplatedFileIter<I>
     type Item = Result<TemplateFile>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let file = match self.inner.next()? {
-            Ok(f) => f,
-            Err(e) => return Some(Err(e)),
-        };
-
-        // we are only able to run utf8 through the templating engine, but not all paths are valid utf8
-        let path = match file.path.to_str() {
-            Some(path) => path,
-            None => {
-                return Some(Err(anyhow::anyhow!(
-                    "invalid path '{}' is not UTF8",
-                    file.path.display(),
-                )));
-            }
-        };
+        loop {
+            let file = match self.inner.next()? {
+                Ok(f) => f,
+                Err(e) => return Some(Err(e)),
+            };
 
-        // Render the path
-        let rendered_path = match self
-            .env
-            .template_from_str(path)
-            .and_then(|t| t.render(&self.params))
-        {
-            Ok(p) => p,
-            Err(e) => {
-                return Some(Err(anyhow::anyhow!(
-                    "failed to render path '{}': {:#}",
-                    file.path.display(),
-                    e
-                )));
-            }
-        };
+            // we are only able to run utf8 through the templating engine, but not all paths are valid utf8
+            let path = match file.path.to_str() {
+                Some(path) => path,
+                None => {
+                    return Some(Err(anyhow::anyhow!(
+                        "invalid path '{}' is not UTF8",
+                        file.path.display(),
+                    )));
+                }
+            };
 
-        let rendered_content = match std::str::from_utf8(&file.content) {
-            Err(_) => {
-                // if content is not valid utf8 we skip rendering and return as is
-                file.content.to_vec()
-            }
-            Ok(content) => match self
-                .env
-                .template_from_str(content)
-                .and_then(|t| t.render(&self.params))
-            {
-                Ok(rendered_content) => rendered_content.into_bytes(),
+            // Render the path
+            let rendered_path = match self.engine.render(path, path, &self.params) {
+                Ok(p) => p,
                 Err(e) => {
                     return Some(Err(anyhow::anyhow!(
-                        "template execution for '{}' failed: {:#}",
+                        "failed to render path '{}': {:#}",
                         file.path.display(),
                         e
                     )));
                 }
-            },
-        };
+            };
+
+            // a path rendering to an empty name is a conditional file or directory, e.g.
+            // `{% if values.docker %}Dockerfile{% endif %}`
+            if rendered_path.split('/').any(str::is_empty) {
+                report::skip(file.path, SkipReason::EmptyPath);
+                continue;
+            }
+
+            let encoding = match file.kind {
+                FileKind::File => self.encodings.of(&file.path, &file.content),
+                _ => None,
+            };
+            let rendered_content = match std::str::from_utf8(&file.content) {
+                _ if file.kind == FileKind::Dir || self.verbatim.contains(&file.path) => {
+                    file.content.to_vec()
+                }
+                _ if let Some(encoding) = encoding => {
+                    let rendered = encoding.decode(&file.content).and_then(|content| {
+                        encoding.encode(&self.engine.render(path, &content, &self.params)?)
+                    });
+                    match rendered {
+                        Ok(rendered) => rendered,
+                        Err(e) => {
+                            return Some(Err(anyhow::anyhow!(
+                                "template execution for '{}' failed: {:#}",
+                                file.path.display(),
+                                e
+                            )));
+                        }
+                    }
+                }
+                Ok(content) if !is_binary(&file.content) => {
+                    match self.engine.render(path, content, &self.params) {
+                        Ok(rendered_content) => rendered_content.into_bytes(),
+                        Err(e) => {
+                            let errors = match self.collect_errors {
+                                true => self.engine.check(path, content, &self.params),
+                                false => Vec::new(),
+                            };
+                            if errors.len() > 1 {
+                                return Some(Err(anyhow::anyhow!(
+                                    "template execution for '{}' failed with {} errors:\n{}",
+                                    file.path.display(),
+                                    errors.len(),
+                                    errors.join("\n")
+                                )));
+                            }
+                            return Some(Err(anyhow::anyhow!(
+                                "template execution for '{}' failed: {:#}",
+                                file.path.display(),
+                                e
+                            )));
+                        }
+                    }
+                }
+                _ => {
+                    if self.strict_text {
+                        return Some(Err(anyhow::anyhow!(
+                            "'{}' is binary and can not be rendered (--strict-text)",
+                            file.path.display()
+                        )));
+                    }
+                    // binary content is copied as it is, only the path is rendered
+                    if let Err(e) = warnings::emit(
+                        WarningKind::BinaryPassthrough,
+                        format!(
+                            "'{}' is binary and is copied without rendering (declare the encoding of text files under encodings in rte.yaml)",
+                            file.path.display()
+                        ),
+                    ) {
+                        return Some(Err(e));
+                    }
+                    file.content.to_vec()
+                }
+            };
 
-        Some(Ok(TemplateFile {
-            path: rendered_path.into(),
-            content: rendered_content,
-        }))
+            let file = TemplateFile {
+                path: rendered_path.into(),
+                content: rendered_content,
+                kind: file.kind,
+                mode: file.mode,
+            };
+            if let Err(e) = self.symlinks.check(&file) {
+                return Some(Err(e));
+            }
+            return Some(Ok(file));
+        }
     }
 }
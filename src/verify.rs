@@ -0,0 +1,50 @@
+//! Verification commands declared in the manifest, run in the destination after rendering to
+//! catch templates which render fine but produce broken projects.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::cancel;
+
+/// Run each command with the shell in `dest`. All commands are run, even if one fails, and the
+/// failures are reported together.
+pub fn run(dest: &Path, commands: &[String]) -> Result<()> {
+    let mut failures = Vec::new();
+    for command in commands {
+        cancel::check()?;
+        tracing::info!("verify: {}", command);
+        let status = shell(command)
+            .current_dir(dest)
+            .status()
+            .with_context(|| format!("Failed to run '{}'", command))?;
+        if !status.success() {
+            failures.push(format!("'{}' ({})", command, status));
+        }
+    }
+
+    cancel::check()?;
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "verification of '{}' failed: {}",
+            dest.display(),
+            failures.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
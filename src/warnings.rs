@@ -0,0 +1,111 @@
+//! Warnings with a configurable severity per warning class, so teams can enforce stricter or
+//! looser policies (e.g. `-W unused-parameter=deny` in CI).
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::{report, secrets};
+
+/// Class of a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum WarningKind {
+    /// A parameter is not referenced by any template
+    UnusedParameter,
+    /// A file is binary (not valid UTF8 or with null bytes) and is copied without rendering
+    BinaryPassthrough,
+    /// An existing file in the destination is overwritten
+    OverwrittenFile,
+    /// An overlay replaces a file of an earlier source
+    OverlayOverride,
+    /// A file could not be merged cleanly on update
+    MergeConflict,
+    /// A rendered file or the whole output exceeds the configured size limit
+    SizeBudget,
+    /// The template is deprecated
+    Deprecated,
+    /// The source could not be opened and a fallback source is used
+    SourceFallback,
+}
+
+/// What to do when a warning occurs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Severity {
+    /// Ignore the warning
+    Allow,
+    /// Print the warning
+    Warn,
+    /// Fail with an error
+    Deny,
+}
+
+/// Severity for a warning class or for all warnings (`all=deny`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WarningLevel {
+    pub kind: Option<WarningKind>,
+    pub severity: Severity,
+}
+
+/// Parse `KIND=SEVERITY`, e.g. `unused-parameter=deny` or `all=allow`
+pub fn parse_warning_level(s: &str) -> Result<WarningLevel, String> {
+    let (kind, severity) = s
+        .split_once('=')
+        .ok_or("expected format: KIND=allow|warn|deny")?;
+    let kind = match kind {
+        "all" => None,
+        kind => Some(WarningKind::from_str(kind, false)?),
+    };
+    Ok(WarningLevel {
+        kind,
+        severity: Severity::from_str(severity, false)?,
+    })
+}
+
+static LEVELS: OnceLock<HashMap<Option<WarningKind>, Severity>> = OnceLock::new();
+
+/// Set the severities for this run. Later levels override earlier ones. Without configuration
+/// every warning is printed.
+pub fn configure(levels: &[WarningLevel]) {
+    let mut map = HashMap::new();
+    for level in levels {
+        if level.kind.is_none() {
+            // all=... resets previously configured classes
+            map.clear();
+        }
+        map.insert(level.kind, level.severity);
+    }
+    let _ = LEVELS.set(map);
+}
+
+fn severity(kind: WarningKind) -> Severity {
+    let Some(levels) = LEVELS.get() else {
+        return Severity::Warn;
+    };
+    levels
+        .get(&Some(kind))
+        .or_else(|| levels.get(&None))
+        .copied()
+        .unwrap_or(Severity::Warn)
+}
+
+/// Emit a warning. Returns an error if warnings of this class are denied.
+pub fn emit(kind: WarningKind, message: impl Display) -> Result<()> {
+    let name = kind
+        .to_possible_value()
+        .map(|v| v.get_name().to_owned())
+        .unwrap_or_default();
+    match severity(kind) {
+        Severity::Allow => Ok(()),
+        Severity::Warn => {
+            let message = secrets::mask(&message.to_string());
+            // like all output on stderr, --quiet hides warnings, denied ones still fail
+            tracing::warn!("[{}] {}", name, message);
+            report::warning(&name, message);
+            Ok(())
+        }
+        Severity::Deny => anyhow::bail!("{} (denied by {}=deny)", message, name),
+    }
+}
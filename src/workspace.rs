@@ -0,0 +1,234 @@
+//! Rendering into a new member of a Cargo workspace (--workspace): the member is added to the
+//! `members` of the workspace's Cargo.toml and, if the workspace README has an index between
+//! `<!-- rte:members -->` and `<!-- /rte:members -->`, to that index. Both files are edited in
+//! place, everything else in them stays as it is.
+
+use std::fs;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::glob;
+
+/// Markers of the member index in the README of the workspace
+const INDEX_START: &str = "<!-- rte:members -->";
+const INDEX_END: &str = "<!-- /rte:members -->";
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    workspace: Option<Workspace>,
+    package: Option<Package>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Workspace {
+    members: Option<Spanned<Vec<String>>>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: Option<String>,
+    description: Option<String>,
+}
+
+/// Find the Cargo workspace a directory (which might not exist yet) belongs to: the closest
+/// parent with a Cargo.toml which has a `[workspace]` table.
+pub fn find_root(dir: &Path) -> Result<PathBuf> {
+    let dir =
+        std::path::absolute(dir).with_context(|| format!("Failed to resolve {}", dir.display()))?;
+    for parent in dir.ancestors().skip(1) {
+        let manifest = parent.join("Cargo.toml");
+        if !manifest.is_file() {
+            continue;
+        }
+        if read_manifest(&manifest)?.1.workspace.is_some() {
+            return Ok(parent.to_path_buf());
+        }
+    }
+    anyhow::bail!(
+        "--workspace: no Cargo workspace (Cargo.toml with [workspace]) above {}",
+        dir.display()
+    )
+}
+
+fn read_manifest(path: &Path) -> Result<(String, Manifest)> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let manifest =
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok((content, manifest))
+}
+
+/// Add a rendered member to the workspace at `root`. Nothing changes if a pattern of `members`
+/// matches it already.
+pub fn add_member(root: &Path, member_dir: &Path) -> Result<()> {
+    let member = member_path(root, member_dir)?;
+    let manifest_path = root.join("Cargo.toml");
+    let (content, manifest) = read_manifest(&manifest_path)?;
+    let workspace = manifest
+        .workspace
+        .with_context(|| format!("{} has no [workspace]", manifest_path.display()))?;
+    if glob::gitignore_set(&workspace.exclude)?.is_match(&member) {
+        anyhow::bail!(
+            "--workspace: '{}' is excluded from the workspace in {}",
+            member,
+            manifest_path.display()
+        );
+    }
+    let updated = match &workspace.members {
+        Some(members) if is_member(members.get_ref(), &member)? => None,
+        Some(members) => {
+            let mut list = members.get_ref().clone();
+            list.push(member.clone());
+            Some(replace(
+                &content,
+                members.span(),
+                &format_array(&content, members.span(), &list),
+            ))
+        }
+        None => {
+            // right after the [workspace] header
+            let header = content
+                .match_indices('\n')
+                .map(|(newline, _)| newline + 1)
+                .chain([0])
+                .find(|&line| content[line..].trim_start().starts_with("[workspace]"))
+                .with_context(|| {
+                    format!("{}: [workspace] header not found", manifest_path.display())
+                })?;
+            let header_end = content[header..]
+                .find('\n')
+                .map_or(content.len(), |newline| header + newline + 1);
+            let line = format!(
+                "members = {}\n",
+                format_array(&content, 0..0, std::slice::from_ref(&member))
+            );
+            Some(replace(&content, header_end..header_end, &line))
+        }
+    };
+    if let Some(updated) = updated {
+        fs::write(&manifest_path, updated)
+            .with_context(|| format!("Failed to write {}", manifest_path.display()))?;
+        tracing::info!("added {} to the workspace members", member);
+    }
+
+    add_to_index(root, member_dir, &member)
+}
+
+/// Path of the member relative to the workspace root, with `/` as separator
+fn member_path(root: &Path, member_dir: &Path) -> Result<String> {
+    let member_dir = std::path::absolute(member_dir)
+        .with_context(|| format!("Failed to resolve {}", member_dir.display()))?;
+    let relative = member_dir.strip_prefix(root).with_context(|| {
+        format!(
+            "{} is not below the workspace {}",
+            member_dir.display(),
+            root.display()
+        )
+    })?;
+    let parts: Vec<&str> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    Ok(parts.join("/"))
+}
+
+/// Whether a member matches one of the paths or glob patterns of `members`
+fn is_member(members: &[String], member: &str) -> Result<bool> {
+    for pattern in members {
+        let pattern = pattern.trim_end_matches('/');
+        if pattern == member
+            || globset::Glob::new(pattern)
+                .with_context(|| format!("invalid workspace member pattern '{}'", pattern))?
+                .compile_matcher()
+                .is_match(member)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Format a TOML array of strings like the one it replaces: on one line, or one entry per line
+fn format_array(content: &str, span: Range<usize>, items: &[String]) -> String {
+    let quoted: Vec<String> = items
+        .iter()
+        .map(|item| toml::Value::String(item.clone()).to_string())
+        .collect();
+    if content[span].contains('\n') {
+        let mut array = String::from("[\n");
+        for item in quoted {
+            array.push_str(&format!("    {},\n", item));
+        }
+        array.push(']');
+        array
+    } else {
+        format!("[{}]", quoted.join(", "))
+    }
+}
+
+fn replace(content: &str, span: Range<usize>, with: &str) -> String {
+    let mut updated = String::with_capacity(content.len() + with.len());
+    updated.push_str(&content[..span.start]);
+    updated.push_str(with);
+    updated.push_str(&content[span.end..]);
+    updated
+}
+
+/// Add a line for the member to the index in the README of the workspace, if it has one
+fn add_to_index(root: &Path, member_dir: &Path, member: &str) -> Result<()> {
+    let readme = root.join("README.md");
+    let Ok(content) = fs::read_to_string(&readme) else {
+        return Ok(());
+    };
+    let (Some(start), Some(end)) = (content.find(INDEX_START), content.find(INDEX_END)) else {
+        return Ok(());
+    };
+    if end < start {
+        anyhow::bail!(
+            "{}: {} comes before {}",
+            readme.display(),
+            INDEX_END,
+            INDEX_START
+        );
+    }
+    let link = format!("]({})", member);
+    if content[start..end].contains(&link) {
+        return Ok(());
+    }
+
+    let package = match read_manifest(&member_dir.join("Cargo.toml")) {
+        Ok((_, manifest)) => manifest.package,
+        Err(_) => None,
+    };
+    let name = package
+        .as_ref()
+        .and_then(|package| package.name.clone())
+        .unwrap_or_else(|| member.rsplit('/').next().unwrap_or(member).to_owned());
+    let mut line = format!("- [{}]({})", name, member);
+    if let Some(description) = package.and_then(|package| package.description) {
+        line.push_str(&format!(" - {}", description));
+    }
+    line.push('\n');
+
+    // before the end marker, on a line of its own
+    let insert_at = content[..end]
+        .rfind('\n')
+        .map_or(end, |newline| newline + 1);
+    let insert_at = insert_at.max(start + INDEX_START.len());
+    let line = if content[..insert_at].ends_with('\n') {
+        line
+    } else {
+        format!("\n{}", line)
+    };
+    fs::write(&readme, replace(&content, insert_at..insert_at, &line))
+        .with_context(|| format!("Failed to write {}", readme.display()))
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::fsync;
+use crate::report::{self, SkipReason};
+use crate::template::{FileKind, TemplateFile};
+
+/// File type bits of unix modes, zip archives store symlinks as files with the target as content
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+pub fn is_zip(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".zip")
+}
+
+/// Iterator over the files of a zip archive. Zip archives have their index at the end, hence the
+/// archive is read into memory first.
+pub struct ZipFileIter {
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+    index: usize,
+    /// Directories which contain entries, the other directories are empty
+    parents: HashSet<PathBuf>,
+}
+
+impl ZipFileIter {
+    pub fn new(mut reader: impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .context("Failed to read zip archive")?;
+        let archive = ZipArchive::new(Cursor::new(data)).context("Failed to open zip archive")?;
+        let parents = archive
+            .file_names()
+            .flat_map(|name| Path::new(name).ancestors().skip(1).map(Path::to_path_buf))
+            .collect();
+        Ok(Self {
+            archive,
+            index: 0,
+            parents,
+        })
+    }
+}
+
+impl Iterator for ZipFileIter {
+    type Item = Result<TemplateFile>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.archive.len() {
+            let index = self.index;
+            self.index += 1;
+
+            let mut entry = match self.archive.by_index(index) {
+                Ok(e) => e,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            let Some(path) = entry.enclosed_name() else {
+                return Some(Err(anyhow::anyhow!(
+                    "invalid path '{}' in zip archive",
+                    entry.name()
+                )));
+            };
+
+            // directories are created for their files, only empty ones are entries of their own
+            if entry.is_dir() {
+                if self.parents.contains(&path) {
+                    report::skip(entry.name(), SkipReason::Directory);
+                    continue;
+                }
+                return Some(Ok(TemplateFile {
+                    path,
+                    content: Vec::new(),
+                    kind: FileKind::Dir,
+                    mode: None,
+                }));
+            }
+
+            let mut content = Vec::new();
+            if let Err(e) = entry.read_to_end(&mut content) {
+                return Some(Err(e.into()));
+            }
+            let kind = match entry.unix_mode() {
+                Some(mode) if mode & S_IFMT == S_IFLNK => FileKind::Symlink,
+                _ => FileKind::File,
+            };
+
+            return Some(Ok(TemplateFile {
+                path,
+                content,
+                kind,
+                mode: entry.unix_mode().map(|mode| mode & 0o777),
+            }));
+        }
+        None
+    }
+}
+
+/// Write files into a zip archive. With `sync` the archive is written to a temporary sibling file,
+/// synced to disk and then moved into place.
+pub fn write_to_zip(
+    dest: &Path,
+    files: impl Iterator<Item = Result<TemplateFile>>,
+    sync: bool,
+) -> Result<()> {
+    let target = fsync::archive_target(dest, sync)?;
+    let file = File::create(&target)
+        .with_context(|| format!("Failed to create archive: {}", target.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+
+    for file in files {
+        let file = file?;
+        // zip archives always use forward slashes
+        let name = file.path.to_string_lossy().replace('\\', "/");
+        if file.kind == FileKind::Dir {
+            zip.add_directory(name, options.unix_permissions(0o755))
+                .with_context(|| {
+                    format!(
+                        "Failed to add directory to archive: {}",
+                        file.path.display()
+                    )
+                })?;
+            continue;
+        }
+        if file.kind == FileKind::Symlink {
+            let target =
+                std::str::from_utf8(&file.content).context("symlink target is not UTF8")?;
+            zip.add_symlink(name, target, options.unix_permissions(0o777))
+                .with_context(|| {
+                    format!("Failed to add symlink to archive: {}", file.path.display())
+                })?;
+            continue;
+        }
+        let options = match file.mode {
+            Some(mode) => options.unix_permissions(mode),
+            None => options,
+        };
+        zip.start_file(name, options)
+            .and_then(|()| zip.write_all(&file.content).map_err(Into::into))
+            .with_context(|| format!("Failed to add file to archive: {}", file.path.display()))?;
+    }
+
+    let file = zip.finish().context("Failed to finalize zip archive")?;
+    if sync {
+        file.sync_all()
+            .with_context(|| format!("failed to sync file: {}", target.display()))?;
+        fsync::persist(&target, dest)?;
+    }
+    Ok(())
+}